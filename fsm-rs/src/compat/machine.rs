@@ -0,0 +1,164 @@
+use heck::SnakeCase;
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
+use syn::{
+    parse::{Error, Parse, ParseStream, Result},
+    Fields, Ident, ItemEnum,
+};
+
+/// The states of a `machine!`: one struct per variant, wrapped in an enum
+/// with an extra `Error` variant that unexpected messages lead to.
+#[derive(Debug)]
+pub(crate) struct Machine {
+    item: ItemEnum,
+}
+
+impl Parse for Machine {
+    /// example machine:
+    ///
+    /// ```text
+    /// #[derive(Clone, Debug, PartialEq)]
+    /// enum Traffic {
+    ///     Green { count: u8 },
+    ///     Orange,
+    ///     Red,
+    /// }
+    /// ```
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        let item: ItemEnum = input.parse()?;
+
+        if !item.generics.params.is_empty() {
+            return Err(Error::new(
+                item.ident.span(),
+                "a machine can't have generic parameters",
+            ));
+        }
+
+        for variant in &item.variants {
+            if variant.ident == "Error" {
+                return Err(Error::new(
+                    variant.ident.span(),
+                    "the Error state is generated for every machine",
+                ));
+            }
+            if let Fields::Unnamed(_) = variant.fields {
+                return Err(Error::new(
+                    variant.ident.span(),
+                    format!(
+                        "expected {} or {} {{ ... }}, a state's fields need names",
+                        variant.ident, variant.ident
+                    ),
+                ));
+            }
+        }
+
+        Ok(Machine { item })
+    }
+}
+
+impl ToTokens for Machine {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let attrs = &self.item.attrs;
+        let machine_name = &self.item.ident;
+
+        let mut structs = Vec::new();
+        let mut constructors = Vec::new();
+        for variant in &self.item.variants {
+            let state_name = &variant.ident;
+            let fields: Vec<_> = variant.fields.iter().collect();
+            let field_names: Vec<_> = fields.iter().map(|v| &v.ident).collect();
+            let field_types = fields.iter().map(|v| &v.ty);
+
+            structs.push(quote! {
+                #( #attrs )*
+                pub struct #state_name {
+                    #( #fields ),*
+                }
+            });
+
+            let constructor =
+                Ident::new(&state_name.to_string().to_snake_case(), state_name.span());
+            constructors.push(quote! {
+                pub fn #constructor(#( #field_names: #field_types ),*) -> #machine_name {
+                    #machine_name::#state_name(#state_name { #( #field_names ),* })
+                }
+            });
+        }
+
+        let state_names = self.item.variants.iter().map(|v| &v.ident);
+
+        tokens.extend(quote! {
+            #( #structs )*
+
+            #( #attrs )*
+            pub enum #machine_name {
+                Error,
+                #( #state_names(#state_names), )*
+            }
+
+            impl #machine_name {
+                #( #constructors )*
+
+                pub fn error() -> #machine_name {
+                    #machine_name::Error
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_machine_parse_and_to_tokens() {
+        let machine: Machine = syn::parse2(quote! {
+            #[derive(Clone)]
+            enum Traffic {
+                Green { pub count: u8 },
+                Orange,
+            }
+        })
+        .unwrap();
+
+        let left = quote! {
+            #[derive(Clone)]
+            pub struct Green {
+                pub count: u8
+            }
+            #[derive(Clone)]
+            pub struct Orange {}
+            #[derive(Clone)]
+            pub enum Traffic {
+                Error,
+                Green(Green),
+                Orange(Orange),
+            }
+            impl Traffic {
+                pub fn green(count: u8) -> Traffic {
+                    Traffic::Green(Green { count })
+                }
+                pub fn orange() -> Traffic {
+                    Traffic::Orange(Orange {})
+                }
+                pub fn error() -> Traffic {
+                    Traffic::Error
+                }
+            }
+        };
+
+        let mut right = TokenStream::new();
+        machine.to_tokens(&mut right);
+
+        assert_eq!(format!("{}", left), format!("{}", right));
+
+        let result: Result<Machine> = syn::parse2(quote! {
+            enum Traffic {
+                Green(u8),
+            }
+        });
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,290 @@
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
+use syn::{
+    bracketed, parenthesized,
+    parse::{Error, Parse, ParseStream, Result},
+    punctuated::Punctuated,
+    Expr, FnArg, Ident, Pat, ReturnType, Signature, Token, Type,
+};
+
+#[derive(Debug)]
+pub(crate) enum Method {
+    /// `get count: u8`, a shared reference to a field.
+    Get { field: Ident, ty: Type },
+    /// `set count: u8`, a mutable reference to a field.
+    Set { field: Ident, ty: Type },
+    /// `default(false) fn working(&self) -> bool`, forwarded to the state.
+    Fn {
+        default: Option<Expr>,
+        signature: Box<Signature>,
+    },
+}
+
+/// One `States => method` line of `methods!`.
+#[derive(Debug)]
+pub(crate) struct Line {
+    pub state_names: Vec<Ident>,
+    pub method: Method,
+}
+
+impl Parse for Line {
+    /// example lines:
+    ///
+    /// ```text
+    /// Green => get count: u8
+    /// Green => set count: u8
+    /// Green, Orange => fn can_pass(&self) -> bool
+    /// Green, Orange => default(false) fn working(&self) -> bool
+    /// ```
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        // Green, Orange => ...
+        // _____________
+        let mut state_names = vec![input.parse()?];
+        while input.peek(Token![,]) {
+            let _: Token![,] = input.parse()?;
+            state_names.push(input.parse()?);
+        }
+
+        // Green, Orange => ...
+        //               __
+        let _: Token![=>] = input.parse()?;
+
+        // Green, Orange => default(false) fn working(&self) -> bool
+        //                  ______________
+        let default = if input.peek(Ident) && input.peek2(syn::token::Paren) {
+            let magic: Ident = input.parse()?;
+            if magic != "default" {
+                return Err(Error::new(magic.span(), "expected default(...) fn ..."));
+            }
+            let content;
+            let _ = parenthesized!(content in input);
+            Some(content.parse()?)
+        } else {
+            None
+        };
+
+        let method = if default.is_some() || input.peek(Token![fn]) {
+            let signature: Signature = input.parse()?;
+            if signature.receiver().is_none() {
+                return Err(Error::new(
+                    signature.ident.span(),
+                    format!("{} needs a self receiver", signature.ident),
+                ));
+            }
+            for arg in &signature.inputs {
+                if let FnArg::Typed(v) = arg {
+                    if let Pat::Ident(_) = *v.pat {
+                        continue;
+                    }
+                    return Err(Error::new_spanned(&v.pat, "expected a named argument"));
+                }
+            }
+            Method::Fn {
+                default,
+                signature: Box::new(signature),
+            }
+        } else {
+            // Green => get count: u8
+            //          ___ _____  __
+            let accessor: Ident = input.parse()?;
+            let field: Ident = input.parse()?;
+            let _: Token![:] = input.parse()?;
+            let ty: Type = input.parse()?;
+
+            if accessor == "get" {
+                Method::Get { field, ty }
+            } else if accessor == "set" {
+                Method::Set { field, ty }
+            } else {
+                return Err(Error::new(
+                    accessor.span(),
+                    "expected get, set, fn or default(...) fn",
+                ));
+            }
+        };
+
+        Ok(Line {
+            state_names,
+            method,
+        })
+    }
+}
+
+impl ToTokens for Line {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let state_names = &self.state_names;
+
+        tokens.extend(match &self.method {
+            Method::Get { field, ty } => quote! {
+                pub fn #field(&self) -> Option<&#ty> {
+                    match self {
+                        #( Self::#state_names(state) => {
+                            let #field: &#ty = &state.#field;
+                            Some(#field)
+                        } )*
+                        _ => None,
+                    }
+                }
+            },
+            Method::Set { field, ty } => {
+                let method_name = Ident::new(&format!("{}_mut", field), field.span());
+                quote! {
+                    pub fn #method_name(&mut self) -> Option<&mut #ty> {
+                        match self {
+                            #( Self::#state_names(state) => {
+                                let #field: &mut #ty = &mut state.#field;
+                                Some(#field)
+                            } )*
+                            _ => None,
+                        }
+                    }
+                }
+            }
+            Method::Fn { default, signature } => {
+                let method_name = &signature.ident;
+                let args: Vec<_> = signature
+                    .inputs
+                    .iter()
+                    .filter_map(|v| match v {
+                        FnArg::Typed(v) => Some(&v.pat),
+                        FnArg::Receiver(_) => None,
+                    })
+                    .collect();
+                let output = match &signature.output {
+                    ReturnType::Default => quote!(()),
+                    ReturnType::Type(_, v) => quote!(#v),
+                };
+
+                // Without a default the states that weren't listed answer
+                // `None`.
+                let call = quote!(state.#method_name(#( #args ),*));
+                let (output, value, default) = match default {
+                    Some(default) => (output, call, quote!(#default)),
+                    None => (quote!(Option<#output>), quote!(Some(#call)), quote!(None)),
+                };
+
+                let mut signature = signature.clone();
+                signature.output = syn::parse_quote!(-> #output);
+
+                quote! {
+                    pub #signature {
+                        match self {
+                            #( Self::#state_names(state) => #value, )*
+                            _ => #default,
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// The methods a `machine!` forwards to some of its states.
+#[derive(Debug)]
+pub(crate) struct Methods {
+    pub machine_name: Ident,
+    pub lines: Vec<Line>,
+}
+
+impl Parse for Methods {
+    /// example methods:
+    ///
+    /// ```text
+    /// Traffic, [
+    ///     Green => get count: u8,
+    ///     Green => set count: u8,
+    ///     Green, Orange, Red => fn can_pass(&self) -> bool
+    /// ]
+    /// ```
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        // Traffic, [ ... ]
+        // _______
+        let machine_name: Ident = input.parse()?;
+        let _: Token![,] = input.parse()?;
+
+        // Traffic, [ ... ]
+        //          _______
+        let content;
+        let _ = bracketed!(content in input);
+
+        let lines: Punctuated<Line, Token![,]> = content.parse_terminated(Line::parse)?;
+
+        Ok(Methods {
+            machine_name,
+            lines: lines.into_iter().collect(),
+        })
+    }
+}
+
+impl ToTokens for Methods {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let machine_name = &self.machine_name;
+        let lines = &self.lines;
+
+        tokens.extend(quote! {
+            impl #machine_name {
+                #( #lines )*
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_methods_parse_and_to_tokens() {
+        let methods: Methods = syn::parse2(quote! {
+            Traffic, [
+                Green => get count: u8,
+                Green => set count: u8,
+                Green, Orange => fn can_pass(&self) -> bool,
+                Green, Orange => default(false) fn working(&self, strict: bool) -> bool
+            ]
+        })
+        .unwrap();
+
+        let left = quote! {
+            impl Traffic {
+                pub fn count(&self) -> Option<&u8> {
+                    match self {
+                        Self::Green(state) => {
+                            let count: &u8 = &state.count;
+                            Some(count)
+                        }
+                        _ => None,
+                    }
+                }
+                pub fn count_mut(&mut self) -> Option<&mut u8> {
+                    match self {
+                        Self::Green(state) => {
+                            let count: &mut u8 = &mut state.count;
+                            Some(count)
+                        }
+                        _ => None,
+                    }
+                }
+                pub fn can_pass(&self) -> Option<bool> {
+                    match self {
+                        Self::Green(state) => Some(state.can_pass()),
+                        Self::Orange(state) => Some(state.can_pass()),
+                        _ => None,
+                    }
+                }
+                pub fn working(&self, strict: bool) -> bool {
+                    match self {
+                        Self::Green(state) => state.working(strict),
+                        Self::Orange(state) => state.working(strict),
+                        _ => false,
+                    }
+                }
+            }
+        };
+
+        let mut right = TokenStream::new();
+        methods.to_tokens(&mut right);
+
+        assert_eq!(format!("{}", left), format!("{}", right));
+    }
+}
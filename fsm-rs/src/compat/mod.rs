@@ -0,0 +1,3 @@
+pub mod machine;
+pub mod methods;
+pub mod transitions;
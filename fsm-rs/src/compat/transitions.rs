@@ -0,0 +1,247 @@
+use heck::SnakeCase;
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
+use syn::{
+    bracketed, parenthesized,
+    parse::{Error, Parse, ParseStream, Result},
+    punctuated::Punctuated,
+    token, GenericArgument, Ident, PathArguments, Token, Type,
+};
+
+/// One `(State, Message) => Target` row of `transitions!`.
+#[derive(Debug)]
+pub(crate) struct Row {
+    pub state_name: Ident,
+    pub message: Type,
+    pub targets: Vec<Ident>,
+}
+
+impl Parse for Row {
+    /// example row:
+    ///
+    /// ```text
+    /// (Green, Advance) => Orange
+    /// ```
+    ///
+    /// example row with more than one possible target:
+    ///
+    /// ```text
+    /// (Green, PassCar) => [Green, Orange]
+    /// ```
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        // (Green, Advance) => Orange
+        // ________________
+        let content;
+        let _ = parenthesized!(content in input);
+        let state_name: Ident = content.parse()?;
+        let _: Token![,] = content.parse()?;
+        let message: Type = content.parse()?;
+
+        // (Green, Advance) => Orange
+        //                  __
+        let _: Token![=>] = input.parse()?;
+
+        // (Green, PassCar) => [Green, Orange]
+        //                     _______________
+        let targets = if input.peek(token::Bracket) {
+            let content;
+            let _ = bracketed!(content in input);
+            let targets: Punctuated<Ident, Token![,]> = content.parse_terminated(Ident::parse)?;
+            if targets.is_empty() {
+                return Err(Error::new(
+                    state_name.span(),
+                    format!("{} needs at least one target", state_name),
+                ));
+            }
+            targets.into_iter().collect()
+        } else {
+            vec![input.parse()?]
+        };
+
+        Ok(Row {
+            state_name,
+            message,
+            targets,
+        })
+    }
+}
+
+impl Row {
+    /// The `on_<message>` method handling this row, named after the last
+    /// segment of the message type.
+    fn method_name(&self) -> Result<Ident> {
+        match &self.message {
+            Type::Path(v) if v.qself.is_none() => {
+                let segment = v.path.segments.last().unwrap();
+                Ok(Ident::new(
+                    &format!("on_{}", segment.ident.to_string().to_snake_case()),
+                    segment.ident.span(),
+                ))
+            }
+            _ => Err(Error::new_spanned(
+                &self.message,
+                "expected a message type like Advance or PassCar<'a, T>",
+            )),
+        }
+    }
+
+    /// The lifetimes and type parameters the message type is written with,
+    /// e.g. `<'a, T>` for `PassCar<'a, T>`.
+    fn generics(&self) -> Vec<TokenStream> {
+        let segment = match &self.message {
+            Type::Path(v) => v.path.segments.last().unwrap(),
+            _ => return Vec::new(),
+        };
+
+        let arguments = match &segment.arguments {
+            PathArguments::AngleBracketed(v) => &v.args,
+            _ => return Vec::new(),
+        };
+
+        arguments
+            .iter()
+            .filter_map(|v| match v {
+                GenericArgument::Lifetime(lifetime) => Some(quote!(#lifetime)),
+                GenericArgument::Type(Type::Path(ty))
+                    if ty.qself.is_none() && ty.path.get_ident().is_some() =>
+                {
+                    Some(quote!(#ty))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// The `on_<message>` methods of a `machine!`. A state with one target
+/// returns that state's struct, a state with more returns the machine.
+#[derive(Debug)]
+pub(crate) struct Transitions {
+    pub machine_name: Ident,
+    pub rows: Vec<Row>,
+}
+
+impl Parse for Transitions {
+    /// example transitions:
+    ///
+    /// ```text
+    /// Traffic, [
+    ///     (Green, Advance) => Orange,
+    ///     (Orange, Advance) => Red,
+    ///     (Green, PassCar) => [Green, Orange]
+    /// ]
+    /// ```
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        // Traffic, [ ... ]
+        // _______
+        let machine_name: Ident = input.parse()?;
+        let _: Token![,] = input.parse()?;
+
+        // Traffic, [ ... ]
+        //          _______
+        let content;
+        let _ = bracketed!(content in input);
+        let rows: Punctuated<Row, Token![,]> = content.parse_terminated(Row::parse)?;
+
+        for row in &rows {
+            let _ = row.method_name()?;
+        }
+
+        Ok(Transitions {
+            machine_name,
+            rows: rows.into_iter().collect(),
+        })
+    }
+}
+
+impl ToTokens for Transitions {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let machine_name = &self.machine_name;
+
+        // Group the rows by message, keeping the order they were written in.
+        let mut messages: Vec<(&Type, Vec<&Row>)> = Vec::new();
+        for row in &self.rows {
+            match messages.iter_mut().find(|(v, _)| *v == &row.message) {
+                Some((_, rows)) => rows.push(row),
+                None => messages.push((&row.message, vec![row])),
+            }
+        }
+
+        let methods = messages.iter().map(|(message, rows)| {
+            let method_name = rows[0].method_name().unwrap();
+            let generics = rows[0].generics();
+            let generics = if generics.is_empty() {
+                quote!()
+            } else {
+                quote!(<#( #generics ),*>)
+            };
+
+            let arms = rows.iter().map(|row| {
+                let state_name = &row.state_name;
+                match row.targets.as_slice() {
+                    [target] => quote! {
+                        #machine_name::#state_name(state) => #machine_name::#target(state.#method_name(input)),
+                    },
+                    _ => quote! {
+                        #machine_name::#state_name(state) => state.#method_name(input),
+                    },
+                }
+            });
+
+            quote! {
+                pub fn #method_name #generics(self, input: #message) -> #machine_name {
+                    match self {
+                        #( #arms )*
+                        _ => #machine_name::Error,
+                    }
+                }
+            }
+        });
+
+        tokens.extend(quote! {
+            impl #machine_name {
+                #( #methods )*
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transitions_parse_and_to_tokens() {
+        let transitions: Transitions = syn::parse2(quote! {
+            Traffic, [
+                (Green, prefix::Advance) => Orange,
+                (Orange, prefix::Advance) => Red,
+                (Green, PassCar<'a, T>) => [Green, Orange]
+            ]
+        })
+        .unwrap();
+
+        let left = quote! {
+            impl Traffic {
+                pub fn on_advance(self, input: prefix::Advance) -> Traffic {
+                    match self {
+                        Traffic::Green(state) => Traffic::Orange(state.on_advance(input)),
+                        Traffic::Orange(state) => Traffic::Red(state.on_advance(input)),
+                        _ => Traffic::Error,
+                    }
+                }
+                pub fn on_pass_car<'a, T>(self, input: PassCar<'a, T>) -> Traffic {
+                    match self {
+                        Traffic::Green(state) => state.on_pass_car(input),
+                        _ => Traffic::Error,
+                    }
+                }
+            }
+        };
+
+        let mut right = TokenStream::new();
+        transitions.to_tokens(&mut right);
+
+        assert_eq!(format!("{}", left), format!("{}", right));
+    }
+}
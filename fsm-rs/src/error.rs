@@ -1,16 +1,43 @@
 use core::fmt::{Debug, Display, Formatter, Result};
+use proc_macro2::{Span, TokenStream};
+use quote::quote_spanned;
 
+/// A problem with a machine definition, pointing at the token it is about.
 #[derive(Clone)]
 pub struct Error {
+    span: Span,
     message: String,
 }
 
 impl Error {
-    pub fn new<T: Display>(message: T) -> Self {
+    pub fn new<T: Display>(span: Span, message: T) -> Self {
         Error {
+            span,
             message: message.to_string(),
         }
     }
+
+    /// Report the problem as a warning instead of a compile error.
+    ///
+    /// Procedural macros can't emit warnings on stable, so this uses a
+    /// deprecated constant at the offending span and lets rustc's
+    /// `deprecated` lint carry the message.
+    pub fn to_warning_tokens(&self) -> TokenStream {
+        let message = &self.message;
+        quote_spanned! {self.span=>
+            const _: () = {
+                #[deprecated(note = #message)]
+                const FSM_WARNING: () = ();
+                FSM_WARNING
+            };
+        }
+    }
+}
+
+impl From<Error> for syn::Error {
+    fn from(error: Error) -> Self {
+        syn::Error::new(error.span, error.message)
+    }
 }
 
 impl Debug for Error {
@@ -0,0 +1,160 @@
+use syn::{
+    parse::{Error, Parse, ParseStream, Result},
+    Attribute, Lit, Meta, NestedMeta, Token,
+};
+
+use crate::fsm::backend::Backend;
+
+/// The `#[fsm(...)]` options a definition starts with, and the other
+/// attributes there, which go on `Machine`.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Attributes {
+    pub backend: Backend,
+    /// Whether to generate the `#[cfg(test)]` module `harness`, see
+    /// `harness::harness`.
+    pub harness: bool,
+    /// `#[fsm(queue = 8)]`, the capacity of the queue of `Machine::post`
+    /// and `Machine::run`, which are only generated with one.
+    pub queue: Option<usize>,
+    /// Whether to generate `Machine::snapshot`, `Machine::restore` and the
+    /// `Codec` of snapshots and events.
+    pub snapshot: bool,
+    /// Every attribute but `#[fsm(...)]`, doc comments included.
+    pub attrs: Vec<Attribute>,
+}
+
+impl Parse for Attributes {
+    /// example attributes:
+    ///
+    /// ```text
+    /// /// Fire control of the trigger.
+    /// #[fsm(backend = "table")]
+    /// #[fsm(harness, queue = 8, snapshot)]
+    /// ```
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        let mut attributes = Attributes::default();
+
+        for attr in input.call(Attribute::parse_outer)? {
+            let expected = || {
+                Error::new_spanned(
+                    &attr,
+                    r#"expected #[fsm(backend = "table")], #[fsm(harness)], #[fsm(queue = 8)] or #[fsm(snapshot)]"#,
+                )
+            };
+
+            // #[fsm(backend = "table")]
+            //   ___
+            if !attr.path.is_ident("fsm") {
+                attributes.attrs.push(attr);
+                continue;
+            }
+
+            // #[fsm(backend = "table", harness)]
+            //       __________________________
+            let list = match attr.parse_meta()? {
+                Meta::List(list) => list,
+                _ => return Err(expected()),
+            };
+            for nested in list.nested {
+                match nested {
+                    NestedMeta::Meta(Meta::NameValue(v)) if v.path.is_ident("backend") => {
+                        attributes.backend = Backend::from_lit(v.lit)?;
+                    }
+                    NestedMeta::Meta(Meta::Path(v)) if v.is_ident("harness") => {
+                        attributes.harness = true;
+                    }
+                    NestedMeta::Meta(Meta::Path(v)) if v.is_ident("snapshot") => {
+                        attributes.snapshot = true;
+                    }
+                    NestedMeta::Meta(Meta::NameValue(v)) if v.path.is_ident("queue") => {
+                        let capacity = match &v.lit {
+                            Lit::Int(capacity) => capacity.base10_parse::<usize>()?,
+                            _ => 0,
+                        };
+                        if capacity == 0 {
+                            return Err(Error::new_spanned(
+                                &v.lit,
+                                "expected the capacity of the queue, e.g. queue = 8",
+                            ));
+                        }
+                        attributes.queue = Some(capacity);
+                    }
+                    _ => return Err(expected()),
+                }
+            }
+        }
+
+        Ok(attributes)
+    }
+}
+
+impl Attributes {
+    pub fn peek(input: ParseStream<'_>) -> bool {
+        input.peek(Token![#])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::quote;
+    use syn::parse2;
+
+    #[test]
+    fn test_attributes_parse() {
+        let attributes: Attributes = parse2(quote! {
+            #[fsm(backend = "table")]
+            #[fsm(harness, queue = 8, snapshot)]
+        })
+        .unwrap();
+
+        assert_eq!(
+            attributes,
+            Attributes {
+                backend: Backend::Table,
+                harness: true,
+                queue: Some(8),
+                snapshot: true,
+                attrs: Vec::new(),
+            }
+        );
+
+        let attributes: Attributes = parse2(quote! {
+            #[fsm(harness, backend = "match")]
+        })
+        .unwrap();
+
+        assert_eq!(
+            attributes,
+            Attributes {
+                backend: Backend::Match,
+                harness: true,
+                queue: None,
+                snapshot: false,
+                attrs: Vec::new(),
+            }
+        );
+
+        let result: Result<Attributes> = parse2(quote! {
+            #[fsm(queue = 0)]
+        });
+
+        assert!(result.is_err());
+
+        let result: Result<Attributes> = parse2(quote! {
+            #[fsm(backend = "jump")]
+        });
+
+        assert!(result.is_err());
+
+        let attributes: Attributes = parse2(quote! {
+            /// The trigger.
+            #[fsm(snapshot)]
+            #[derive(Debug)]
+        })
+        .unwrap();
+
+        assert!(attributes.snapshot);
+        assert_eq!(attributes.attrs.len(), 2);
+    }
+}
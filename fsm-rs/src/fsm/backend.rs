@@ -0,0 +1,52 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{
+    parse::{Error, Result},
+    Lit,
+};
+
+/// The code `Machine::event` and `Machine::tick` are generated as.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum Backend {
+    /// Nested `match`es on the event and the current state, which grow as
+    /// states × events.
+    #[default]
+    Match,
+    /// A `const` transition table and a small interpreter walking it, which
+    /// grow as states + transitions.
+    Table,
+    /// The nested `match`es of `Match` in an `async fn`, awaiting the
+    /// `entry`, `exit` and `on` callbacks, which are `async fn`s too.
+    Async,
+}
+
+impl Backend {
+    /// The backend named by `backend = "table"` or `backend = "async"`.
+    pub fn from_lit(lit: Lit) -> Result<Self> {
+        match lit {
+            Lit::Str(v) if v.value() == "match" => Ok(Backend::Match),
+            Lit::Str(v) if v.value() == "table" => Ok(Backend::Table),
+            Lit::Str(v) if v.value() == "async" => Ok(Backend::Async),
+            v => Err(Error::new_spanned(
+                v,
+                r#"expected backend = "match", backend = "table" or backend = "async""#,
+            )),
+        }
+    }
+
+    /// `async` in front of the functions running callbacks.
+    pub fn asyncness(self) -> TokenStream {
+        match self {
+            Backend::Async => quote!(async),
+            Backend::Match | Backend::Table => quote!(),
+        }
+    }
+
+    /// `.await` after every call of a function running callbacks.
+    pub fn awaited(self) -> TokenStream {
+        match self {
+            Backend::Async => quote!(.await),
+            Backend::Match | Backend::Table => quote!(),
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{
+    braced, bracketed,
+    parse::{Parse, ParseStream, Result},
+    punctuated::Punctuated,
+    Ident, Token,
+};
+
+use crate::fsm::region::Region;
+
+/// `Preloading: [Release]`, the events `Machine::run` keeps queued while in
+/// the state, or any state inside it.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Deferral {
+    pub state_name: Ident,
+    pub event_names: Vec<Ident>,
+}
+
+impl Parse for Deferral {
+    /// example deferral:
+    ///
+    /// ```text
+    /// Preloading: [Release, Half]
+    /// ```
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        // Preloading: [Release, Half]
+        // __________
+        let state_name = Ident::parse(input)?;
+
+        // Preloading: [Release, Half]
+        //           _
+        let _ = <Token![:]>::parse(input)?;
+
+        // Preloading: [Release, Half]
+        //             _______________
+        let content;
+        bracketed!(content in input);
+
+        let event_names: Punctuated<Ident, Token![,]> = content.parse_terminated(Ident::parse)?;
+        Ok(Deferral {
+            state_name,
+            event_names: event_names.into_iter().collect(),
+        })
+    }
+}
+
+/// The events states can't handle yet, which `Machine::run` replays once a
+/// transition left them.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Defer(pub Vec<Deferral>);
+
+impl Parse for Defer {
+    /// example deferrals:
+    ///
+    /// ```text
+    /// Defer {
+    ///     Preloading: [Release],
+    ///     Overcurrent: [Half, Pull],
+    /// }
+    /// ```
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        // Defer { ... }
+        // _____
+        let defer_magic = Ident::parse(input)?;
+
+        if defer_magic != "Defer" {
+            return Err(input.error("expected Defer { ... }"));
+        }
+
+        let content;
+        braced!(content in input);
+
+        let deferrals: Punctuated<Deferral, Token![,]> =
+            content.parse_terminated(Deferral::parse)?;
+        Ok(Defer(deferrals.into_iter().collect()))
+    }
+}
+
+impl Defer {
+    pub fn peek(input: ParseStream<'_>) -> bool {
+        matches!(input.fork().parse::<Ident>(), Ok(v) if v == "Defer")
+    }
+
+    /// The events a leaf defers, its own and those of its superstates,
+    /// except the ones it has a transition for, which win over deferring.
+    pub fn leaf_events<'a>(&'a self, region: &Region, leaf: &Ident) -> Vec<&'a Ident> {
+        let path = region.states.path(leaf).unwrap_or_default();
+
+        let mut event_names = Vec::new();
+        for deferral in &self.0 {
+            if !path.iter().any(|v| v.state_name == deferral.state_name) {
+                continue;
+            }
+
+            for event_name in &deferral.event_names {
+                let handled = region
+                    .transitions
+                    .events
+                    .iter()
+                    .filter(|v| v.event_name == *event_name)
+                    .any(|v| !v.leaf_targets(&region.states, leaf).is_empty());
+                if !handled && !event_names.contains(&event_name) {
+                    event_names.push(event_name);
+                }
+            }
+        }
+        event_names
+    }
+
+    /// `Machine::defers`, or `Region::defers` of a region.
+    pub fn to_defers_fn_tokens(&self, region: &Region) -> TokenStream {
+        let pairs: Vec<_> = region
+            .states
+            .leaves()
+            .into_iter()
+            .flat_map(|leaf| {
+                let state_name = &leaf.state_name;
+                self.leaf_events(region, state_name)
+                    .into_iter()
+                    .map(move |event_name| quote!((State::#state_name(_), Event::#event_name(_))))
+            })
+            .collect();
+
+        let body = if pairs.is_empty() {
+            quote! {
+                let _ = (state, event);
+                false
+            }
+        } else {
+            quote!(matches!((state, event), #( #pairs )|*))
+        };
+
+        quote! {
+            /// Whether `Machine::run` keeps `event` queued while in `state`,
+            /// until a transition left it.
+            pub fn defers(state: &State, event: &Event) -> bool {
+                #body
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse2;
+
+    #[test]
+    fn test_defer_parse_and_leaf_events() {
+        let defer: Defer = parse2(quote! {
+            Defer {
+                Armed: [Release, Half],
+                Preloading: [Pull],
+            }
+        })
+        .unwrap();
+
+        assert_eq!(defer.0.len(), 2);
+
+        let mut region: Region = parse2(quote! {
+            Trigger {
+                States {
+                    Ready = Ready,
+                    Armed = Armed {
+                        Preloading = Preloading,
+                        Single = Single,
+                    },
+                }
+
+                Transitions {
+                    Half [
+                        Ready => Preloading,
+                    ],
+                    Pull [
+                        Preloading => Single,
+                    ],
+                    Release [
+                        Single => Ready,
+                    ],
+                }
+            }
+        })
+        .unwrap();
+        region
+            .transitions
+            .expand(&region.states, &region.final_states)
+            .unwrap();
+
+        let leaf_events = |leaf: &str| {
+            defer
+                .leaf_events(&region, &Ident::new(leaf, proc_macro2::Span::call_site()))
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // Single handles Release and Preloading handles Pull itself
+        assert_eq!(leaf_events("Preloading"), vec!["Release", "Half"]);
+        assert_eq!(leaf_events("Single"), vec!["Half"]);
+        assert!(leaf_events("Ready").is_empty());
+
+        let result: Result<Defer> = parse2(quote! {
+            Defer {
+                Armed [Release],
+            }
+        });
+
+        assert!(result.is_err());
+    }
+}
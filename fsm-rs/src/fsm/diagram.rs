@@ -0,0 +1,505 @@
+use syn::Ident;
+
+use crate::fsm::{machine::Machine, region::Region, states::State, transitions::History};
+
+/// One arrow of the state diagram, `from` and `to` may be superstates.
+struct Edge<'a> {
+    from: &'a Ident,
+    to: &'a Ident,
+    label: String,
+    /// Internal transitions are drawn inside `from` instead of as an arrow.
+    internal: bool,
+    /// History targets point at the history pseudo-state inside `to`.
+    history: Option<History>,
+}
+
+/// Every transition in `Transitions` order, labelled `Event [guard]`, then
+/// every transition taken after a delay, labelled `after 200ms`.
+fn edges(region: &Region) -> Vec<Edge<'_>> {
+    let mut edges = Vec::new();
+    for transition in &region.transitions.events {
+        for (from, targets) in &transition.pairs {
+            for target in targets {
+                let mut label = match &target.guard {
+                    Some(guard) => format!("{} [{}]", transition.event_name, guard),
+                    None => transition.event_name.to_string(),
+                };
+                if target.internal {
+                    label.push_str(" / internal");
+                }
+                edges.push(Edge {
+                    from,
+                    to: &target.to,
+                    label,
+                    internal: target.internal,
+                    history: target.history,
+                });
+            }
+        }
+    }
+    for (from, timeout) in &region.transitions.timeouts {
+        edges.push(Edge {
+            from,
+            to: &timeout.target.to,
+            label: timeout.label(),
+            internal: false,
+            history: timeout.target.history,
+        });
+    }
+    edges
+}
+
+/// Graphviz can't draw an arrow to a cluster, so superstates are drawn as
+/// an arrow to their entry leaf clipped at the cluster border.
+fn dot_node<'a>(region: &'a Region, state_name: &'a Ident) -> (&'a Ident, Option<String>) {
+    let path = region.states.entry_path(state_name).unwrap_or_default();
+    match path.last() {
+        Some(leaf) if leaf.state_name != *state_name => {
+            (&leaf.state_name, Some(format!("cluster_{}", state_name)))
+        }
+        _ => (state_name, None),
+    }
+}
+
+fn dot_history_node(state_name: &Ident, history: History) -> String {
+    match history {
+        History::Shallow => format!("{}_history", state_name),
+        History::Deep => format!("{}_deep_history", state_name),
+    }
+}
+
+/// A state's name followed by its internal transitions, one per line.
+fn dot_label(state: &State, edges: &[Edge<'_>]) -> Option<String> {
+    let internal: Vec<_> = edges
+        .iter()
+        .filter(|v| v.internal && *v.from == state.state_name)
+        .map(|v| v.label.as_str())
+        .collect();
+
+    if internal.is_empty() {
+        None
+    } else {
+        Some(format!("{}\\n{}", state.state_name, internal.join("\\n")))
+    }
+}
+
+fn dot_states(
+    region: &Region,
+    states: &[State],
+    edges: &[Edge<'_>],
+    indent: &str,
+    dot: &mut String,
+) {
+    for state in states {
+        let label = dot_label(state, edges);
+
+        if state.is_superstate() {
+            let label = label.unwrap_or_else(|| state.state_name.to_string());
+            dot.push_str(&format!(
+                "{}subgraph cluster_{} {{\n",
+                indent, state.state_name
+            ));
+            dot.push_str(&format!("{}    label=\"{}\";\n", indent, label));
+            dot_states(
+                region,
+                &state.children,
+                edges,
+                &format!("{}    ", indent),
+                dot,
+            );
+            for history in [History::Shallow, History::Deep] {
+                let used = edges
+                    .iter()
+                    .any(|v| *v.to == state.state_name && v.history == Some(history));
+                if used {
+                    dot.push_str(&format!(
+                        "{}    {} [label=\"{}\", shape=circle];\n",
+                        indent,
+                        dot_history_node(&state.state_name, history),
+                        history.marker()
+                    ));
+                }
+            }
+            dot.push_str(&format!("{}}}\n", indent));
+            continue;
+        }
+
+        let mut attributes = Vec::new();
+        attributes.extend(label.map(|v| format!("label=\"{}\"", v)));
+        if region.final_states.contains(&state.state_name) {
+            attributes.push("peripheries=2".to_string());
+        }
+
+        if attributes.is_empty() {
+            dot.push_str(&format!("{}{};\n", indent, state.state_name));
+        } else {
+            dot.push_str(&format!(
+                "{}{} [{}];\n",
+                indent,
+                state.state_name,
+                attributes.join(", ")
+            ));
+        }
+    }
+}
+
+/// One region's initial arrow, states and transitions.
+fn dot_region(region: &Region, initial_node: &str, indent: &str, dot: &mut String) {
+    let (initial, lhead) = dot_node(region, &region.initial);
+    dot.push_str(&format!("{}{} [shape=point];\n", indent, initial_node));
+    match lhead {
+        Some(lhead) => dot.push_str(&format!(
+            "{}{} -> {} [lhead={}];\n",
+            indent, initial_node, initial, lhead
+        )),
+        None => dot.push_str(&format!("{}{} -> {};\n", indent, initial_node, initial)),
+    }
+
+    let edges = edges(region);
+    dot_states(region, &region.states.states, &edges, indent, dot);
+
+    for edge in edges.iter().filter(|v| !v.internal) {
+        let (from, ltail) = dot_node(region, edge.from);
+        let (to, lhead) = match edge.history {
+            Some(history) => (dot_history_node(edge.to, history), None),
+            None => {
+                let (to, lhead) = dot_node(region, edge.to);
+                (to.to_string(), lhead)
+            }
+        };
+
+        let mut attributes = vec![format!("label=\"{}\"", edge.label)];
+        attributes.extend(ltail.map(|v| format!("ltail={}", v)));
+        attributes.extend(lhead.map(|v| format!("lhead={}", v)));
+
+        dot.push_str(&format!(
+            "{}{} -> {} [{}];\n",
+            indent,
+            from,
+            to,
+            attributes.join(", ")
+        ));
+    }
+}
+
+/// The state diagram in Graphviz DOT, every region in a dashed cluster.
+pub(crate) fn dot(machine: &Machine) -> String {
+    let mut dot = format!("digraph {} {{\n", machine.machine_name.name());
+    dot.push_str("    compound=true;\n");
+    dot.push_str("    node [shape=box, style=rounded];\n");
+
+    match machine.single_region() {
+        Some(region) => dot_region(region, "__initial", "    ", &mut dot),
+        None => {
+            for region in &machine.regions {
+                let name = region.name.as_ref().expect("regions are named");
+                dot.push_str(&format!("    subgraph cluster_region_{} {{\n", name));
+                dot.push_str(&format!("        label=\"{}\";\n", name));
+                dot.push_str("        style=dashed;\n");
+                let initial_node = format!("__initial_{}", name);
+                dot_region(region, &initial_node, "        ", &mut dot);
+                dot.push_str("    }\n");
+            }
+        }
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+/// Mermaid and PlantUML share the same nesting of composite states, only
+/// the way a simple state is declared differs.
+fn uml_states(states: &[State], leaf: &str, indent: &str, uml: &mut String) {
+    for state in states {
+        if state.is_superstate() {
+            uml.push_str(&format!("{}state {} {{\n", indent, state.state_name));
+            uml.push_str(&format!(
+                "{}    [*] --> {}\n",
+                indent, state.children[0].state_name
+            ));
+            uml_states(&state.children, leaf, &format!("{}    ", indent), uml);
+            uml.push_str(&format!("{}}}\n", indent));
+        } else {
+            uml.push_str(&format!("{}{}{}\n", indent, leaf, state.state_name));
+        }
+    }
+}
+
+/// One region's initial arrow, states, transitions and final states.
+///
+/// Mermaid has no history pseudo-states, `history_states` is false for it
+/// and the label notes the history instead.
+fn uml_region(region: &Region, leaf: &str, history_states: bool, indent: &str, uml: &mut String) {
+    uml.push_str(&format!("{}[*] --> {}\n", indent, region.initial));
+
+    uml_states(&region.states.states, leaf, indent, uml);
+
+    for edge in edges(region) {
+        if edge.internal {
+            uml.push_str(&format!("{}{} : {}\n", indent, edge.from, edge.label));
+            continue;
+        }
+
+        let (to, label) = match edge.history {
+            Some(history) if history_states => {
+                (format!("{}[{}]", edge.to, history.marker()), edge.label)
+            }
+            Some(history) => (
+                edge.to.to_string(),
+                format!("{} ({})", edge.label, history.marker()),
+            ),
+            None => (edge.to.to_string(), edge.label),
+        };
+        uml.push_str(&format!("{}{} --> {} : {}\n", indent, edge.from, to, label));
+    }
+
+    for state_name in &region.final_states.0 {
+        uml.push_str(&format!("{}{} --> [*]\n", indent, state_name));
+    }
+}
+
+/// Regions run concurrently inside one composite state, separated by `--`.
+fn uml(machine: &Machine, header: &str, leaf: &str, history_states: bool, footer: &str) -> String {
+    let mut uml = String::from(header);
+
+    match machine.single_region() {
+        Some(region) => uml_region(region, leaf, history_states, "    ", &mut uml),
+        None => {
+            uml.push_str("    state Machine {\n");
+            for (i, region) in machine.regions.iter().enumerate() {
+                if i > 0 {
+                    uml.push_str("        --\n");
+                }
+                uml_region(region, leaf, history_states, "        ", &mut uml);
+            }
+            uml.push_str("    }\n");
+        }
+    }
+
+    uml.push_str(footer);
+    uml
+}
+
+/// The state diagram as a Mermaid `stateDiagram-v2`.
+pub(crate) fn mermaid(machine: &Machine) -> String {
+    uml(machine, "stateDiagram-v2\n", "", false, "")
+}
+
+/// The state diagram in PlantUML.
+pub(crate) fn plantuml(machine: &Machine) -> String {
+    uml(machine, "@startuml\n", "state ", true, "@enduml\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::quote;
+
+    fn machine() -> Machine {
+        syn::parse2(quote! {
+            Context = Gun;
+
+            Initial = Idle;
+
+            States {
+                Idle = Idle,
+                Armed = Armed {
+                    Loading = Loading,
+                    Firing = Firing,
+                },
+                Jammed = Jammed,
+            }
+
+            Final { Jammed }
+
+            Events {
+                Arm = Arm,
+                Fire = Fire,
+                Jam = Jam,
+                Tick = Tick,
+                Resume = Resume,
+            }
+
+            Transitions {
+                Arm [
+                    Idle => Armed,
+                ],
+                Fire [
+                    Loading => Firing if loaded,
+                ],
+                Jam [
+                    Armed => Jammed,
+                ],
+                Tick [
+                    Idle -> internal,
+                    Armed -> internal if counting,
+                ],
+                Resume [
+                    Idle => History(Armed),
+                ],
+            }
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_dot() {
+        assert_eq!(
+            dot(&machine()),
+            r#"digraph Machine {
+    compound=true;
+    node [shape=box, style=rounded];
+    __initial [shape=point];
+    __initial -> Idle;
+    Idle [label="Idle\nTick / internal"];
+    subgraph cluster_Armed {
+        label="Armed\nTick [counting] / internal";
+        Loading;
+        Firing;
+        Armed_history [label="H", shape=circle];
+    }
+    Jammed [peripheries=2];
+    Idle -> Loading [label="Arm", lhead=cluster_Armed];
+    Loading -> Firing [label="Fire [loaded]"];
+    Loading -> Jammed [label="Jam", ltail=cluster_Armed];
+    Idle -> Armed_history [label="Resume"];
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_mermaid() {
+        assert_eq!(
+            mermaid(&machine()),
+            r#"stateDiagram-v2
+    [*] --> Idle
+    Idle
+    state Armed {
+        [*] --> Loading
+        Loading
+        Firing
+    }
+    Jammed
+    Idle --> Armed : Arm
+    Loading --> Firing : Fire [loaded]
+    Armed --> Jammed : Jam
+    Armed : Tick [counting] / internal
+    Idle : Tick / internal
+    Idle --> Armed : Resume (H)
+    Jammed --> [*]
+"#
+        );
+    }
+
+    #[test]
+    fn test_plantuml() {
+        assert_eq!(
+            plantuml(&machine()),
+            r#"@startuml
+    [*] --> Idle
+    state Idle
+    state Armed {
+        [*] --> Loading
+        state Loading
+        state Firing
+    }
+    state Jammed
+    Idle --> Armed : Arm
+    Loading --> Firing : Fire [loaded]
+    Armed --> Jammed : Jam
+    Armed : Tick [counting] / internal
+    Idle : Tick / internal
+    Idle --> Armed[H] : Resume
+    Jammed --> [*]
+@enduml
+"#
+        );
+    }
+
+    #[test]
+    fn test_regions() {
+        let machine: Machine = syn::parse2(quote! {
+            Context = Gun;
+
+            Regions {
+                Power {
+                    States {
+                        Healthy = Healthy,
+                        Off = Off,
+                    }
+
+                    Final { Off }
+
+                    Transitions {
+                        PowerOff [
+                            Healthy => Off,
+                        ],
+                    }
+                },
+                Trigger {
+                    States {
+                        Released = Released,
+                        Pulled = Pulled,
+                    }
+
+                    Transitions {
+                        Pull [
+                            Released => Pulled,
+                        ],
+                    }
+                },
+            }
+
+            Events {
+                PowerOff = PowerOff,
+                Pull = Pull,
+            }
+        })
+        .unwrap();
+
+        assert_eq!(
+            dot(&machine),
+            r#"digraph Machine {
+    compound=true;
+    node [shape=box, style=rounded];
+    subgraph cluster_region_Power {
+        label="Power";
+        style=dashed;
+        __initial_Power [shape=point];
+        __initial_Power -> Healthy;
+        Healthy;
+        Off [peripheries=2];
+        Healthy -> Off [label="PowerOff"];
+    }
+    subgraph cluster_region_Trigger {
+        label="Trigger";
+        style=dashed;
+        __initial_Trigger [shape=point];
+        __initial_Trigger -> Released;
+        Released;
+        Pulled;
+        Released -> Pulled [label="Pull"];
+    }
+}
+"#
+        );
+        assert_eq!(
+            mermaid(&machine),
+            r#"stateDiagram-v2
+    state Machine {
+        [*] --> Healthy
+        Healthy
+        Off
+        Healthy --> Off : PowerOff
+        Off --> [*]
+        --
+        [*] --> Released
+        Released
+        Pulled
+        Released --> Pulled : Pull
+    }
+"#
+        );
+    }
+}
@@ -4,11 +4,15 @@ use syn::{
     braced,
     parse::{Parse, ParseStream, Result},
     punctuated::Punctuated,
-    Ident, Token, Type,
+    Attribute, Ident, Token, Type,
 };
 
+use crate::fsm::passthrough::Passthrough;
+
 #[derive(Debug, PartialEq)]
 pub(crate) struct Event {
+    /// Attributes and doc comments of the variant.
+    pub attrs: Vec<Attribute>,
     pub event_name: Ident,
     pub event_type: Type,
 }
@@ -17,9 +21,14 @@ impl Parse for Event {
     /// example event:
     ///
     /// ```text
+    /// /// The trigger was pulled all the way.
     /// S1 = S1
     /// ```
     fn parse(input: ParseStream<'_>) -> Result<Self> {
+        // /// The trigger was pulled all the way.
+        // _______________________________________
+        let attrs = input.call(Attribute::parse_outer)?;
+
         // S1 = S1
         // __
         let event_name: Ident = Ident::parse(input)?;
@@ -33,6 +42,7 @@ impl Parse for Event {
         let event_type: Type = Type::parse(input)?;
 
         Ok(Event {
+            attrs,
             event_name,
             event_type,
         })
@@ -41,22 +51,31 @@ impl Parse for Event {
 
 impl ToTokens for Event {
     fn to_tokens(&self, tokens: &mut TokenStream) {
+        let attrs = &self.attrs;
         let event_name = &self.event_name;
         let event_type = &self.event_type;
         tokens.extend(quote!(
+            #( #attrs )*
             #event_name(#event_type)
         ));
     }
 }
 
 #[derive(Debug, PartialEq)]
-pub(crate) struct Events(pub Vec<Event>);
+pub(crate) struct Events {
+    /// Goes on `Event`, and its visibility on `EventId` too.
+    pub passthrough: Passthrough,
+    /// The `Events` keyword, where errors about the whole section point.
+    pub keyword: Ident,
+    pub events: Vec<Event>,
+}
 
 impl Parse for Events {
     /// example events:
     ///
     /// ```text
-    /// Events {
+    /// #[derive(Hash)]
+    /// pub(crate) Events {
     ///     S1 = S1,
     ///     S2 = S2,
     ///     S3 = S3,
@@ -65,6 +84,11 @@ impl Parse for Events {
     /// }
     /// ```
     fn parse(input: ParseStream<'_>) -> Result<Self> {
+        // #[derive(Hash)]
+        // pub(crate) Events { ... }
+        // __________
+        let passthrough = Passthrough::parse(input)?;
+
         // Events { ... }
         // --------------
         let events_magic = Ident::parse(input)?;
@@ -77,16 +101,65 @@ impl Parse for Events {
         braced!(content in input);
 
         let events: Punctuated<Event, Token![,]> = content.parse_terminated(Event::parse)?;
-        Ok(Events(events.into_iter().collect()))
+        Ok(Events {
+            passthrough,
+            keyword: events_magic,
+            events: events.into_iter().collect(),
+        })
+    }
+}
+
+impl Events {
+    /// `EventId` and the `Event` queries built on it.
+    pub fn to_introspection_tokens(&self) -> TokenStream {
+        let event_names: Vec<_> = self.events.iter().map(|v| &v.event_name).collect();
+        let names: Vec<_> = event_names.iter().map(|v| v.to_string()).collect();
+        let vis = self.passthrough.vis();
+
+        quote! {
+            /// The events of `Event` without their data.
+            #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+            #vis enum EventId {
+                #(#event_names),*
+            }
+
+            impl EventId {
+                pub fn name(self) -> &'static str {
+                    match self {
+                        #( EventId::#event_names => #names, )*
+                    }
+                }
+            }
+
+            impl Event {
+                /// Every event, in declaration order.
+                pub const ALL: &'static [EventId] = &[#( EventId::#event_names ),*];
+
+                /// The names of `ALL`.
+                pub const NAMES: &'static [&'static str] = &[#( #names ),*];
+
+                pub fn id(&self) -> EventId {
+                    match self {
+                        #( Event::#event_names(_) => EventId::#event_names, )*
+                    }
+                }
+
+                pub fn name(&self) -> &'static str {
+                    self.id().name()
+                }
+            }
+        }
     }
 }
 
 impl ToTokens for Events {
     fn to_tokens(&self, tokens: &mut TokenStream) {
-        let events = &self.0;
+        let events = &self.events;
+        let attrs = self.passthrough.attrs();
+        let vis = self.passthrough.vis();
         tokens.extend(quote!(
-            #[derive(Clone, Copy, Debug, PartialEq)]
-            pub enum Event {
+            #attrs
+            #vis enum Event {
                 #(#events),*
             }
         ));
@@ -97,11 +170,11 @@ impl ToTokens for Events {
 mod tests {
     use super::*;
     use proc_macro2::TokenStream;
-    use syn::{self, parse_quote};
 
     #[test]
     fn test_events_parse_and_to_tokens() {
         let events: Events = syn::parse2(quote! {
+            #[derive(Clone, Copy, Debug, PartialEq)]
             Events {
                 E1 = E1
             }
@@ -109,7 +182,7 @@ mod tests {
         .unwrap();
 
         let left = quote! {
-            #[derive(Clone, Debug, PartialEq)]
+            #[derive(Clone, Copy, Debug, PartialEq)]
             pub enum Event {
                 E1(E1)
             }
@@ -120,4 +193,53 @@ mod tests {
 
         assert_eq!(format!("{}", left), format!("{}", right))
     }
+
+    #[test]
+    fn test_events_to_introspection_tokens() {
+        let events: Events = syn::parse2(quote! {
+            Events {
+                E1 = E1
+            }
+        })
+        .unwrap();
+
+        let left = quote! {
+            /// The events of `Event` without their data.
+            #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+            pub enum EventId {
+                E1
+            }
+
+            impl EventId {
+                pub fn name(self) -> &'static str {
+                    match self {
+                        EventId::E1 => "E1",
+                    }
+                }
+            }
+
+            impl Event {
+                /// Every event, in declaration order.
+                pub const ALL: &'static [EventId] = &[EventId::E1];
+
+                /// The names of `ALL`.
+                pub const NAMES: &'static [&'static str] = &["E1"];
+
+                pub fn id(&self) -> EventId {
+                    match self {
+                        Event::E1(_) => EventId::E1,
+                    }
+                }
+
+                pub fn name(&self) -> &'static str {
+                    self.id().name()
+                }
+            }
+        };
+
+        assert_eq!(
+            format!("{}", left),
+            format!("{}", events.to_introspection_tokens())
+        );
+    }
 }
@@ -0,0 +1,69 @@
+use syn::{
+    braced,
+    parse::{Parse, ParseStream, Result},
+    punctuated::Punctuated,
+    Ident, Token,
+};
+
+/// States the machine never leaves once it entered them.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct FinalStates(pub Vec<Ident>);
+
+impl Parse for FinalStates {
+    /// example final states:
+    ///
+    /// ```text
+    /// Final { S4, S5 }
+    /// ```
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        // Final { ... }
+        // _____
+        let final_magic = Ident::parse(input)?;
+
+        if final_magic != "Final" {
+            return Err(input.error("expected Final { ... }"));
+        }
+
+        // Final { S4, S5 }
+        //         ______
+        let content;
+        braced!(content in input);
+
+        let states: Punctuated<Ident, Token![,]> = content.parse_terminated(Ident::parse)?;
+        Ok(FinalStates(states.into_iter().collect()))
+    }
+}
+
+impl FinalStates {
+    pub fn peek(input: ParseStream<'_>) -> bool {
+        matches!(input.fork().parse::<Ident>(), Ok(v) if v == "Final")
+    }
+
+    pub fn contains(&self, state_name: &Ident) -> bool {
+        self.0.contains(state_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::quote;
+    use syn::parse2;
+
+    #[test]
+    fn test_final_states_parse() {
+        let final_states: FinalStates = parse2(quote! {
+            Final { S4, S5 }
+        })
+        .unwrap();
+
+        assert_eq!(
+            final_states
+                .0
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>(),
+            vec!["S4", "S5"]
+        );
+    }
+}
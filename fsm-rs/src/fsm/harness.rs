@@ -0,0 +1,444 @@
+use proc_macro2::{Ident, Literal, Span, TokenStream};
+use quote::quote;
+
+use crate::fsm::{machine::Machine, region::Region};
+
+/// How the harness reaches one region of the machine.
+struct Access {
+    /// `check_machine`, or `check_<region>` with `Regions`.
+    check_fn: Ident,
+    /// `super::`, or `super::<region>::` with `Regions`.
+    path: TokenStream,
+    /// The region's `TRANSITIONS`.
+    transitions: TokenStream,
+    /// `.<region>` with `Regions`, picking the region out of `State`,
+    /// `Outcome` and `Timeouts`.
+    field: TokenStream,
+}
+
+impl Access {
+    fn new(region: &Region) -> Self {
+        match region.module_name() {
+            Some(module) => Access {
+                check_fn: Ident::new(&format!("check_{}", module), Span::call_site()),
+                path: quote!(super::#module::),
+                transitions: quote!(super::#module::Region::TRANSITIONS),
+                field: quote!(.#module),
+            },
+            None => Access {
+                check_fn: Ident::new("check_machine", Span::call_site()),
+                path: quote!(super::),
+                transitions: quote!(super::Machine::TRANSITIONS),
+                field: quote!(),
+            },
+        }
+    }
+}
+
+/// The check of one step of a region against its transition table, and
+/// the calls to it after an event and after a tick.
+fn region_check(
+    region: &Region,
+    error_type: &TokenStream,
+) -> (TokenStream, TokenStream, TokenStream) {
+    let states = &region.states;
+    let Access {
+        check_fn,
+        path,
+        transitions,
+        field,
+    } = Access::new(region);
+
+    // `TRANSITIONS` has no room for `after` transitions
+    let timeouts = states.leaves().into_iter().filter_map(|leaf| {
+        let leaf_name = &leaf.state_name;
+        let timeouts = region.transitions.leaf_timeouts(states, leaf_name);
+        if timeouts.is_empty() {
+            return None;
+        }
+        let entered = timeouts
+            .iter()
+            .flat_map(|(_, timeout)| timeout.target.entered(states, leaf_name));
+        Some(quote!((#path StateId::#leaf_name, &[#( #path StateId::#entered ),*])))
+    });
+
+    let check = quote! {
+        fn #check_fn<E>(
+            before: &#path State,
+            event: Option<&super::Event>,
+            after: &#path State,
+            result: Option<&Result<#path Transition, #path TransitionError<E>>>,
+        ) -> Result<(), String> {
+            const TIMEOUTS: &[(#path StateId, &[#path StateId])] = &[#( #timeouts ),*];
+
+            let (step, targets) = match event {
+                Some(event) => (
+                    event.name(),
+                    #transitions
+                        .iter()
+                        .find(|(from, on, _)| *from == before.id() && *on == event.id())
+                        .map(|v| v.2),
+                ),
+                None => (
+                    "tick",
+                    TIMEOUTS.iter().find(|(from, _)| *from == before.id()).map(|v| v.1),
+                ),
+            };
+            let listed = |state: &#path State| matches!(targets, Some(v) if v.contains(&state.id()));
+
+            let (outcome, allowed) = match result {
+                None => ("nothing due", after == before),
+                Some(Ok(#path Transition::Taken { from, to })) => {
+                    ("taken", from == before && to == after && listed(after))
+                }
+                Some(Ok(#path Transition::Internal { state })) => {
+                    ("internal", state == before && after == before && listed(before))
+                }
+                Some(Ok(#path Transition::Ignored { .. })) => {
+                    ("ignored", after == before && targets.is_none())
+                }
+                Some(Err(#path TransitionError::Unhandled { .. })) => {
+                    ("unhandled", after == before && targets.is_none())
+                }
+                Some(Err(#path TransitionError::NoGuardPassed { .. })) => {
+                    ("no guard passed", after == before && targets.is_some())
+                }
+                Some(Err(#path TransitionError::Callback(_))) => {
+                    ("callback error", after == before || listed(after))
+                }
+            };
+
+            if allowed {
+                Ok(())
+            } else {
+                Err(format!(
+                    "{} --{}--> {} ({}) doesn't match the transition table",
+                    before.name(),
+                    step,
+                    after.name(),
+                    outcome
+                ))
+            }
+        }
+    };
+
+    let after_event = quote! {
+        #check_fn(&before #field, Some(event), &after #field, Some(&result #field))?;
+    };
+    let after_tick = if region.transitions.timeouts.is_empty() {
+        // a region without timers has nothing to tell about the tick
+        quote!(#check_fn::<#error_type>(&before #field, None, &after #field, None)?;)
+    } else {
+        quote!(#check_fn(&before #field, None, &after #field, result #field.as_ref())?;)
+    };
+
+    (check, after_event, after_tick)
+}
+
+/// The `#[cfg(test)]` module `harness` of `#[fsm(harness)]`: random steps
+/// drive fresh machines, each checked against the transition table after
+/// every step, and the steps of the first run that fails are shrunk before
+/// `check` panics with them.
+///
+/// The context every run starts with and the data of every event are made
+/// up by the `harness::Arbitrary` the test implements for their types.
+pub(crate) fn harness(machine: &Machine) -> TokenStream {
+    let context_type = machine.machine_context.context_type();
+    let error_type = machine.machine_error.error_type();
+    let derives = machine.derives_of_both(&["Clone", "Copy", "Debug", "PartialEq"]);
+
+    let event_names: Vec<_> = machine
+        .events
+        .events
+        .iter()
+        .map(|v| &v.event_name)
+        .collect();
+    let event_types = machine.events.events.iter().map(|v| &v.event_type);
+    let event_indices = (0..event_names.len()).map(|v| Literal::u64_unsuffixed(v as u64));
+    let event_count = Literal::u64_unsuffixed(event_names.len() as u64);
+
+    let (checks, (after_events, after_ticks)): (Vec<_>, (Vec<_>, Vec<_>)) = machine
+        .regions
+        .iter()
+        .map(|v| region_check(v, &error_type))
+        .map(|(check, after_event, after_tick)| (check, (after_event, after_tick)))
+        .unzip();
+
+    // without `after` transitions there is nothing to tick
+    let timed = machine
+        .regions
+        .iter()
+        .any(|v| !v.transitions.timeouts.is_empty());
+    let (tick_variant, arbitrary_tick, tick_case) = if timed {
+        (
+            quote! {
+                /// Let time pass up to the next deadline of an `after`
+                /// transition, if any.
+                Tick,
+            },
+            quote! {
+                if random.below(4) == 0 {
+                    return Step::Tick;
+                }
+            },
+            quote! {
+                Step::Tick => {
+                    if let Some(now) = machine.next_deadline() {
+                        let result = machine.tick(now);
+                        let after = machine.state();
+                        #( #after_ticks )*
+                    }
+                }
+            },
+        )
+    } else {
+        (quote!(), quote!(), quote!())
+    };
+
+    quote! {
+        #[cfg(test)]
+        impl harness::Arbitrary for Event {
+            fn arbitrary(random: &mut harness::Random) -> Self {
+                match random.below(#event_count) {
+                    #( #event_indices => Event::#event_names(
+                        <#event_types as harness::Arbitrary>::arbitrary(random)
+                    ), )*
+                    _ => unreachable!(),
+                }
+            }
+        }
+
+        #[cfg(test)]
+        impl harness::Arbitrary for Machine {
+            fn arbitrary(random: &mut harness::Random) -> Self {
+                Machine::with_context(<#context_type as harness::Arbitrary>::arbitrary(random))
+            }
+        }
+
+        /// Property-based tests of `Machine`: `check` drives fresh machines
+        /// with random steps, checks every step against `TRANSITIONS` and
+        /// panics with the shortest steps it found still failing.
+        #[cfg(test)]
+        #[allow(dead_code)]
+        pub mod harness {
+            extern crate std;
+
+            use std::{format, panic, string::String, vec::Vec};
+
+            /// A xorshift generator, so that every run is replayed from its
+            /// seed.
+            #[derive(Clone, Debug)]
+            pub struct Random(u64);
+
+            impl Random {
+                pub fn new(seed: u64) -> Self {
+                    // xorshift never leaves zero
+                    Random(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+                }
+
+                pub fn next_u64(&mut self) -> u64 {
+                    self.0 ^= self.0 << 13;
+                    self.0 ^= self.0 >> 7;
+                    self.0 ^= self.0 << 17;
+                    self.0
+                }
+
+                /// A number in `0..n`.
+                pub fn below(&mut self, n: u64) -> u64 {
+                    self.next_u64() % n
+                }
+
+                pub fn bool(&mut self) -> bool {
+                    self.next_u64() & 1 == 1
+                }
+            }
+
+            /// Made up by the harness: implemented by the test for the
+            /// context and the data of every event.
+            pub trait Arbitrary {
+                fn arbitrary(random: &mut Random) -> Self;
+            }
+
+            /// One step of a run.
+            #derives
+            pub enum Step {
+                Event(super::Event),
+                #tick_variant
+            }
+
+            impl Arbitrary for Step {
+                fn arbitrary(random: &mut Random) -> Self {
+                    #arbitrary_tick
+                    Step::Event(<super::Event as Arbitrary>::arbitrary(random))
+                }
+            }
+
+            /// How many runs of how many steps `check` tries, and the seed
+            /// of the first.
+            #[derive(Clone, Copy, Debug, PartialEq)]
+            pub struct Config {
+                pub runs: u32,
+                pub steps: u32,
+                pub seed: u64,
+            }
+
+            impl Default for Config {
+                fn default() -> Self {
+                    Config {
+                        runs: 256,
+                        steps: 64,
+                        seed: 0,
+                    }
+                }
+            }
+
+            /// The step a replay failed at, and why.
+            #[derive(Clone, Debug, PartialEq)]
+            pub struct Failure {
+                pub step: usize,
+                pub message: String,
+            }
+
+            /// Replay `config.runs` runs of random steps, and panic with the
+            /// seed and the shrunk steps of the first that fails.
+            pub fn check(config: Config) {
+                for run in 0..u64::from(config.runs) {
+                    let seed = config.seed.wrapping_add(run);
+                    let mut random = Random::new(seed);
+                    let steps: Vec<Step> = (0..config.steps)
+                        .map(|_| <Step as Arbitrary>::arbitrary(&mut random))
+                        .collect();
+
+                    if let Err(failure) = replay(seed, &steps) {
+                        let (steps, failure) = shrink(seed, steps, failure);
+                        let steps: Vec<String> =
+                            steps.iter().map(|v| format!("    {:?}", v)).collect();
+                        panic!(
+                            "seed {} failed at step {}: {}\n{}",
+                            seed,
+                            failure.step,
+                            failure.message,
+                            steps.join("\n")
+                        );
+                    }
+                }
+            }
+
+            /// Take `steps` with a fresh machine made up from `seed`, failing
+            /// at the first step that panics or doesn't match the transition
+            /// table.
+            pub fn replay(seed: u64, steps: &[Step]) -> Result<(), Failure> {
+                let mut step = 0;
+                let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                    run(seed, steps, &mut step)
+                }));
+
+                let message = match result {
+                    Ok(Ok(())) => return Ok(()),
+                    Ok(Err(message)) => message,
+                    Err(payload) => {
+                        let message = payload
+                            .downcast_ref::<&str>()
+                            .map(|v| String::from(*v))
+                            .or_else(|| payload.downcast_ref::<String>().cloned())
+                            .unwrap_or_default();
+                        format!("panicked: {}", message)
+                    }
+                };
+                Err(Failure { step, message })
+            }
+
+            fn run(seed: u64, steps: &[Step], index: &mut usize) -> Result<(), String> {
+                // the context has a stream of its own, so that shrinking the
+                // steps keeps it
+                let mut machine = <super::Machine as Arbitrary>::arbitrary(&mut Random::new(!seed));
+
+                for (i, step) in steps.iter().enumerate() {
+                    *index = i;
+                    let before = machine.state();
+                    match step {
+                        Step::Event(event) => {
+                            let result = machine.event(event.clone());
+                            let after = machine.state();
+                            #( #after_events )*
+                        }
+                        #tick_case
+                    }
+                }
+                Ok(())
+            }
+
+            /// Drop ever smaller chunks of `steps` while the replay still
+            /// fails.
+            fn shrink(seed: u64, mut steps: Vec<Step>, mut failure: Failure) -> (Vec<Step>, Failure) {
+                steps.truncate(failure.step + 1);
+
+                let mut chunk = steps.len() / 2;
+                while chunk > 0 {
+                    let mut start = 0;
+                    while start < steps.len() {
+                        let mut candidate = steps.clone();
+                        let end = (start + chunk).min(candidate.len());
+                        let _ = candidate.drain(start..end);
+
+                        match replay(seed, &candidate) {
+                            Err(v) => {
+                                candidate.truncate(v.step + 1);
+                                steps = candidate;
+                                failure = v;
+                            }
+                            Ok(()) => start += chunk,
+                        }
+                    }
+                    chunk /= 2;
+                }
+
+                (steps, failure)
+            }
+
+            #( #checks )*
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_harness_only_when_asked() {
+        let machine = |attributes: TokenStream| -> Machine {
+            syn::parse2(quote! {
+                #attributes
+
+                Context = Context;
+
+                States {
+                    S1 = S1,
+                    S2 = S2,
+                }
+
+                Events {
+                    E1 = E1,
+                }
+
+                Transitions {
+                    E1 [
+                        S1 => S2,
+                    ],
+                }
+            })
+            .unwrap()
+        };
+
+        let tokens = |machine: Machine| quote!(#machine).to_string();
+
+        assert!(!tokens(machine(quote!())).contains("pub mod harness"));
+        assert!(tokens(machine(quote!(#[fsm(harness)]))).contains("pub mod harness"));
+
+        // a machine without timers has no ticks to take
+        let harness = harness(&machine(quote!(#[fsm(harness)]))).to_string();
+        assert!(harness.contains("fn check_machine"));
+        assert!(!harness.contains("Tick"));
+    }
+}
@@ -0,0 +1,84 @@
+use syn::{
+    parse::{Parse, ParseStream, Result},
+    Ident, Token,
+};
+
+use crate::fsm::states::States;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct InitialState {
+    state_name: Option<Ident>,
+}
+
+impl Parse for InitialState {
+    /// example initial state:
+    ///
+    /// ```text
+    /// Initial = S1;
+    /// ```
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        // Initial = S1;
+        // _______
+        let initial_magic: Ident = Ident::parse(input)?;
+
+        if initial_magic != "Initial" {
+            return Err(input.error("expected Initial = <state name>"));
+        }
+
+        // Initial = S1;
+        //         _
+        let _: Token![=] = input.parse()?;
+
+        // Initial = S1;
+        //           __
+        let state_name: Ident = Ident::parse(input)?;
+
+        // Initial = S1;
+        //             _
+        let _: Token![;] = input.parse()?;
+
+        Ok(InitialState {
+            state_name: Some(state_name),
+        })
+    }
+}
+
+impl InitialState {
+    pub fn peek(input: ParseStream<'_>) -> bool {
+        matches!(input.fork().parse::<Ident>(), Ok(v) if v == "Initial")
+    }
+
+    /// The declared initial state; machines without an `Initial = ...;`
+    /// declaration start in the first declared state.
+    pub fn state_name(&self, states: &States) -> Option<Ident> {
+        self.state_name
+            .clone()
+            .or_else(|| states.all().first().map(|v| v.state_name.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::quote;
+    use syn::parse2;
+
+    #[test]
+    fn test_initial_state_parse() {
+        let states: States = parse2(quote! {
+            States {
+                S1 = S1,
+                S2 = S2,
+            }
+        })
+        .unwrap();
+
+        let initial: InitialState = parse2(quote! {
+            Initial = S2;
+        })
+        .unwrap();
+
+        assert_eq!(initial.state_name(&states).unwrap(), "S2");
+        assert_eq!(InitialState::default().state_name(&states).unwrap(), "S1");
+    }
+}
@@ -0,0 +1,726 @@
+use std::collections::{btree_map::Entry, BTreeMap, BTreeSet, VecDeque};
+use syn::{
+    braced, parenthesized,
+    parse::{Error, Parse, ParseStream, Result},
+    punctuated::Punctuated,
+    Ident, Token,
+};
+
+use crate::fsm::{region::Region, states::State, transitions::TransitionTarget};
+
+/// A property every run of the region has to keep, proven at expansion time
+/// by walking every configuration the region can get into.
+///
+/// Guards are assumed to pass and to fail alike, so a property that holds
+/// holds whatever the context does, while a counterexample may take a guard
+/// the context never passes.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Invariant {
+    /// `reachable(S1, S2)`, every state is entered by some run.
+    Reachable {
+        keyword: Ident,
+        state_names: Vec<Ident>,
+    },
+    /// `forbidden(S1 => S2)`, no transition out of `S1` enters `S2`.
+    Forbidden {
+        keyword: Ident,
+        from: Ident,
+        to: Ident,
+    },
+    /// `through(S1 => S2, S3)`, `S2` is never entered after `S1` without
+    /// passing through `S3` in between.
+    Through {
+        keyword: Ident,
+        from: Ident,
+        to: Ident,
+        through: Ident,
+    },
+    /// `can_reach(S1, S2)`, wherever a run got to, it can still get to one
+    /// of the states.
+    CanReach {
+        keyword: Ident,
+        state_names: Vec<Ident>,
+    },
+    /// `no_deadlock`, every state a run gets to is final or can be left.
+    NoDeadlock { keyword: Ident },
+}
+
+impl Parse for Invariant {
+    /// example invariants:
+    ///
+    /// ```text
+    /// reachable(S3)
+    /// forbidden(S1 => S3)
+    /// through(S1 => S3, S2)
+    /// can_reach(S1, S5)
+    /// no_deadlock
+    /// ```
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        // forbidden(S1 => S3)
+        // _________
+        let keyword: Ident = input.parse()?;
+
+        if keyword == "no_deadlock" {
+            return Ok(Invariant::NoDeadlock { keyword });
+        }
+
+        // forbidden(S1 => S3)
+        //          _________
+        let content;
+        parenthesized!(content in input);
+
+        let invariant = if keyword == "reachable" || keyword == "can_reach" {
+            // can_reach(S1, S5)
+            //           ______
+            let state_names: Punctuated<Ident, Token![,]> =
+                content.parse_terminated(Ident::parse)?;
+            if state_names.is_empty() {
+                return Err(Error::new(
+                    keyword.span(),
+                    format!("{} needs at least one state", keyword),
+                ));
+            }
+            let state_names = state_names.into_iter().collect();
+
+            if keyword == "reachable" {
+                Invariant::Reachable {
+                    keyword,
+                    state_names,
+                }
+            } else {
+                Invariant::CanReach {
+                    keyword,
+                    state_names,
+                }
+            }
+        } else if keyword == "forbidden" || keyword == "through" {
+            // through(S1 => S3, S2)
+            //         ________
+            let from: Ident = content.parse()?;
+            let _: Token![=>] = content.parse()?;
+            let to: Ident = content.parse()?;
+
+            if keyword == "forbidden" {
+                Invariant::Forbidden { keyword, from, to }
+            } else {
+                // through(S1 => S3, S2)
+                //                 ____
+                let _: Token![,] = content.parse()?;
+                let through: Ident = content.parse()?;
+                Invariant::Through {
+                    keyword,
+                    from,
+                    to,
+                    through,
+                }
+            }
+        } else {
+            return Err(Error::new(
+                keyword.span(),
+                "expected reachable, forbidden, through, can_reach or no_deadlock",
+            ));
+        };
+
+        if !content.is_empty() {
+            return Err(content.error("unexpected tokens"));
+        }
+
+        Ok(invariant)
+    }
+}
+
+impl Invariant {
+    /// Every state the invariant names.
+    pub fn state_names(&self) -> Vec<&Ident> {
+        match self {
+            Invariant::Reachable { state_names, .. } | Invariant::CanReach { state_names, .. } => {
+                state_names.iter().collect()
+            }
+            Invariant::Forbidden { from, to, .. } => vec![from, to],
+            Invariant::Through {
+                from, to, through, ..
+            } => vec![from, to, through],
+            Invariant::NoDeadlock { .. } => Vec::new(),
+        }
+    }
+
+    fn keyword(&self) -> &Ident {
+        match self {
+            Invariant::Reachable { keyword, .. }
+            | Invariant::Forbidden { keyword, .. }
+            | Invariant::Through { keyword, .. }
+            | Invariant::CanReach { keyword, .. }
+            | Invariant::NoDeadlock { keyword } => keyword,
+        }
+    }
+
+    /// `None` when the invariant holds, or why it doesn't with the shortest
+    /// run showing it.
+    fn violation(&self, graph: &Graph<'_>) -> Option<String> {
+        match self {
+            Invariant::Reachable { state_names, .. } => {
+                let unreachable: Vec<_> = state_names
+                    .iter()
+                    .filter(|v| !graph.configs.iter().any(|c| graph.inside(c.leaf, v)))
+                    .map(|v| v.to_string())
+                    .collect();
+                if unreachable.is_empty() {
+                    return None;
+                }
+                Some(format!(
+                    "no sequence of events and delays leads from {} to {}",
+                    graph.configs[0].leaf,
+                    unreachable.join(" or ")
+                ))
+            }
+            Invariant::Forbidden { from, to, .. } => {
+                for (index, config) in graph.configs.iter().enumerate() {
+                    if !graph.inside(config.leaf, from) {
+                        continue;
+                    }
+                    for (label, next) in &graph.steps[index] {
+                        if graph.inside(graph.configs[*next].leaf, to) {
+                            let mut path = graph.path(index);
+                            path.push((Some(label.clone()), *next));
+                            return Some(format!(
+                                "{} is entered from {}: {}",
+                                to,
+                                from,
+                                graph.describe(&path)
+                            ));
+                        }
+                    }
+                }
+                None
+            }
+            Invariant::Through {
+                from, to, through, ..
+            } => {
+                // a run is armed once it has been in `from`, and disarmed
+                // again by passing through `through`
+                let mut parents: BTreeMap<Armed, Option<(String, Armed)>> = BTreeMap::new();
+                let mut pending = VecDeque::new();
+                let _ = parents.insert((0, false), None);
+                pending.push_back((0, false));
+
+                while let Some(node) = pending.pop_front() {
+                    let (index, armed) = node;
+                    let armed = armed || graph.inside(graph.configs[index].leaf, from);
+                    for (label, next) in &graph.steps[index] {
+                        let leaf = graph.configs[*next].leaf;
+                        let passed = graph.inside(leaf, through);
+
+                        if armed && !passed && graph.inside(leaf, to) {
+                            // walk back to the initial configuration
+                            let mut path = vec![(Some(label.clone()), *next)];
+                            let mut node = node;
+                            loop {
+                                let parent = parents.get(&node).cloned().flatten();
+                                path.push((parent.as_ref().map(|v| v.0.clone()), node.0));
+                                match parent {
+                                    Some((_, v)) => node = v,
+                                    None => break,
+                                }
+                            }
+                            path.reverse();
+                            return Some(format!(
+                                "{} is entered from {} without passing through {}: {}",
+                                to,
+                                from,
+                                through,
+                                graph.describe(&path)
+                            ));
+                        }
+
+                        let next = (*next, armed && !passed);
+                        if let Entry::Vacant(v) = parents.entry(next) {
+                            let _ = v.insert(Some((label.clone(), node)));
+                            pending.push_back(next);
+                        }
+                    }
+                }
+                None
+            }
+            Invariant::CanReach { state_names, .. } => {
+                // the configurations some run out of gets to one of the
+                // states, grown backwards until nothing changes
+                let mut good: BTreeSet<usize> = (0..graph.configs.len())
+                    .filter(|v| {
+                        state_names
+                            .iter()
+                            .any(|state_name| graph.inside(graph.configs[*v].leaf, state_name))
+                    })
+                    .collect();
+                loop {
+                    let more: Vec<_> = (0..graph.configs.len())
+                        .filter(|v| !good.contains(v))
+                        .filter(|v| graph.steps[*v].iter().any(|(_, next)| good.contains(next)))
+                        .collect();
+                    if more.is_empty() {
+                        break;
+                    }
+                    good.extend(more);
+                }
+
+                let stuck = (0..graph.configs.len()).find(|v| !good.contains(v))?;
+                let state_names: Vec<_> = state_names.iter().map(|v| v.to_string()).collect();
+                Some(format!(
+                    "{} can't be reached from {}: {}",
+                    state_names.join(" or "),
+                    graph.configs[stuck].leaf,
+                    graph.describe(&graph.path(stuck))
+                ))
+            }
+            Invariant::NoDeadlock { .. } => {
+                let stuck = (0..graph.configs.len()).find(|v| {
+                    graph.steps[*v].is_empty() && !graph.is_final(graph.configs[*v].leaf)
+                })?;
+                Some(format!(
+                    "the machine gets stuck in {}: {}",
+                    graph.configs[stuck].leaf,
+                    graph.describe(&graph.path(stuck))
+                ))
+            }
+        }
+    }
+}
+
+/// The properties of a region, see `Invariant`.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Invariants(pub Vec<Invariant>);
+
+impl Parse for Invariants {
+    /// example invariants:
+    ///
+    /// ```text
+    /// Invariants {
+    ///     reachable(S3),
+    ///     through(S1 => S3, S2),
+    ///     no_deadlock,
+    /// }
+    /// ```
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        // Invariants { ... }
+        // __________
+        let magic = Ident::parse(input)?;
+
+        if magic != "Invariants" {
+            return Err(input.error("expected Invariants { ... }"));
+        }
+
+        let content;
+        braced!(content in input);
+
+        let invariants: Punctuated<Invariant, Token![,]> =
+            content.parse_terminated(Invariant::parse)?;
+        Ok(Invariants(invariants.into_iter().collect()))
+    }
+}
+
+impl Invariants {
+    pub fn peek(input: ParseStream<'_>) -> bool {
+        matches!(input.fork().parse::<Ident>(), Ok(v) if v == "Invariants")
+    }
+
+    /// Check every invariant against the expanded transitions of the region,
+    /// one error with a counterexample for each that doesn't hold.
+    pub fn check(&self, region: &Region) -> Vec<Error> {
+        if self.0.is_empty() {
+            return Vec::new();
+        }
+
+        let graph = match Graph::new(region) {
+            Some(graph) => graph,
+            None => return Vec::new(),
+        };
+
+        self.0
+            .iter()
+            .filter_map(|invariant| {
+                let message = invariant.violation(&graph)?;
+                Some(Error::new(
+                    invariant.keyword().span(),
+                    format!("invariant violated: {}", message),
+                ))
+            })
+            .collect()
+    }
+}
+
+/// A configuration, by index, and whether the run got to it through the
+/// `from` state of a `through` invariant since it last passed `through`.
+type Armed = (usize, bool);
+
+/// Where a run of the region can be: its leaf, and the leaf each superstate
+/// with history remembers.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Config<'a> {
+    leaf: &'a Ident,
+    memory: Vec<Option<&'a Ident>>,
+}
+
+/// Every configuration a run of the region gets to, in the order a breadth
+/// first walk from the initial one finds them.
+struct Graph<'a> {
+    region: &'a Region,
+    configs: Vec<Config<'a>>,
+    /// The step each configuration was first found by.
+    parents: Vec<Option<(String, usize)>>,
+    /// The steps out of each configuration, internal transitions aside.
+    steps: Vec<Vec<(String, usize)>>,
+}
+
+impl<'a> Graph<'a> {
+    fn new(region: &'a Region) -> Option<Self> {
+        let Region {
+            initial,
+            states,
+            transitions,
+            ..
+        } = region;
+
+        let histories = transitions.histories(states);
+        let initial_leaf = states.entry_path(initial)?.pop()?;
+
+        let mut graph = Graph {
+            region,
+            configs: vec![Config {
+                leaf: &initial_leaf.state_name,
+                memory: vec![None; histories.len()],
+            }],
+            parents: vec![None],
+            steps: Vec::new(),
+        };
+        let mut indices = BTreeMap::new();
+        let _ = indices.insert(graph.configs[0].clone(), 0);
+
+        let mut index = 0;
+        while index < graph.configs.len() {
+            let config = graph.configs[index].clone();
+
+            let events = transitions.events.iter().flat_map(|transition| {
+                transition
+                    .leaf_targets(states, config.leaf)
+                    .into_iter()
+                    .map(move |(source, target)| {
+                        let label = match &target.guard {
+                            Some(guard) => format!("{} if {}", transition.event_name, guard),
+                            None => transition.event_name.to_string(),
+                        };
+                        (label, source, target)
+                    })
+            });
+            let timers = transitions
+                .leaf_timeouts(states, config.leaf)
+                .into_iter()
+                .map(|(source, timeout)| (timeout.label(), source, &timeout.target));
+
+            let mut steps = Vec::new();
+            for (label, source, target) in events.chain(timers) {
+                if target.internal {
+                    continue;
+                }
+
+                let next = graph.take(&histories, &config, source, target)?;
+                let next_index = match indices.get(&next) {
+                    Some(v) => *v,
+                    None => {
+                        let v = graph.configs.len();
+                        let _ = indices.insert(next.clone(), v);
+                        graph.configs.push(next);
+                        graph.parents.push(Some((label.clone(), index)));
+                        v
+                    }
+                };
+                steps.push((label, next_index));
+            }
+            graph.steps.push(steps);
+
+            index += 1;
+        }
+
+        Some(graph)
+    }
+
+    /// The configuration taking `target` of `source` leads to, following
+    /// what the generated code remembers on exit and resumes on entry.
+    fn take(
+        &self,
+        histories: &[&'a State],
+        config: &Config<'a>,
+        source: &'a Ident,
+        target: &'a TransitionTarget,
+    ) -> Option<Config<'a>> {
+        let states = &self.region.states;
+
+        // a history target resumes whatever its remembered leaf selects
+        let resumed = target.resumed(states, config.leaf);
+        let remembered = histories
+            .iter()
+            .position(|v| v.state_name == target.to)
+            .and_then(|v| config.memory[v]);
+        let to = match remembered {
+            Some(remembered) if resumed.len() > 1 => resumed
+                .iter()
+                .find(|(leaves, _)| leaves.contains(&remembered))
+                .map_or(resumed[0].1, |v| v.1),
+            _ => resumed[0].1,
+        };
+        let leaf = &states.entry_path(to)?.pop()?.state_name;
+
+        // the superstates left on the way remember the leaf left
+        let source_path = states.path(source)?;
+        let target_path = states.path(&target.to)?;
+        let common = source_path[..source_path.len() - 1]
+            .iter()
+            .zip(&target_path[..target_path.len() - 1])
+            .take_while(|(a, b)| a.state_name == b.state_name)
+            .count();
+        let leaf_path = states.path(config.leaf)?;
+
+        let mut memory = config.memory.clone();
+        for exited in &leaf_path[common..leaf_path.len() - 1] {
+            if let Some(v) = histories
+                .iter()
+                .position(|h| h.state_name == exited.state_name)
+            {
+                memory[v] = Some(config.leaf);
+            }
+        }
+
+        Some(Config { leaf, memory })
+    }
+
+    /// Whether `leaf` is `state_name` or inside it.
+    fn inside(&self, leaf: &Ident, state_name: &Ident) -> bool {
+        match self.region.states.path(leaf) {
+            Some(path) => path.iter().any(|v| v.state_name == *state_name),
+            None => false,
+        }
+    }
+
+    fn is_final(&self, leaf: &Ident) -> bool {
+        self.region.final_states.contains(leaf)
+    }
+
+    /// The shortest run from the initial configuration to `index`.
+    fn path(&self, mut index: usize) -> Vec<(Option<String>, usize)> {
+        let mut path = Vec::new();
+        loop {
+            let parent = self.parents[index].clone();
+            path.push((parent.as_ref().map(|v| v.0.clone()), index));
+            match parent {
+                Some((_, v)) => index = v,
+                None => break,
+            }
+        }
+        path.reverse();
+        path
+    }
+
+    /// `S1 --EVENT1--> S2 --after 200ms--> S3`
+    fn describe(&self, path: &[(Option<String>, usize)]) -> String {
+        path.iter()
+            .map(|(label, index)| {
+                let leaf = self.configs[*index].leaf;
+                match label {
+                    Some(label) => format!(" --{}--> {}", label, leaf),
+                    None => leaf.to_string(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fsm::machine::Machine;
+    use quote::quote;
+    use syn::parse2;
+
+    #[test]
+    fn test_invariants_parse() {
+        let invariants: Invariants = parse2(quote! {
+            Invariants {
+                reachable(S3, S4),
+                forbidden(S1 => S3),
+                through(S1 => S3, S2),
+                can_reach(S1),
+                no_deadlock,
+            }
+        })
+        .unwrap();
+
+        let names: Vec<_> = invariants
+            .0
+            .iter()
+            .map(|v| {
+                let state_names: Vec<_> = v.state_names().iter().map(|v| v.to_string()).collect();
+                format!("{} {}", v.keyword(), state_names.join(" "))
+            })
+            .collect();
+
+        assert_eq!(
+            names,
+            vec![
+                "reachable S3 S4",
+                "forbidden S1 S3",
+                "through S1 S3 S2",
+                "can_reach S1",
+                "no_deadlock ",
+            ]
+        );
+
+        let result: Result<Invariants> = parse2(quote! {
+            Invariants {
+                eventually(S1),
+            }
+        });
+
+        assert!(result.is_err());
+
+        let result: Result<Invariants> = parse2(quote! {
+            Invariants {
+                through(S1 => S3),
+            }
+        });
+
+        assert!(result.is_err());
+    }
+
+    fn messages(result: Result<Machine>) -> Vec<String> {
+        match result {
+            Ok(_) => Vec::new(),
+            Err(err) => err.into_iter().map(|v| v.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_invariants_check() {
+        let result = parse2(quote! {
+            Context = Context;
+
+            Initial = PowerOn;
+
+            States {
+                PowerOn = PowerOn,
+                Ready = Ready,
+                Low = Low,
+                Armed = Armed {
+                    Preloading = Preloading,
+                    Firing = Firing,
+                },
+                Overcurrent = Overcurrent,
+                Error = Error,
+            }
+
+            Final { Error }
+
+            Events {
+                Post = Post,
+                Pull = Pull,
+                Release = Release,
+                Battery = Battery,
+                Current = Current,
+            }
+
+            Transitions {
+                Post [
+                    PowerOn => Ready if passed,
+                    PowerOn => Error,
+                ],
+                Pull [
+                    Ready => Preloading,
+                    Preloading => Firing if loaded,
+                ],
+                Release [
+                    Armed => Ready,
+                ],
+                Battery [
+                    Ready => Low,
+                ],
+                Current [
+                    Armed => Overcurrent,
+                    Overcurrent => History(Armed),
+                ],
+                Preloading => Ready after 200ms,
+            }
+
+            Invariants {
+                reachable(Firing, Overcurrent),
+                forbidden(Ready => Firing),
+                through(Ready => Firing, Preloading),
+                forbidden(Overcurrent => Firing),
+                through(Preloading => Overcurrent, Firing),
+                can_reach(Ready, Error),
+                no_deadlock,
+            }
+        });
+
+        assert_eq!(
+            messages(result),
+            vec![
+                "invariant violated: Firing is entered from Overcurrent: \
+                 PowerOn --Post if passed--> Ready --Pull--> Preloading \
+                 --Pull if loaded--> Firing --Current--> Overcurrent --Current--> Firing",
+                "invariant violated: Overcurrent is entered from Preloading without \
+                 passing through Firing: PowerOn --Post if passed--> Ready --Pull--> \
+                 Preloading --Current--> Overcurrent",
+                "invariant violated: Ready or Error can't be reached from Low: \
+                 PowerOn --Post if passed--> Ready --Battery--> Low",
+                "invariant violated: the machine gets stuck in Low: \
+                 PowerOn --Post if passed--> Ready --Battery--> Low",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invariants_check_history() {
+        // History(Armed) only resumes Firing after Firing was left for
+        // Overcurrent, which needs Preloading first
+        let result = parse2(quote! {
+            Context = Context;
+
+            States {
+                Ready = Ready,
+                Overcurrent = Overcurrent,
+                Armed = Armed {
+                    Preloading = Preloading,
+                    Firing = Firing,
+                },
+            }
+
+            Events {
+                Pull = Pull,
+                Current = Current,
+            }
+
+            Transitions {
+                Pull [
+                    Ready => Preloading,
+                    Preloading => Firing,
+                ],
+                Current [
+                    Ready => Overcurrent,
+                    Armed => Overcurrent,
+                    Overcurrent => History(Armed),
+                ],
+            }
+
+            Invariants {
+                through(Ready => Firing, Preloading),
+                reachable(Overcurrent),
+                forbidden(Ready => Overcurrent),
+            }
+        });
+
+        assert_eq!(
+            messages(result),
+            vec![
+                "invariant violated: Overcurrent is entered from Ready: \
+                  Ready --Current--> Overcurrent"
+            ]
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use syn::{
+    bracketed,
+    parse::{Error, Parse, ParseStream, Result},
+    punctuated::Punctuated,
+    Ident, Token,
+};
+
+/// How a check that doesn't make the generated code invalid is reported.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum Level {
+    #[default]
+    Deny,
+    Warn,
+    Allow,
+}
+
+/// The levels of the checks that can be relaxed, all denied by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct Lints {
+    /// An event with no transitions out of any state.
+    pub unused_events: Level,
+    /// A state no sequence of events leads to from the initial state.
+    pub unreachable_states: Level,
+}
+
+impl Parse for Lints {
+    /// example lints:
+    ///
+    /// ```text
+    /// Warn = [unused_events];
+    /// Allow = [unreachable_states];
+    /// ```
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        let mut lints = Lints::default();
+
+        while Lints::peek(input) {
+            // Warn = [unused_events];
+            // ____
+            let level_magic: Ident = Ident::parse(input)?;
+            let level = if level_magic == "Warn" {
+                Level::Warn
+            } else {
+                Level::Allow
+            };
+
+            // Warn = [unused_events];
+            //      _
+            let _: Token![=] = input.parse()?;
+
+            // Warn = [unused_events];
+            //        _______________
+            let content;
+            bracketed!(content in input);
+            let names: Punctuated<Ident, Token![,]> = content.parse_terminated(Ident::parse)?;
+
+            for name in names {
+                if name == "unused_events" {
+                    lints.unused_events = level;
+                } else if name == "unreachable_states" {
+                    lints.unreachable_states = level;
+                } else {
+                    return Err(Error::new(
+                        name.span(),
+                        "expected unused_events or unreachable_states",
+                    ));
+                }
+            }
+
+            // Warn = [unused_events];
+            //                       _
+            let _: Token![;] = input.parse()?;
+        }
+
+        Ok(lints)
+    }
+}
+
+impl Lints {
+    pub fn peek(input: ParseStream<'_>) -> bool {
+        matches!(input.fork().parse::<Ident>(), Ok(v) if v == "Warn" || v == "Allow")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::quote;
+    use syn::parse2;
+
+    #[test]
+    fn test_lints_parse() {
+        let lints: Lints = parse2(quote! {
+            Warn = [unused_events];
+            Allow = [unreachable_states];
+        })
+        .unwrap();
+
+        assert_eq!(
+            lints,
+            Lints {
+                unused_events: Level::Warn,
+                unreachable_states: Level::Allow,
+            }
+        );
+
+        let result: Result<Lints> = parse2(quote! {
+            Warn = [unknown_states];
+        });
+
+        assert!(result.is_err());
+    }
+}
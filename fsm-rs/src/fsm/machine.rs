@@ -1,20 +1,55 @@
-use proc_macro2::TokenStream;
-use quote::{quote, ToTokens};
+use proc_macro2::{Literal, Span, TokenStream};
+use quote::{format_ident, quote, ToTokens};
 use syn::{
     parse::{Parse, ParseStream, Result},
+    Visibility,
 };
 
-use crate::fsm::{
-    events::Events, machine_context::MachineContext, states::States,
-    transitions::Transitions,
+use crate::{
+    error::Error,
+    fsm::{
+        attributes::Attributes,
+        backend::Backend,
+        defer::Defer,
+        diagram,
+        events::Events,
+        harness,
+        invariants::Invariants,
+        lints::Lints,
+        machine_context::{ContextAccess, MachineContext},
+        machine_error::MachineError,
+        machine_name::MachineName,
+        passthrough::Passthrough,
+        queue,
+        region::{Region, Regions},
+        snapshot,
+        transitions::Transitions,
+        unhandled_policy::UnhandledPolicy,
+        validation,
+    },
 };
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub(crate) struct Machine {
+    pub backend: Backend,
+    /// Whether to generate the `#[cfg(test)]` module `harness`.
+    pub harness: bool,
+    /// The capacity of the queue of `Machine::post` and `Machine::run`,
+    /// which are only generated with one.
+    pub queue: Option<usize>,
+    /// Whether to generate `snapshot`, `restore` and `Codec`.
+    pub snapshot: bool,
+    /// The attributes and the visibility of `Machine`.
+    pub passthrough: Passthrough,
+    pub machine_name: MachineName,
     pub machine_context: MachineContext,
+    pub machine_error: MachineError,
+    pub unhandled_policy: UnhandledPolicy,
     pub events: Events,
-    pub states: States,
-    pub transitions: Transitions,
+    /// A single unnamed region, or the declared `Regions`.
+    pub regions: Vec<Region>,
+    /// Problems found by `validation` that are only reported as warnings.
+    pub warnings: Vec<Error>,
 }
 
 impl Parse for Machine {
@@ -22,9 +57,26 @@ impl Parse for Machine {
     ///
     /// ```text
     ///
-    /// Context = Machine;
+    /// /// Doc comments and attributes go on `Machine`.
+    /// #[fsm(backend = "table", harness, queue = 8, snapshot)]
+    /// #[derive(Debug)]
+    /// pub(crate)
+    ///
+    /// Name = TriggerFsm<B: Board>;
+    ///
+    /// Context = Machine<B>;
+    ///
+    /// Error = MachineError;
+    ///
+    /// Unhandled = Ignore;
+    ///
+    /// Warn = [unused_events];
     ///
+    /// Initial = S1;
+    ///
+    /// #[derive(Hash)]
     /// States {
+    ///     /// Doc comments and attributes go on the variants.
     ///     S1 = S1,
     ///     S2 = S2,
     ///     S3 = S3,
@@ -32,255 +84,1279 @@ impl Parse for Machine {
     ///     S5 = S5
     /// }
     ///
+    /// Final { S5 }
+    ///
     /// Events {
     ///     EVENT1 = Event1,
     ///     EVENT2 = Event2
     /// }
-
+    ///
     /// Transitions {
     ///     EVENT1 [
-    ///        S1 => S2,
+    ///        S1 => S2 if guard,
     ///        S1 => S3,
     ///     ],
     ///     EVENT2 [
     ///         S4 => S5,
     ///     ]
     /// }
+    ///
+    /// Defer {
+    ///     S1: [EVENT2],
+    /// }
+    ///
+    /// Invariants {
+    ///     reachable(S5),
+    ///     through(S1 => S5, S4),
+    /// }
+    /// ```
+    ///
+    /// example machine with regions, each with its own `Initial`, `States`,
+    /// `Final`, `Transitions`, `Defer` and `Invariants`:
+    ///
+    /// ```text
+    /// Context = Machine;
+    ///
+    /// Regions {
+    ///     Power { ... },
+    ///     Trigger { ... },
+    /// }
+    ///
+    /// Events {
+    ///     EVENT1 = Event1,
+    ///     EVENT2 = Event2
+    /// }
     /// ```
     fn parse(input: ParseStream<'_>) -> Result<Self> {
-        // Context = Machine;
+        // /// Doc comments and attributes go on `Machine`.
+        // #[fsm(backend = "table", harness, queue = 8, snapshot)]
+        // #[derive(Debug)]
+        let Attributes {
+            backend,
+            harness,
+            queue,
+            snapshot,
+            attrs,
+        } = if Attributes::peek(input) {
+            Attributes::parse(input)?
+        } else {
+            Attributes::default()
+        };
+        if harness && backend == Backend::Async {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "the harness drives its machines synchronously, it can't with the async backend",
+            ));
+        }
+
+        // pub(crate)
+        let vis = Visibility::parse(input)?;
+        let passthrough = Passthrough { attrs, vis };
+
+        // Name = TriggerFsm<B: Board>;
+        let machine_name = if MachineName::peek(input) {
+            MachineName::parse(input)?
+        } else {
+            MachineName::default()
+        };
+        if harness && machine_name.is_generic() {
+            return Err(syn::Error::new(
+                machine_name.name().span(),
+                "the harness builds its machines itself, it can't have generic parameters",
+            ));
+        }
+
+        // Context = Machine<B>;
         let machine_context = MachineContext::parse(input)?;
 
-        // States {
-        //     S1 = S1,
-        //     S2 = S2,
-        //     S3 = S3,
-        //     S4 = S4,
-        //     S5 = S5
-        // }
-        let states = States::parse(input)?;
-
-        // Events {
-        //     EVENT1 = Event1,
-        //     EVENT2 = Event2
-        // }
-        let events = Events::parse(input)?;
-
-        // Transitions {
-        //     EVENT1 [
-        //         S1 => S2,
-        //         S1 => S3,
-        //     ],
-        //     EVENT2 [
-        //         S4 => S5,
-        //     ],
-        // }
-        let transitions = Transitions::parse(input)?;
+        // Error = MachineError;
+        let machine_error = if MachineError::peek(input) {
+            MachineError::parse(input)?
+        } else {
+            MachineError::default()
+        };
+
+        // Unhandled = Ignore;
+        let unhandled_policy = if UnhandledPolicy::peek(input) {
+            UnhandledPolicy::parse(input)?
+        } else {
+            UnhandledPolicy::default()
+        };
+
+        // Warn = [unused_events];
+        let lints = Lints::parse(input)?;
+
+        let (mut regions, events) = if Regions::peek(input) {
+            // Regions {
+            //     Power { ... },
+            //     Trigger { ... },
+            // }
+            let regions = Regions::parse(input)?;
+
+            // Events {
+            //     EVENT1 = Event1,
+            //     EVENT2 = Event2
+            // }
+            let events = Events::parse(input)?;
+
+            (regions.0, events)
+        } else {
+            // Initial = S1;
+            //
+            // States {
+            //     S1 = S1,
+            //     S2 = S2,
+            //     S3 = S3,
+            //     S4 = S4,
+            //     S5 = S5
+            // }
+            //
+            // Final { S5 }
+            let (initial, states, final_states) = Region::parse_states(input)?;
+
+            // Events {
+            //     EVENT1 = Event1,
+            //     EVENT2 = Event2
+            // }
+            let events = Events::parse(input)?;
+
+            // Transitions {
+            //     EVENT1 [
+            //         S1 => S2 if guard,
+            //         S1 => S3,
+            //     ],
+            //     EVENT2 [
+            //         S4 => S5,
+            //     ],
+            // }
+            let transitions = Transitions::parse(input)?;
+
+            // Defer {
+            //     S1: [EVENT2],
+            // }
+            let defer = if Defer::peek(input) {
+                Defer::parse(input)?
+            } else {
+                Defer::default()
+            };
+
+            // Invariants {
+            //     reachable(S5),
+            //     through(S1 => S5, S4),
+            // }
+            let invariants = if Invariants::peek(input) {
+                Invariants::parse(input)?
+            } else {
+                Invariants::default()
+            };
+
+            let region = Region {
+                name: None,
+                initial,
+                states,
+                final_states,
+                transitions,
+                defer,
+                invariants,
+            };
+            (vec![region], events)
+        };
+
+        validation::check_names(&regions, &events)?;
+        if queue.is_none() {
+            if let Some(deferral) = regions.iter().flat_map(|v| &v.defer.0).next() {
+                return Err(syn::Error::new(
+                    deferral.state_name.span(),
+                    "deferred events are kept in the queue, add #[fsm(queue = 8)]",
+                ));
+            }
+        }
+        for region in &mut regions {
+            region
+                .transitions
+                .expand(&region.states, &region.final_states)?;
+        }
+        let warnings = validation::check_definition(&regions, &events, lints)?;
+        validation::check_invariants(&regions)?;
+        if backend == Backend::Table {
+            validation::check_table(&regions)?;
+        }
 
         Ok(Machine {
+            backend,
+            harness,
+            queue,
+            snapshot,
+            passthrough,
+            machine_name,
             machine_context,
+            machine_error,
+            unhandled_policy,
             events,
-            states,
-            transitions,
+            regions,
+            warnings,
         })
     }
 }
 
 impl ToTokens for Machine {
     fn to_tokens(&self, tokens: &mut TokenStream) {
-        let states = &self.states;
-        let events = &self.events;
+        let warnings = self.warnings.iter().map(|v| v.to_warning_tokens());
+        tokens.extend(quote!(#( #warnings )*));
 
-        let machine_context_type = &self.machine_context.context_type();
+        let mut items = TokenStream::new();
+        match self.single_region() {
+            Some(region) => self.machine_to_tokens(region, &mut items),
+            None => self.regions_to_tokens(&self.regions, &mut items),
+        }
+
+        if self.harness {
+            items.extend(harness::harness(self));
+        }
+
+        tokens.extend(self.machine_name.to_module_tokens(
+            &self.passthrough.vis(),
+            &self.exports(),
+            items,
+        ));
+    }
+}
+
+impl Machine {
+    /// The only region of a machine without `Regions`.
+    pub fn single_region(&self) -> Option<&Region> {
+        match self.regions.as_slice() {
+            [region] if region.name.is_none() => Some(region),
+            _ => None,
+        }
+    }
+
+    /// The items a named machine re-exports with its name as a prefix, next
+    /// to `Machine` itself.
+    fn exports(&self) -> Vec<&'static str> {
+        let mut exports = vec!["State", "Event", "EventId"];
+        let timed = self
+            .regions
+            .iter()
+            .any(|v| !v.transitions.timeouts.is_empty());
+
+        if self.single_region().is_some() {
+            exports.extend(&[
+                "StateId",
+                "Transition",
+                "TransitionError",
+                "Observer",
+                "NoObserver",
+            ]);
+        } else {
+            exports.push("Outcome");
+            if timed {
+                exports.push("Timeouts");
+            }
+        }
+        if timed {
+            exports.push("Clock");
+        }
+        if self.queue.is_some() {
+            exports.extend(&["Queue", "EventQueue"]);
+        }
+        if self.snapshot {
+            exports.extend(&["Codec", "Snapshot"]);
+        }
+
+        exports
+    }
+
+    /// The clock the deadlines of `after` transitions are measured with, if
+    /// any region has one.
+    fn clock_to_tokens(&self) -> TokenStream {
+        if self
+            .regions
+            .iter()
+            .all(|v| v.transitions.timeouts.is_empty())
+        {
+            return quote!();
+        }
+
+        quote! {
+            /// The monotonic clock timing the `after` transitions, implemented
+            /// by the context, e.g. with SysTick on target or with a fake clock
+            /// in host tests.
+            pub trait Clock {
+                /// Milliseconds since some fixed point, never going backwards.
+                fn now(&self) -> u64;
+            }
+        }
+    }
+
+    /// The derives of the types generated to hold `State` and `Event`,
+    /// those of `traits` the user derived for both, for the `State` of
+    /// every region.
+    pub fn derives_of_both(&self, traits: &[&str]) -> TokenStream {
+        let event_derives = self.events.passthrough.derives();
+        let state_derives: Vec<_> = self
+            .regions
+            .iter()
+            .map(|v| v.states.passthrough.derives())
+            .collect();
+
+        let derived: Vec<_> = traits
+            .iter()
+            .filter(|v| event_derives.iter().any(|derived| derived == *v))
+            .filter(|v| {
+                state_derives
+                    .iter()
+                    .all(|derives| derives.iter().any(|derived| derived == *v))
+            })
+            .map(|v| format_ident!("{}", v))
+            .collect();
+
+        if derived.is_empty() {
+            quote!()
+        } else {
+            quote!(#[derive(#( #derived ),*)])
+        }
+    }
+
+    /// The derives of the `serde` feature, and `Codec` with its
+    /// implementation for `Event`, for a machine with `snapshot`.
+    fn codec_to_tokens(&self) -> (TokenStream, TokenStream) {
+        if !self.snapshot {
+            return (quote!(), quote!());
+        }
+
+        let codec = snapshot::codec();
+        let event_codec = snapshot::event_codec(&self.events);
+        (
+            snapshot::serde_derive(self.snapshot),
+            quote! {
+                #codec
 
-        let event_fn_impl = self.transitions.to_event_fn_tokens();
+                #event_codec
+            },
+        )
+    }
+
+    fn machine_to_tokens(&self, region: &Region, tokens: &mut TokenStream) {
+        let states = &region.states;
+        let events = &self.events;
 
+        let state_introspection = states.to_introspection_tokens();
+        let event_introspection = events.to_introspection_tokens();
+        let clock = self.clock_to_tokens();
+        let queue = self
+            .queue
+            .map(|v| queue::queue(v, &self.derives_of_both(&["Clone", "Copy", "Debug"])));
+        let (serde, codec) = self.codec_to_tokens();
+        let region_impl = self.region_to_tokens(region, ContextAccess::Field);
+
+        // the derives go first, onto `State`, `Event` and `StateId`
         tokens.extend(quote! {
             #[allow(non_snake_case)]
 
+            #serde
             #states
 
+            #serde
+            #events
+
+            #serde
+            #state_introspection
+
+            #event_introspection
+
+            #clock
+
+            #queue
+
+            #codec
+
+            #region_impl
+        });
+    }
+
+    /// Every region gets a module of its own with the same items a machine
+    /// without regions has, next to one `Machine` driving all of them.
+    fn regions_to_tokens(&self, regions: &[Region], tokens: &mut TokenStream) {
+        let events = &self.events;
+        let event_introspection = events.to_introspection_tokens();
+
+        let machine_context_type = &self.machine_context.context_type();
+        let machine_error_type = self.machine_error.error_type();
+        let asyncness = self.backend.asyncness();
+        let awaited = self.backend.awaited();
+
+        let generics = self.machine_name.generics();
+        let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+        let turbofish = self.machine_name.turbofish();
+        let new_bound = if self.machine_name.is_generic() {
+            quote!(where #machine_context_type: Default)
+        } else {
+            quote!()
+        };
+
+        let (serde, codec) = self.codec_to_tokens();
+
+        let modules = regions.iter().map(|region| {
+            let module = region.module_name();
+            let states = &region.states;
+            let state_introspection = states.to_introspection_tokens();
+            let region_impl = self.region_to_tokens(region, ContextAccess::Argument);
+
+            quote! {
+                pub mod #module {
+                    use super::*;
+
+                    #[allow(non_snake_case)]
+
+                    #serde
+                    #states
+
+                    #serde
+                    #state_introspection
+
+                    #region_impl
+                }
+            }
+        });
+        let fields: Vec<_> = regions.iter().map(|v| v.module_name()).collect();
+
+        // regions whose initial states have a delay start it from the
+        // context's clock
+        let new_args = regions.iter().map(|region| {
+            let starts_timers = region
+                .states
+                .entry_path(&region.initial)
+                .into_iter()
+                .flatten()
+                .any(|v| region.transitions.timeouts.contains_key(&v.state_name));
+            if starts_timers {
+                quote!(&context)
+            } else {
+                quote!()
+            }
+        });
+
+        let clock = self.clock_to_tokens();
+        let derives = self.derives_of_both(&["Clone", "Copy", "Debug", "PartialEq"]);
+        let timed_fields: Vec<_> = regions
+            .iter()
+            .filter(|v| !v.transitions.timeouts.is_empty())
+            .map(|v| v.module_name())
+            .collect();
+        let (timeouts, tick_fns) = if timed_fields.is_empty() {
+            (quote!(), quote!())
+        } else {
+            let timeouts = quote! {
+                /// What every region with `after` transitions did on a tick.
+                #derives
+                pub struct Timeouts<E> {
+                    #(
+                        pub #timed_fields:
+                            Option<Result<#timed_fields::Transition, #timed_fields::TransitionError<E>>>,
+                    )*
+                }
+            };
+            let tick_fns = quote! {
+                /// Take the `after` transition of every region whose delay is
+                /// up at `now`, in declaration order.
+                pub #asyncness fn tick(&mut self, now: u64) -> Timeouts<#machine_error_type> {
+                    Timeouts {
+                        #( #timed_fields: self.#timed_fields.tick(&mut self.context, now) #awaited, )*
+                    }
+                }
+
+                /// When `tick` has an `after` transition to take next in any
+                /// region.
+                pub fn next_deadline(&self) -> Option<u64> {
+                    [#( self.#timed_fields.next_deadline() ),*].iter().flatten().min().copied()
+                }
+            };
+            (timeouts, tick_fns)
+        };
+
+        let (queue, queue_fns) = match self.queue {
+            Some(capacity) => (
+                queue::queue(capacity, &self.derives_of_both(&["Clone", "Copy", "Debug"])),
+                queue::regions_fns(&fields, &machine_error_type, &turbofish, self.backend),
+            ),
+            None => (quote!(), quote!()),
+        };
+
+        let machine_attrs = self.passthrough.attrs();
+        let machine_vis = self.passthrough.vis();
+
+        let (snapshot, snapshot_fns) = if self.snapshot {
+            snapshot::regions_snapshot(regions, &serde, &derives)
+        } else {
+            (quote!(), quote!())
+        };
+
+        let dot = diagram::dot(self);
+        let mermaid = diagram::mermaid(self);
+        let plantuml = diagram::plantuml(self);
+
+        tokens.extend(quote! {
+            #serde
             #events
 
-            pub struct Machine {
+            #event_introspection
+
+            #clock
+
+            #queue
+
+            #codec
+
+            #( #modules )*
+
+            /// The current state of every region.
+            #derives
+            pub struct State {
+                #( pub #fields: #fields::State, )*
+            }
+
+            /// What every region did with an event.
+            #derives
+            pub struct Outcome<E> {
+                #( pub #fields: Result<#fields::Transition, #fields::TransitionError<E>>, )*
+            }
+
+            impl<E> Outcome<E> {
+                /// Whether no region returned an error.
+                pub fn is_ok(&self) -> bool {
+                    #( self.#fields.is_ok() )&&*
+                }
+            }
+
+            #timeouts
+
+            #snapshot
+
+            #machine_attrs
+            #machine_vis struct Machine #generics #where_clause {
                 context: #machine_context_type,
-                current_state: State,
+                #( #fields: #fields::Region #ty_generics, )*
             }
 
-            impl Machine {
-                pub #event_fn_impl
+            impl #impl_generics Machine #ty_generics #where_clause {
+                pub fn new() -> Self #new_bound {
+                    Machine::with_context(<#machine_context_type as Default>::default())
+                }
 
-                pub fn new() -> Machine {
+                pub fn with_context(context: #machine_context_type) -> Self {
                     Machine {
-                        context: #machine_context_type::default(),
-                        current_state: State::default(),
+                        #( #fields: #fields::Region::new(#new_args), )*
+                        context,
+                    }
+                }
+
+                /// The state diagram in Graphviz DOT.
+                pub const DOT: &'static str = #dot;
+
+                /// The state diagram as a Mermaid `stateDiagram-v2`.
+                pub const MERMAID: &'static str = #mermaid;
+
+                /// The state diagram in PlantUML.
+                pub const PLANTUML: &'static str = #plantuml;
+
+                /// Hand `event` to every region, in declaration order.
+                pub #asyncness fn event(&mut self, event: Event) -> Outcome<#machine_error_type> {
+                    Outcome {
+                        #( #fields: self.#fields.event(&mut self.context, event.clone()) #awaited, )*
                     }
                 }
 
+                pub fn context(&self) -> &#machine_context_type {
+                    &self.context
+                }
+
+                pub fn context_mut(&mut self) -> &mut #machine_context_type {
+                    &mut self.context
+                }
+
+                #(
+                    pub fn #fields(&self) -> &#fields::Region #ty_generics {
+                        &self.#fields
+                    }
+                )*
+
                 pub fn state(&self) -> State {
-                    self.current_state
+                    State {
+                        #( #fields: self.#fields.state(), )*
+                    }
                 }
+
+                /// Whether every region is in a final state it will never
+                /// leave.
+                pub fn is_finished(&self) -> bool {
+                    #( self.#fields.is_finished() )&&*
+                }
+
+                /// Whether `event` would take a transition in any region.
+                pub fn can_handle(&self, event: &Event) -> bool {
+                    #( self.#fields.can_handle(&self.context, event) )||*
+                }
+
+                #tick_fns
+
+                #queue_fns
+
+                #snapshot_fns
             }
         });
     }
+
+    /// What a region's states need: the result types of `event`, the
+    /// observer, and `Machine` itself or, with `Regions`, a `Region` that is
+    /// handed the context.
+    fn region_to_tokens(&self, region: &Region, access: ContextAccess) -> TokenStream {
+        let states = &region.states;
+        let derives = self.derives_of_both(&["Clone", "Copy", "Debug", "PartialEq"]);
+
+        let machine_context_type = &self.machine_context.context_type();
+
+        // a generic machine's parameters go before those of the observer
+        let generics = self.machine_name.generics();
+        let generic_params = generics.params.iter();
+        let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+        let impl_params = self.machine_name.impl_params();
+        let type_args = self.machine_name.type_args();
+        let turbofish = self.machine_name.turbofish();
+        let new_bound = if self.machine_name.is_generic() {
+            quote!(where #machine_context_type: Default)
+        } else {
+            quote!()
+        };
+
+        let machine_error_type = self.machine_error.error_type();
+
+        let superstates = states.superstates();
+        let superstate_fields: Vec<_> = superstates.iter().map(|v| v.field_name()).collect();
+        let superstate_types: Vec<_> = superstates.iter().map(|v| &v.state_type).collect();
+        let history_fields: Vec<_> = region
+            .transitions
+            .histories(states)
+            .iter()
+            .map(|v| v.history_field_name())
+            .collect();
+
+        let deadline_fields: Vec<_> = region
+            .transitions
+            .timed(states)
+            .iter()
+            .map(|v| v.deadline_field_name())
+            .collect();
+
+        let initial_path = states
+            .entry_path(&region.initial)
+            .expect("the initial state is validated while parsing");
+        let initial = initial_path[initial_path.len() - 1];
+        let initial_name = &initial.state_name;
+        let initial_type = &initial.state_type;
+
+        // the delays of the initial states start when the machine is built,
+        // a region is handed the context for that
+        let initial_deadlines: Vec<_> = region
+            .transitions
+            .timed(states)
+            .iter()
+            .map(|state| {
+                let timeout = &region.transitions.timeouts[&state.state_name];
+                let millis = Literal::u64_unsuffixed(timeout.millis);
+                if initial_path
+                    .iter()
+                    .any(|v| v.state_name == state.state_name)
+                {
+                    quote!(Some(now + #millis))
+                } else {
+                    quote!(None)
+                }
+            })
+            .collect();
+        let starts_timers = initial_path
+            .iter()
+            .any(|v| region.transitions.timeouts.contains_key(&v.state_name));
+        let (new_param, new_arg, now) = match (access, starts_timers) {
+            (_, false) => (quote!(), quote!(), quote!()),
+            (ContextAccess::Field, true) => {
+                (quote!(), quote!(), quote!(let now = Clock::now(&context);))
+            }
+            (ContextAccess::Argument, true) => (
+                quote!(context: &#machine_context_type,),
+                quote!(context,),
+                quote!(let now = Clock::now(context);),
+            ),
+        };
+
+        let final_states = &region.final_states.0;
+        let is_finished = if final_states.is_empty() {
+            quote!(false)
+        } else {
+            quote!(matches!(self.current_state, #( State::#final_states(_) )|*))
+        };
+
+        let (transitions_const, can_handle_fn) =
+            region
+                .transitions
+                .to_introspection_tokens(states, access, machine_context_type);
+
+        // only a machine with `after` transitions has a clock to follow
+        let timed_out_fn = if region.transitions.timeouts.is_empty() {
+            quote!()
+        } else {
+            quote! {
+                /// The delay of an `after` transition from `from` to `to` was
+                /// up, and `result` is what `Machine::tick` returns.
+                fn timed_out(
+                    &mut self,
+                    _from: &State,
+                    _to: StateId,
+                    _result: &Result<Transition, TransitionError<#machine_error_type>>,
+                ) {
+                }
+            }
+        };
+
+        let (table_module, event_fn_impl, can_handle_fn, tick_fns, table_fns) = match self.backend {
+            Backend::Match | Backend::Async => {
+                let event_fn_impl = region.transitions.to_event_fn_tokens(
+                    states,
+                    &machine_error_type,
+                    self.unhandled_policy,
+                    access,
+                    machine_context_type,
+                    self.backend,
+                );
+                let tick_fns = if region.transitions.timeouts.is_empty() {
+                    quote!()
+                } else {
+                    region.transitions.to_tick_fn_tokens(
+                        states,
+                        &machine_error_type,
+                        access,
+                        machine_context_type,
+                        self.backend,
+                    )
+                };
+                (quote!(), event_fn_impl, can_handle_fn, tick_fns, quote!())
+            }
+            Backend::Table => {
+                let table = region.transitions.to_table_tokens(
+                    states,
+                    &machine_error_type,
+                    self.unhandled_policy,
+                    access,
+                    machine_context_type,
+                );
+                (
+                    table.module,
+                    table.event_fn,
+                    table.can_handle_fn,
+                    table.tick_fns,
+                    table.helper_fns,
+                )
+            }
+        };
+
+        // only a machine with a queue defers events
+        let (defers_fn, queue_fns) = match (self.queue, access) {
+            (None, _) => (quote!(), quote!()),
+            (Some(_), ContextAccess::Field) => (
+                region.defer.to_defers_fn_tokens(region),
+                queue::machine_fns(&machine_error_type, &turbofish, self.backend),
+            ),
+            (Some(_), ContextAccess::Argument) => {
+                (region.defer.to_defers_fn_tokens(region), quote!())
+            }
+        };
+
+        let (snapshot, snapshot_fns) = if self.snapshot {
+            (
+                snapshot::region_snapshot(region, &snapshot::serde_derive(self.snapshot), &derives),
+                snapshot::region_snapshot_fns(region, access, machine_context_type),
+            )
+        } else {
+            (quote!(), quote!())
+        };
+
+        // a machine owns its context and has the diagrams, a region of one
+        // has neither
+        let (name, context_field, constructors, context_fns) = match access {
+            ContextAccess::Field => {
+                let dot = diagram::dot(self);
+                let mermaid = diagram::mermaid(self);
+                let plantuml = diagram::plantuml(self);
+
+                let constructors = quote! {
+                    pub fn new() -> Self #new_bound {
+                        Machine::with_context(<#machine_context_type as Default>::default())
+                    }
+
+                    pub fn with_context(context: #machine_context_type) -> Self {
+                        Machine::with_observer(context, NoObserver)
+                    }
+
+                    #transitions_const
+
+                    /// The state diagram in Graphviz DOT.
+                    pub const DOT: &'static str = #dot;
+
+                    /// The state diagram as a Mermaid `stateDiagram-v2`.
+                    pub const MERMAID: &'static str = #mermaid;
+
+                    /// The state diagram in PlantUML.
+                    pub const PLANTUML: &'static str = #plantuml;
+                };
+                let context_fns = quote! {
+                    pub fn context(&self) -> &#machine_context_type {
+                        &self.context
+                    }
+
+                    pub fn context_mut(&mut self) -> &mut #machine_context_type {
+                        &mut self.context
+                    }
+                };
+                (
+                    quote!(Machine),
+                    quote!(context: #machine_context_type,),
+                    constructors,
+                    context_fns,
+                )
+            }
+            ContextAccess::Argument => {
+                let constructors = quote! {
+                    pub fn new(#new_param) -> Self {
+                        Region::with_observer(#new_arg NoObserver)
+                    }
+
+                    #transitions_const
+                };
+                (quote!(Region), quote!(), constructors, quote!())
+            }
+        };
+        // the region of a generic machine uses its parameters only in the
+        // context it is handed
+        let (phantom_field, phantom_init) = match access {
+            ContextAccess::Argument if self.machine_name.is_generic() => (
+                quote!(context: core::marker::PhantomData<fn() -> #machine_context_type>,),
+                quote!(context: core::marker::PhantomData,),
+            ),
+            _ => (quote!(), quote!()),
+        };
+        let (machine_attrs, machine_vis) = match access {
+            ContextAccess::Field => (self.passthrough.attrs(), self.passthrough.vis()),
+            ContextAccess::Argument => (quote!(), quote!(pub)),
+        };
+        let context_init = match access {
+            ContextAccess::Field => quote!(context,),
+            ContextAccess::Argument => quote!(),
+        };
+
+        quote! {
+            #table_module
+
+            #derives
+            pub enum TransitionError<E> {
+                /// Every guard of the transitions out of `state` for `event` failed.
+                NoGuardPassed { state: State, event: Event },
+                /// `state` has no transition for `event`.
+                Unhandled { state: State, event: Event },
+                /// An `on`, `exit` or `entry` callback returned an error.
+                Callback(E),
+            }
+
+            /// What `Machine::event` did with an event.
+            #derives
+            pub enum Transition {
+                /// The machine left `from` and entered `to`.
+                Taken { from: State, to: State },
+                /// An internal transition ran the event's action without
+                /// leaving `state`.
+                Internal { state: State },
+                /// `state` has no transition for `event` and the machine
+                /// ignores unhandled events.
+                Ignored { state: State, event: Event },
+            }
+
+            /// Notified of every transition `Machine::event` takes or rejects,
+            /// e.g. to trace them. Every method does nothing by default.
+            pub trait Observer {
+                /// `event` selected a transition from `from` to `to`, no
+                /// callback has run yet.
+                fn before_transition(&mut self, _from: &State, _event: &Event, _to: StateId) {}
+
+                /// The transition finished and `result` is what `Machine::event`
+                /// returns. If an `on` or `exit` callback failed the machine is
+                /// still in `from`.
+                fn after_transition(
+                    &mut self,
+                    _from: &State,
+                    _event: &Event,
+                    _to: StateId,
+                    _result: &Result<Transition, TransitionError<#machine_error_type>>,
+                ) {
+                }
+
+                /// `event` didn't select any transition out of `state`.
+                fn rejected(
+                    &mut self,
+                    _state: &State,
+                    _event: &Event,
+                    _result: &Result<Transition, TransitionError<#machine_error_type>>,
+                ) {
+                }
+
+                #timed_out_fn
+            }
+
+            /// The observer of machines built without one, which compiles
+            /// away entirely.
+            #[derive(Clone, Copy, Debug, Default)]
+            pub struct NoObserver;
+
+            impl Observer for NoObserver {}
+
+            #snapshot
+
+            #machine_attrs
+            #machine_vis struct #name<#( #generic_params, )* O: Observer = NoObserver> #where_clause {
+                #context_field
+                #phantom_field
+                current_state: State,
+                #( #superstate_fields: #superstate_types, )*
+                #( #history_fields: Option<StateId>, )*
+                #( #deadline_fields: Option<u64>, )*
+                observer: O,
+            }
+
+            impl #impl_generics #name #ty_generics #where_clause {
+                #constructors
+
+                #defers_fn
+            }
+
+            impl<#impl_params O: Observer> #name<#type_args O> #where_clause {
+                pub #event_fn_impl
+
+                pub fn with_observer(#context_field #new_param observer: O) -> Self {
+                    #now
+                    #name {
+                        #context_init
+                        #phantom_init
+                        current_state: State::#initial_name(<#initial_type as Default>::default()),
+                        #( #superstate_fields: <#superstate_types as Default>::default(), )*
+                        #( #history_fields: None, )*
+                        #( #deadline_fields: #initial_deadlines, )*
+                        observer,
+                    }
+                }
+
+                #context_fns
+
+                pub fn observer(&self) -> &O {
+                    &self.observer
+                }
+
+                pub fn observer_mut(&mut self) -> &mut O {
+                    &mut self.observer
+                }
+
+                pub fn state(&self) -> State {
+                    self.current_state.clone()
+                }
+
+                /// Whether the machine is in a final state it will never
+                /// leave.
+                pub fn is_finished(&self) -> bool {
+                    #is_finished
+                }
+
+                #can_handle_fn
+
+                #tick_fns
+
+                #table_fns
+
+                #queue_fns
+
+                #snapshot_fns
+
+                /// The events the current state has a transition for, whether
+                /// or not their guards would pass.
+                pub fn permitted_events(&self) -> impl Iterator<Item = EventId> {
+                    let state = self.current_state.id();
+                    #name #turbofish ::TRANSITIONS
+                        .iter()
+                        .filter(move |(from, _, _)| *from == state)
+                        .map(|(_, event, _)| *event)
+                }
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use proc_macro2::TokenStream;
-    use syn::{self, parse_quote, ItemEnum, Visibility};
 
     #[test]
     fn test_machine_parse_and_to_tokens() {
         let machine: Machine = syn::parse2(quote! {
-            Context = FireControl;
+            Context = Door;
 
+            Error = DoorError;
+
+            #[derive(Clone, Debug, PartialEq)]
             States {
-                PowerON = PowerON,
-                POSTError = POSTError,
-                Ready = Ready,
-                BatteryVoltageLow = BatteryVoltageLow,
-                Overcurrent = Overcurrent,
-                Preloading = Preloading,
-                Safe = Safe,
-                HalfAutoNFire = HalfAutoNFire,
-                FullAutoFire = FullAutoFire,
+                Open = Open,
+                Close = Close,
             }
 
+            #[derive(Clone, Copy, Debug, PartialEq)]
             Events {
-                POST = Post,
-                BatteryVoltageChange = BatteryVoltageChange,
-                SystemCurrentChange = SystemCurrentChange,
-                PullHalfTrigger = PullHalfTrigger,
-                PullFullTrigger = PullFullTrigger,
-                ReleaseTrigger = ReleaseTrigger,
+                Turn = Turn,
             }
 
             Transitions {
-                POST [
-                    PowerON => Ready,
-                    PowerON => POSTError,
-                ],
-                BatteryVoltageChange [
-                    Ready => BatteryVoltageLow,
-                    Safe => BatteryVoltageLow,
-                    Preloading => BatteryVoltageLow,
-                    HalfAutoNFire => BatteryVoltageLow,
-                    FullAutoFire => BatteryVoltageLow,
-                ],
-                SystemCurrentChange [
-                    Preloading => Overcurrent,
-                    HalfAutoNFire => Overcurrent,
-                    FullAutoFire => Overcurrent,
-                ],
-                PullHalfTrigger [
-                    Ready => Preloading,
-                ],
-                PullFullTrigger [
-                    Preloading => Safe,
-                    Preloading => HalfAutoNFire,
-                    Preloading => FullAutoFire,
-                ],
-                ReleaseTrigger [
-                    Safe => Ready,
-                    HalfAutoNFire => Ready,
-                    FullAutoFire => Ready,
+                Turn [
+                    Open => Close,
+                    Close => Open if unlocked,
                 ],
             }
         })
         .unwrap();
 
+        // the diagrams themselves are checked in `diagram`
+        let dot = diagram::dot(&machine);
+        let mermaid = diagram::mermaid(&machine);
+        let plantuml = diagram::plantuml(&machine);
+
+        // and so are the state and event queries
+        let state_introspection = machine.regions[0].states.to_introspection_tokens();
+        let event_introspection = machine.events.to_introspection_tokens();
+
         let left = quote! {
             #[allow(non_snake_case)]
-            #[derive(Clone, Copy, Debug)]
+            #[derive(Clone, Debug, PartialEq)]
             pub enum State {
-                Open,
-                Close,
+                Open(Open),
+                Close(Close)
             }
-            const INIT_STATE: State = State::Open;
-            #[derive(Clone, Copy, Debug)]
+            #[derive(Clone, Copy, Debug, PartialEq)]
             pub enum Event {
-                Turn,
+                Turn(Turn)
             }
-            pub struct Machine {
-                current_state: State,
+            #state_introspection
+            #event_introspection
+            // only what both `State` and `Event` derive
+            #[derive(Clone, Debug, PartialEq)]
+            pub enum TransitionError<E> {
+                /// Every guard of the transitions out of `state` for `event` failed.
+                NoGuardPassed { state: State, event: Event },
+                /// `state` has no transition for `event`.
+                Unhandled { state: State, event: Event },
+                /// An `on`, `exit` or `entry` callback returned an error.
+                Callback(E),
             }
-            impl Machine {
-                pub fn state(&self) -> State {
-                    self.current_state
-                }
+            /// What `Machine::event` did with an event.
+            #[derive(Clone, Debug, PartialEq)]
+            pub enum Transition {
+                /// The machine left `from` and entered `to`.
+                Taken { from: State, to: State },
+                /// An internal transition ran the event's action without
+                /// leaving `state`.
+                Internal { state: State },
+                /// `state` has no transition for `event` and the machine
+                /// ignores unhandled events.
+                Ignored { state: State, event: Event },
             }
-            mod turn {
-                pub enum AfterExitClose {
-                    Close,
-                    Open,
-                }
-                pub enum AfterExitOpen {
-                    Close,
+            /// Notified of every transition `Machine::event` takes or rejects,
+            /// e.g. to trace them. Every method does nothing by default.
+            pub trait Observer {
+                /// `event` selected a transition from `from` to `to`, no
+                /// callback has run yet.
+                fn before_transition(&mut self, _from: &State, _event: &Event, _to: StateId) {}
+                /// The transition finished and `result` is what `Machine::event`
+                /// returns. If an `on` or `exit` callback failed the machine is
+                /// still in `from`.
+                fn after_transition(
+                    &mut self,
+                    _from: &State,
+                    _event: &Event,
+                    _to: StateId,
+                    _result: &Result<Transition, TransitionError<DoorError>>,
+                ) {
                 }
-                pub trait Callback {
-                    fn on_turn(&self, data: (&str)) -> Result<(), &'static str>;
-                    fn exit_close(&self, data: (&str)) -> Result<AfterExitClose, &'static str>;
-                    fn entry_close_from_close(&self, data: (&str));
-                    fn entry_open_from_close(&self, data: (&str));
-                    fn exit_open(&self, data: (&str)) -> Result<AfterExitOpen, &'static str>;
-                    fn entry_close_from_open(&self, data: (&str));
+                /// `event` didn't select any transition out of `state`.
+                fn rejected(
+                    &mut self,
+                    _state: &State,
+                    _event: &Event,
+                    _result: &Result<Transition, TransitionError<DoorError>>,
+                ) {
                 }
             }
+            /// The observer of machines built without one, which compiles
+            /// away entirely.
+            #[derive(Clone, Copy, Debug, Default)]
+            pub struct NoObserver;
+            impl Observer for NoObserver {}
+            pub struct Machine<O: Observer = NoObserver> {
+                context: Door,
+                current_state: State,
+                observer: O,
+            }
             impl Machine {
-                fn event(&mut self, event: Event) -> Result<bool, &'static str> {
-                    match event {
-                        Event::Turn => {
-                            if let Err(err) = self.on_turn() {
-                                return Err(err);
-                            }
-                            match self.current_state {
-                                State::Close => {
-                                    match self.exit_close() {
-                                        Ok(r) => {
-                                            match r {
-                                                AfterExitClose::Close => {
-                                                    self.current_state = State::Close;
-                                                    self.entry_close_from_close();
-                                                    Ok(true)
-                                                }
-                                                AfterExitClose::Open => {
-                                                    self.current_state = State::Open;
-                                                    self.entry_open_from_close();
-                                                    Ok(true)
-                                                }
-                                            }
+                pub fn new() -> Self {
+                    Machine::with_context(<Door as Default>::default())
+                }
+                pub fn with_context(context: Door) -> Self {
+                    Machine::with_observer(context, NoObserver)
+                }
+                /// For every state and event with a transition, the states the
+                /// machine may end up in, guarded targets first.
+                pub const TRANSITIONS: &'static [(StateId, EventId, &'static [StateId])] = &[
+                    (StateId::Open, EventId::Turn, &[StateId::Close]),
+                    (StateId::Close, EventId::Turn, &[StateId::Open])
+                ];
+                /// The state diagram in Graphviz DOT.
+                pub const DOT: &'static str = #dot;
+                /// The state diagram as a Mermaid `stateDiagram-v2`.
+                pub const MERMAID: &'static str = #mermaid;
+                /// The state diagram in PlantUML.
+                pub const PLANTUML: &'static str = #plantuml;
+            }
+            impl<O: Observer> Machine<O> {
+                pub fn event(&mut self, event: Event) -> Result<Transition, TransitionError<DoorError>> {
+                    let result = match &event {
+                        Event::Turn(data) => {
+                            match &self.current_state {
+                                State::Open(state) => {
+                                    {
+                                        let from = self.current_state.clone();
+                                        self.observer.before_transition(&from, &event, StateId::Close);
+                                        let mut result = data.on(&mut self.context);
+                                        if result.is_ok() {
+                                            result = state.exit(&mut self.context, Some(&event));
                                         }
-                                        Err(err) => {
-                                            Err(err)
+                                        if result.is_ok() {
+                                            let mut next = <Close as Default>::default();
+                                            result = next.entry(&mut self.context, Some(&event));
+                                            self.current_state = State::Close(next);
                                         }
+                                        let result = result
+                                            .map(|_| Transition::Taken {
+                                                from: from.clone(),
+                                                to: self.current_state.clone(),
+                                            })
+                                            .map_err(TransitionError::Callback);
+                                        self.observer.after_transition(&from, &event, StateId::Close, &result);
+                                        result
                                     }
                                 }
-                                State::Open => {
-                                    match self.exit_open() {
-                                        Ok(r) => {
-                                            match r {
-                                                AfterExitOpen::Close => {
-                                                    self.current_state = State::Close;
-                                                    self.entry_close_from_open();
-                                                    Ok(true)
-                                                }
-                                            }
+                                State::Close(state) => {
+                                    if self.context.unlocked(data) {
+                                        let from = self.current_state.clone();
+                                        self.observer.before_transition(&from, &event, StateId::Open);
+                                        let mut result = data.on(&mut self.context);
+                                        if result.is_ok() {
+                                            result = state.exit(&mut self.context, Some(&event));
                                         }
-                                        Err(err) => {
-                                            Err(err)
+                                        if result.is_ok() {
+                                            let mut next = <Open as Default>::default();
+                                            result = next.entry(&mut self.context, Some(&event));
+                                            self.current_state = State::Open(next);
                                         }
+                                        let result = result
+                                            .map(|_| Transition::Taken {
+                                                from: from.clone(),
+                                                to: self.current_state.clone(),
+                                            })
+                                            .map_err(TransitionError::Callback);
+                                        self.observer.after_transition(&from, &event, StateId::Open, &result);
+                                        result
+                                    } else {
+                                        Err(TransitionError::NoGuardPassed {
+                                            state: self.current_state.clone(),
+                                            event: event.clone(),
+                                        })
                                     }
                                 }
+                                #[allow(unreachable_patterns)]
+                                _ => {
+                                    Err(TransitionError::Unhandled {
+                                        state: self.current_state.clone(),
+                                        event: event.clone(),
+                                    })
+                                }
                             }
                         }
+                        #[allow(unreachable_patterns)]
+                        _ => {
+                            Err(TransitionError::Unhandled {
+                                state: self.current_state.clone(),
+                                event: event.clone(),
+                            })
+                        }
+                    };
+                    if let Ok(Transition::Ignored { .. })
+                    | Err(TransitionError::NoGuardPassed { .. })
+                    | Err(TransitionError::Unhandled { .. }) = &result
+                    {
+                        self.observer.rejected(&self.current_state, &event, &result);
                     }
+                    result
                 }
-            }
-            pub fn new() -> Machine {
-                Machine {
-                    current_state: INIT_STATE,
+                pub fn with_observer(context: Door, observer: O) -> Self {
+                    Machine {
+                        context,
+                        current_state: State::Open(<Open as Default>::default()),
+                        observer,
+                    }
+                }
+                pub fn context(&self) -> &Door {
+                    &self.context
+                }
+                pub fn context_mut(&mut self) -> &mut Door {
+                    &mut self.context
+                }
+                pub fn observer(&self) -> &O {
+                    &self.observer
+                }
+                pub fn observer_mut(&mut self) -> &mut O {
+                    &mut self.observer
+                }
+                pub fn state(&self) -> State {
+                    self.current_state.clone()
+                }
+                /// Whether the machine is in a final state it will never
+                /// leave.
+                pub fn is_finished(&self) -> bool {
+                    false
+                }
+                /// Whether `event` would take a transition from the current state.
+                pub fn can_handle(&self, event: &Event) -> bool {
+                    match event {
+                        Event::Turn(data) => match &self.current_state {
+                            State::Open(_) => true,
+                            State::Close(_) => self.context.unlocked(data),
+                            #[allow(unreachable_patterns)]
+                            _ => false,
+                        },
+                        #[allow(unreachable_patterns)]
+                        _ => false,
+                    }
+                }
+                /// The events the current state has a transition for, whether
+                /// or not their guards would pass.
+                pub fn permitted_events(&self) -> impl Iterator<Item = EventId> {
+                    let state = self.current_state.id();
+                    Machine::TRANSITIONS
+                        .iter()
+                        .filter(move |(from, _, _)| *from == state)
+                        .map(|(_, event, _)| *event)
                 }
             }
         };
@@ -290,4 +1366,167 @@ mod tests {
 
         assert_eq!(format!("{}", left), format!("{}", right));
     }
+
+    #[test]
+    fn test_defer_needs_queue() {
+        let result: Result<Machine> = syn::parse2(quote! {
+            Context = Door;
+
+            States {
+                Open = Open,
+                Close = Close,
+            }
+
+            Events {
+                Turn = Turn,
+                Lock = Lock,
+            }
+
+            Transitions {
+                Turn [
+                    Open => Close,
+                    Close => Open,
+                ],
+                Lock [
+                    Close -> internal,
+                ],
+            }
+
+            Defer {
+                Open: [Lock],
+            }
+        });
+
+        assert_eq!(
+            result.err().map(|v| v.to_string()),
+            Some("deferred events are kept in the queue, add #[fsm(queue = 8)]".to_string())
+        );
+    }
+
+    #[test]
+    fn test_named_machine_to_tokens() {
+        let machine: Machine = syn::parse2(quote! {
+            Name = DoorFsm<L: Lock>;
+
+            Context = Door<L>;
+
+            States {
+                Open = Open,
+                Close = Close,
+            }
+
+            Events {
+                Turn = Turn,
+            }
+
+            Transitions {
+                Turn [
+                    Open => Close,
+                    Close => Open,
+                ],
+            }
+        })
+        .unwrap();
+
+        let tokens = quote!(#machine).to_string();
+        assert!(tokens.contains(&quote!(pub mod door_fsm).to_string()));
+        assert!(tokens
+            .contains(&quote!(pub struct Machine<L: Lock, O: Observer = NoObserver>).to_string()));
+        assert!(tokens.contains(&quote!(impl<L: Lock> Machine<L>).to_string()));
+        assert!(tokens.contains(&quote!(impl<L: Lock, O: Observer> Machine<L, O>).to_string()));
+        assert!(tokens.contains(&quote!(Machine::<L>::TRANSITIONS).to_string()));
+        assert!(tokens.contains(&quote!(Machine as DoorFsm, State as DoorFsmState,).to_string()));
+
+        let result: Result<Machine> = syn::parse2(quote! {
+            #[fsm(harness)]
+
+            Name = DoorFsm<L: Lock>;
+
+            Context = Door<L>;
+
+            States {
+                Open = Open,
+            }
+
+            Events {
+                Turn = Turn,
+            }
+
+            Transitions {
+                Turn [
+                    Open => Open,
+                ],
+            }
+        });
+
+        assert_eq!(
+            result.err().map(|v| v.to_string()),
+            Some(
+                "the harness builds its machines itself, it can't have generic parameters"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_async_backend_to_tokens() {
+        let machine: Machine = syn::parse2(quote! {
+            #[fsm(backend = "async")]
+
+            Context = Door;
+
+            States {
+                Open = Open,
+                Close = Close,
+            }
+
+            Events {
+                Turn = Turn,
+            }
+
+            Transitions {
+                Turn [
+                    Open => Close,
+                    Close => Open,
+                ],
+            }
+        })
+        .unwrap();
+
+        let tokens = quote!(#machine).to_string();
+        assert!(tokens.contains(&quote!(pub async fn event(&mut self, event: Event)).to_string()));
+        assert!(tokens.contains(&quote!(data.on(&mut self.context).await;).to_string()));
+        assert!(tokens
+            .contains(&quote!(state.exit(&mut self.context, Some(&event)).await;).to_string()));
+        assert!(tokens
+            .contains(&quote!(next.entry(&mut self.context, Some(&event)).await;).to_string()));
+
+        let result: Result<Machine> = syn::parse2(quote! {
+            #[fsm(backend = "async", harness)]
+
+            Context = Door;
+
+            States {
+                Open = Open,
+            }
+
+            Events {
+                Turn = Turn,
+            }
+
+            Transitions {
+                Turn [
+                    Open => Open,
+                ],
+            }
+        });
+
+        assert_eq!(
+            result.err().map(|v| v.to_string()),
+            Some(
+                "the harness drives its machines synchronously, it can't with the async backend"
+                    .to_string()
+            )
+        );
+    }
 }
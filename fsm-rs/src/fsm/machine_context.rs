@@ -1,6 +1,8 @@
+use proc_macro2::TokenStream;
+use quote::quote;
 use syn::{
     parse::{Parse, ParseStream, Result},
-    Ident, Token, Type
+    Ident, Token, Type,
 };
 
 #[derive(Clone, Debug, PartialEq)]
@@ -45,18 +47,43 @@ impl MachineContext {
     }
 }
 
+/// How the generated code reaches the context: a `Machine` owns it, while
+/// each of its `Regions` is handed it by the `Machine` on every call.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ContextAccess {
+    Field,
+    Argument,
+}
+
+impl ContextAccess {
+    /// The context as a place expression, e.g. for `&mut #context`.
+    pub fn context(self) -> TokenStream {
+        match self {
+            ContextAccess::Field => quote!(self.context),
+            ContextAccess::Argument => quote!((*context)),
+        }
+    }
+
+    /// The parameter passing the context in, if any, with a trailing comma.
+    pub fn param(self, context_type: &Type, mutable: bool) -> TokenStream {
+        match (self, mutable) {
+            (ContextAccess::Field, _) => quote!(),
+            (ContextAccess::Argument, true) => quote!(context: &mut #context_type,),
+            (ContextAccess::Argument, false) => quote!(context: &#context_type,),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use quote::quote;
-    use syn::{parse2, parse_quote};
+    use syn::parse2;
 
     #[test]
-    fn test_initial_state_parse() {
+    fn test_machine_context_parse() {
         let _: MachineContext = parse2(quote! {
-            pub struct FSM {
-                str: String
-            }
+            Context = FSM;
         })
         .unwrap();
     }
@@ -0,0 +1,82 @@
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
+use syn::{
+    parse::{Parse, ParseStream, Result},
+    Ident, Token, Type,
+};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct MachineError {
+    error_type: Option<Type>,
+}
+
+impl Parse for MachineError {
+    /// example machine error:
+    ///
+    /// ```text
+    /// Error = MachineError;
+    /// ```
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        // Error = MachineError;
+        // _____
+        let error_magic: Ident = Ident::parse(input)?;
+
+        if error_magic != "Error" {
+            return Err(input.error("expected Error = <type name>"));
+        }
+
+        // Error = MachineError;
+        //       _
+        let _: Token![=] = input.parse()?;
+
+        // Error = MachineError;
+        //         ____________
+        let error_type: Type = Type::parse(input)?;
+
+        // Error = MachineError;
+        //                     _
+        let _: Token![;] = input.parse()?;
+
+        Ok(MachineError {
+            error_type: Some(error_type),
+        })
+    }
+}
+
+impl MachineError {
+    pub fn peek(input: ParseStream<'_>) -> bool {
+        matches!(input.fork().parse::<Ident>(), Ok(v) if v == "Error")
+    }
+
+    /// Machines without an `Error = ...;` declaration keep using string
+    /// errors in their callbacks.
+    pub fn error_type(&self) -> TokenStream {
+        match &self.error_type {
+            Some(error_type) => error_type.to_token_stream(),
+            None => quote!(&'static str),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse2;
+
+    #[test]
+    fn test_machine_error_parse() {
+        let error: MachineError = parse2(quote! {
+            Error = FireControlError;
+        })
+        .unwrap();
+
+        assert_eq!(
+            format!("{}", error.error_type()),
+            format!("{}", quote!(FireControlError))
+        );
+        assert_eq!(
+            format!("{}", MachineError::default().error_type()),
+            format!("{}", quote!(&'static str))
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use heck::SnakeCase;
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use syn::{
+    parse::{Parse, ParseStream, Result},
+    GenericParam, Generics, Ident, Token, WhereClause,
+};
+
+/// What the machine is called and the generic parameters it takes, e.g. the
+/// board it runs on. A machine called anything but `Machine` gets its items
+/// in a module of its own, re-exported with its name as a prefix, so one
+/// module can hold several machines.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct MachineName {
+    name: Ident,
+    generics: Generics,
+}
+
+impl Parse for MachineName {
+    /// example machine name:
+    ///
+    /// ```text
+    /// Name = TriggerFsm<B: Board>;
+    /// ```
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        // Name = TriggerFsm<B: Board>;
+        // ____
+        let name_magic: Ident = Ident::parse(input)?;
+
+        if name_magic != "Name" {
+            return Err(input.error("expected Name = <machine name>"));
+        }
+
+        // Name = TriggerFsm<B: Board>;
+        //      _
+        let _: Token![=] = input.parse()?;
+
+        // Name = TriggerFsm<B: Board>;
+        //        __________
+        let name: Ident = Ident::parse(input)?;
+
+        // Name = TriggerFsm<B: Board>;
+        //                  __________
+        let mut generics: Generics = input.parse()?;
+
+        // Name = TriggerFsm<B> where B: Board;
+        //                      ______________
+        generics.where_clause = input.parse::<Option<WhereClause>>()?;
+
+        // Name = TriggerFsm<B: Board>;
+        //                            _
+        let _: Token![;] = input.parse()?;
+
+        Ok(MachineName { name, generics })
+    }
+}
+
+impl Default for MachineName {
+    fn default() -> Self {
+        MachineName {
+            name: Ident::new("Machine", Span::call_site()),
+            generics: Generics::default(),
+        }
+    }
+}
+
+impl MachineName {
+    pub fn peek(input: ParseStream<'_>) -> bool {
+        matches!(input.fork().parse::<Ident>(), Ok(v) if v == "Name")
+    }
+
+    pub fn name(&self) -> &Ident {
+        &self.name
+    }
+
+    pub fn generics(&self) -> &Generics {
+        &self.generics
+    }
+
+    pub fn is_generic(&self) -> bool {
+        !self.generics.params.is_empty()
+    }
+
+    /// The module the items go in, `None` for a machine called `Machine`.
+    pub fn module_name(&self) -> Option<Ident> {
+        if self.name == "Machine" {
+            return None;
+        }
+
+        Some(Ident::new(
+            &self.name.to_string().to_snake_case(),
+            self.name.span(),
+        ))
+    }
+
+    /// The generic parameters as declared by an `impl`, without defaults,
+    /// each with a trailing comma to go before `O: Observer`.
+    pub fn impl_params(&self) -> TokenStream {
+        let params = self.generics.params.iter().map(|v| match v {
+            GenericParam::Type(param) => {
+                let ident = &param.ident;
+                let bounds = &param.bounds;
+                if bounds.is_empty() {
+                    quote!(#ident)
+                } else {
+                    quote!(#ident: #bounds)
+                }
+            }
+            GenericParam::Const(param) => {
+                let ident = &param.ident;
+                let ty = &param.ty;
+                quote!(const #ident: #ty)
+            }
+            GenericParam::Lifetime(param) => quote!(#param),
+        });
+
+        quote!(#( #params, )*)
+    }
+
+    /// The generic parameters as passed to the type, each with a trailing
+    /// comma to go before `O`.
+    pub fn type_args(&self) -> TokenStream {
+        let args = self.generics.params.iter().map(|v| match v {
+            GenericParam::Type(param) => {
+                let ident = &param.ident;
+                quote!(#ident)
+            }
+            GenericParam::Const(param) => {
+                let ident = &param.ident;
+                quote!(#ident)
+            }
+            GenericParam::Lifetime(param) => {
+                let lifetime = &param.lifetime;
+                quote!(#lifetime)
+            }
+        });
+
+        quote!(#( #args, )*)
+    }
+
+    /// The turbofish naming the machine's own impl in expressions, e.g.
+    /// `Machine::<B>::TRANSITIONS`, where the parameters can't be inferred.
+    pub fn turbofish(&self) -> TokenStream {
+        if self.is_generic() {
+            let (_, ty_generics, _) = self.generics.split_for_impl();
+            let turbofish = ty_generics.as_turbofish();
+            quote!(#turbofish)
+        } else {
+            quote!()
+        }
+    }
+
+    /// Put `items` in the machine's module, and `exports` next to it with
+    /// the name as a prefix and `Machine` as the name itself.
+    pub fn to_module_tokens(
+        &self,
+        vis: &TokenStream,
+        exports: &[&str],
+        items: TokenStream,
+    ) -> TokenStream {
+        let module = match self.module_name() {
+            Some(module) => module,
+            None => return items,
+        };
+
+        let name = &self.name;
+        let exported = exports.iter().map(|v| format_ident!("{}", v));
+        let aliases = exports.iter().map(|v| format_ident!("{}{}", name, v));
+
+        quote! {
+            #vis mod #module {
+                use super::*;
+
+                #items
+            }
+
+            #vis use self::#module::{
+                Machine as #name,
+                #( #exported as #aliases, )*
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse2;
+
+    #[test]
+    fn test_machine_name_parse() {
+        let machine_name: MachineName = parse2(quote! {
+            Name = TriggerFsm<'a, B: Board + 'a, const N: usize = 4> where B: Clone;
+        })
+        .unwrap();
+
+        assert_eq!(machine_name.name(), "TriggerFsm");
+        assert_eq!(
+            machine_name.module_name().unwrap().to_string(),
+            "trigger_fsm"
+        );
+        assert_eq!(
+            format!("{}", machine_name.impl_params()),
+            format!("{}", quote!('a, B: Board + 'a, const N: usize,))
+        );
+        assert_eq!(
+            format!("{}", machine_name.type_args()),
+            format!("{}", quote!('a, B, N,))
+        );
+        assert_eq!(
+            format!("{}", machine_name.turbofish()),
+            format!("{}", quote!(::<'a, B, N>))
+        );
+        assert!(machine_name.generics().where_clause.is_some());
+
+        let machine_name = MachineName::default();
+
+        assert_eq!(machine_name.module_name(), None);
+        assert!(!machine_name.is_generic());
+        assert_eq!(format!("{}", machine_name.turbofish()), "");
+        assert_eq!(
+            format!(
+                "{}",
+                machine_name.to_module_tokens(&quote!(pub), &[], quote!(A))
+            ),
+            "A"
+        );
+    }
+}
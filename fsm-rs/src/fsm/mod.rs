@@ -1,5 +1,23 @@
+pub mod attributes;
+pub mod backend;
+pub mod defer;
+pub mod diagram;
 pub mod events;
+pub mod final_states;
+pub mod harness;
+pub mod initial_state;
+pub mod invariants;
+pub mod lints;
 pub mod machine;
 pub mod machine_context;
+pub mod machine_error;
+pub mod machine_name;
+pub mod passthrough;
+pub mod queue;
+pub mod region;
+pub mod snapshot;
 pub mod states;
+pub mod table;
 pub mod transitions;
+pub mod unhandled_policy;
+pub mod validation;
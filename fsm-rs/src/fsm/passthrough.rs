@@ -0,0 +1,113 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{
+    parse::{Parse, ParseStream, Result},
+    Attribute, Ident, Meta, NestedMeta, Visibility,
+};
+
+/// The outer attributes, doc comments included, and the visibility written
+/// in front of a section, which go on the item generated for it.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Passthrough {
+    pub attrs: Vec<Attribute>,
+    pub vis: Visibility,
+}
+
+impl Parse for Passthrough {
+    /// example passthrough:
+    ///
+    /// ```text
+    /// /// The trigger states.
+    /// #[derive(defmt::Format)]
+    /// pub(crate)
+    /// ```
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        // #[derive(defmt::Format)]
+        // ________________________
+        let attrs = input.call(Attribute::parse_outer)?;
+
+        // pub(crate)
+        // __________
+        let vis = input.parse()?;
+
+        Ok(Passthrough { attrs, vis })
+    }
+}
+
+impl Default for Passthrough {
+    fn default() -> Self {
+        Passthrough {
+            attrs: Vec::new(),
+            vis: Visibility::Inherited,
+        }
+    }
+}
+
+impl Passthrough {
+    /// The attributes, as written.
+    pub fn attrs(&self) -> TokenStream {
+        let attrs = &self.attrs;
+        quote!(#( #attrs )*)
+    }
+
+    /// The traits derived by the attributes, by the last segment of their
+    /// path.
+    pub fn derives(&self) -> Vec<Ident> {
+        self.attrs
+            .iter()
+            .filter(|v| v.path.is_ident("derive"))
+            .filter_map(|v| match v.parse_meta() {
+                Ok(Meta::List(list)) => Some(list.nested),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|v| match v {
+                NestedMeta::Meta(meta) => meta.path().segments.last().map(|v| v.ident.clone()),
+                NestedMeta::Lit(_) => None,
+            })
+            .collect()
+    }
+
+    /// The visibility, `pub` unless one was written.
+    pub fn vis(&self) -> TokenStream {
+        match &self.vis {
+            Visibility::Inherited => quote!(pub),
+            vis => quote!(#vis),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse2;
+
+    #[test]
+    fn test_passthrough_parse_and_attrs() {
+        let passthrough: Passthrough = parse2(quote! {
+            /// The states.
+            #[derive(Clone, defmt::Format)]
+            #[derive(Eq, Hash)]
+            #[repr(u8)]
+            pub(crate)
+        })
+        .unwrap();
+
+        let left = quote! {
+            #[doc = r" The states."]
+            #[derive(Clone, defmt::Format)]
+            #[derive(Eq, Hash)]
+            #[repr(u8)]
+        };
+
+        assert_eq!(format!("{}", left), format!("{}", passthrough.attrs()));
+        assert_eq!(passthrough.derives(), vec!["Clone", "Format", "Eq", "Hash"]);
+        assert_eq!(format!("{}", passthrough.vis()), "pub (crate)");
+
+        let passthrough = Passthrough::default();
+
+        assert!(passthrough.attrs().is_empty());
+        assert!(passthrough.derives().is_empty());
+        assert_eq!(format!("{}", passthrough.vis()), "pub");
+    }
+}
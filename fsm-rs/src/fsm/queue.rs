@@ -0,0 +1,159 @@
+use proc_macro2::{Literal, TokenStream};
+use quote::quote;
+
+use crate::fsm::backend::Backend;
+
+/// `Queue`, a buffer of fixed capacity so that no allocation is needed, and
+/// `EventQueue` giving the machine the one its context keeps.
+pub(crate) fn queue(capacity: usize, derives: &TokenStream) -> TokenStream {
+    let capacity = Literal::usize_unsuffixed(capacity);
+
+    quote! {
+        /// The events waiting for `Machine::run`. The context keeps it, see
+        /// `EventQueue`, so that callbacks can post follow-up events too.
+        #derives
+        pub struct Queue {
+            events: [Option<Event>; #capacity],
+            len: usize,
+        }
+
+        impl Queue {
+            /// How many events fit, set with `#[fsm(queue = N)]`.
+            pub const CAPACITY: usize = #capacity;
+
+            pub fn new() -> Queue {
+                // a constant repeats without `Event` being `Copy`
+                const EMPTY: Option<Event> = None;
+                Queue {
+                    events: [EMPTY; #capacity],
+                    len: 0,
+                }
+            }
+
+            /// Queue `event` behind the others, or hand it back if the queue
+            /// is full.
+            pub fn post(&mut self, event: Event) -> Result<(), Event> {
+                if self.len == Queue::CAPACITY {
+                    return Err(event);
+                }
+                self.events[self.len] = Some(event);
+                self.len += 1;
+                Ok(())
+            }
+
+            pub fn len(&self) -> usize {
+                self.len
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.len == 0
+            }
+
+            /// The queued events, oldest first.
+            pub fn iter(&self) -> impl Iterator<Item = &Event> {
+                self.events[..self.len].iter().flatten()
+            }
+
+            /// Take out the oldest event that isn't `deferred`.
+            fn take(&mut self, mut deferred: impl FnMut(&Event) -> bool) -> Option<Event> {
+                let index = self.iter().position(|v| !deferred(v))?;
+                let event = self.events[index].take();
+                // the emptied slot moves to the back
+                self.events[index..self.len].rotate_left(1);
+                self.len -= 1;
+                event
+            }
+        }
+
+        impl Default for Queue {
+            fn default() -> Queue {
+                Queue::new()
+            }
+        }
+
+        /// Implemented by the context, which keeps the queue of
+        /// `Machine::post` and `Machine::run`.
+        pub trait EventQueue {
+            fn queue(&mut self) -> &mut Queue;
+        }
+    }
+}
+
+/// `post` and `run` of a machine without `Regions`, whose `defers` tells
+/// which events stay queued, named with the `turbofish` of a generic
+/// machine. `run` is `async` with the `async` backend.
+pub(crate) fn machine_fns(
+    machine_error_type: &TokenStream,
+    turbofish: &TokenStream,
+    backend: Backend,
+) -> TokenStream {
+    let post_fn = post_fn();
+    let asyncness = backend.asyncness();
+    let awaited = backend.awaited();
+
+    quote! {
+        #post_fn
+
+        /// Handle the queued events oldest first, each to completion before
+        /// the next, including those the callbacks post meanwhile. Events the
+        /// current state defers stay queued until a transition left it. Stops
+        /// at the first error, the events after it stay queued.
+        pub #asyncness fn run(&mut self) -> Result<(), TransitionError<#machine_error_type>> {
+            loop {
+                let state = &self.current_state;
+                let event = match EventQueue::queue(&mut self.context).take(|v| Machine #turbofish ::defers(state, v)) {
+                    Some(event) => event,
+                    None => return Ok(()),
+                };
+                let _ = self.event(event) #awaited ?;
+            }
+        }
+    }
+}
+
+/// `post` and `run` of a machine with `Regions`, which keeps an event queued
+/// while any region defers it.
+pub(crate) fn regions_fns(
+    fields: &[Option<syn::Ident>],
+    machine_error_type: &TokenStream,
+    turbofish: &TokenStream,
+    backend: Backend,
+) -> TokenStream {
+    let post_fn = post_fn();
+    let asyncness = backend.asyncness();
+    let awaited = backend.awaited();
+
+    quote! {
+        #post_fn
+
+        /// Handle the queued events oldest first, each to completion before
+        /// the next, including those the callbacks post meanwhile. Events any
+        /// region defers stay queued until a transition left the deferring
+        /// state. Stops at the first outcome with an error, the events after
+        /// it stay queued.
+        pub #asyncness fn run(&mut self) -> Result<(), Outcome<#machine_error_type>> {
+            loop {
+                let state = self.state();
+                let event = match EventQueue::queue(&mut self.context)
+                    .take(|v| #( #fields::Region #turbofish ::defers(&state.#fields, v) )||*)
+                {
+                    Some(event) => event,
+                    None => return Ok(()),
+                };
+                let outcome = self.event(event) #awaited;
+                if !outcome.is_ok() {
+                    return Err(outcome);
+                }
+            }
+        }
+    }
+}
+
+fn post_fn() -> TokenStream {
+    quote! {
+        /// Queue `event` for `run`, or hand it back if the queue is full.
+        pub fn post(&mut self, event: Event) -> Result<(), Event> {
+            EventQueue::queue(&mut self.context).post(event)
+        }
+    }
+}
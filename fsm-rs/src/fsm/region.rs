@@ -0,0 +1,240 @@
+use heck::SnakeCase;
+use syn::{
+    braced,
+    parse::{Parse, ParseStream, Result},
+    punctuated::Punctuated,
+    Ident, Token,
+};
+
+use crate::fsm::{
+    defer::Defer, final_states::FinalStates, initial_state::InitialState, invariants::Invariants,
+    states::States, transitions::Transitions,
+};
+
+/// One state machine of a `fsm!` definition: the whole definition, or one of
+/// its `Regions`, which all run side by side on the same events.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Region {
+    /// `None` for a definition without `Regions`.
+    pub name: Option<Ident>,
+    /// The declared initial state, which may be a superstate.
+    pub initial: Ident,
+    pub states: States,
+    pub final_states: FinalStates,
+    pub transitions: Transitions,
+    pub defer: Defer,
+    pub invariants: Invariants,
+}
+
+impl Parse for Region {
+    /// example region:
+    ///
+    /// ```text
+    /// Power {
+    ///     Initial = Healthy;
+    ///
+    ///     States {
+    ///         Healthy = Healthy,
+    ///         Overcurrent = Overcurrent,
+    ///     }
+    ///
+    ///     Transitions {
+    ///         CurrentChange [
+    ///             Healthy => Overcurrent if overcurrent,
+    ///             Overcurrent => Healthy,
+    ///         ],
+    ///     }
+    ///
+    ///     Defer {
+    ///         Overcurrent: [TriggerPull],
+    ///     }
+    ///
+    ///     Invariants {
+    ///         can_reach(Healthy),
+    ///     }
+    /// }
+    /// ```
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        // Power { ... }
+        // _____
+        let name = Ident::parse(input)?;
+
+        let content;
+        braced!(content in input);
+
+        // Initial = Healthy; States { ... } Final { ... }
+        let (initial, states, final_states) = Region::parse_states(&content)?;
+
+        // Transitions { ... }
+        let transitions = Transitions::parse(&content)?;
+
+        // Defer { ... }
+        let defer = if Defer::peek(&content) {
+            Defer::parse(&content)?
+        } else {
+            Defer::default()
+        };
+
+        // Invariants { ... }
+        let invariants = if Invariants::peek(&content) {
+            Invariants::parse(&content)?
+        } else {
+            Invariants::default()
+        };
+
+        Ok(Region {
+            name: Some(name),
+            initial,
+            states,
+            final_states,
+            transitions,
+            defer,
+            invariants,
+        })
+    }
+}
+
+impl Region {
+    /// The optional `Initial`, `States` and the optional `Final` a region
+    /// starts with.
+    pub fn parse_states(input: ParseStream<'_>) -> Result<(Ident, States, FinalStates)> {
+        // Initial = S1;
+        let initial_state = if InitialState::peek(input) {
+            InitialState::parse(input)?
+        } else {
+            InitialState::default()
+        };
+
+        // States {
+        //     S1 = S1,
+        //     S2 = S2,
+        // }
+        let states = States::parse(input)?;
+
+        // Final { S2 }
+        let final_states = if FinalStates::peek(input) {
+            FinalStates::parse(input)?
+        } else {
+            FinalStates::default()
+        };
+
+        let initial = initial_state
+            .state_name(&states)
+            .ok_or_else(|| input.error("expected at least one state"))?;
+
+        Ok((initial, states, final_states))
+    }
+
+    /// The name of the module generated for the region, and of its field in
+    /// `Machine` and `State`.
+    pub fn module_name(&self) -> Option<Ident> {
+        self.name
+            .as_ref()
+            .map(|name| Ident::new(&name.to_string().to_snake_case(), name.span()))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct Regions(pub Vec<Region>);
+
+impl Parse for Regions {
+    /// example regions:
+    ///
+    /// ```text
+    /// Regions {
+    ///     Power {
+    ///         States { ... }
+    ///         Transitions { ... }
+    ///     },
+    ///     Trigger {
+    ///         States { ... }
+    ///         Transitions { ... }
+    ///     },
+    /// }
+    /// ```
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        // Regions { ... }
+        // _______
+        let magic = Ident::parse(input)?;
+
+        if magic != "Regions" {
+            return Err(input.error("expected Regions { ... }"));
+        }
+
+        let content;
+        braced!(content in input);
+
+        let regions: Punctuated<Region, Token![,]> = content.parse_terminated(Region::parse)?;
+        if regions.is_empty() {
+            return Err(content.error("expected at least one region"));
+        }
+        Ok(Regions(regions.into_iter().collect()))
+    }
+}
+
+impl Regions {
+    pub fn peek(input: ParseStream<'_>) -> bool {
+        matches!(input.fork().parse::<Ident>(), Ok(v) if v == "Regions")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::quote;
+
+    #[test]
+    fn test_regions_parse() {
+        let regions: Regions = syn::parse2(quote! {
+            Regions {
+                Power {
+                    States {
+                        Healthy = Healthy,
+                        Overcurrent = Overcurrent,
+                    }
+
+                    Transitions {}
+                },
+                Trigger {
+                    Initial = Released;
+
+                    States {
+                        Pulled = Pulled,
+                        Released = Released,
+                    }
+
+                    Final { Pulled }
+
+                    Transitions {}
+                },
+            }
+        })
+        .unwrap();
+
+        let regions: Vec<_> = regions
+            .0
+            .iter()
+            .map(|v| {
+                (
+                    v.module_name().unwrap().to_string(),
+                    v.initial.to_string(),
+                    v.final_states.0.len(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            regions,
+            vec![
+                ("power".to_string(), "Healthy".to_string(), 0),
+                ("trigger".to_string(), "Released".to_string(), 1),
+            ]
+        );
+
+        let result: Result<Regions> = syn::parse2(quote! {
+            Regions {}
+        });
+
+        assert!(result.is_err());
+    }
+}
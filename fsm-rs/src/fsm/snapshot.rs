@@ -0,0 +1,408 @@
+use proc_macro2::{Literal, TokenStream};
+use quote::{format_ident, quote};
+use syn::{Ident, Type};
+
+use crate::fsm::{events::Events, machine_context::ContextAccess, region::Region};
+
+/// `#[derive(Serialize, Deserialize)]` for the items a snapshot is made of,
+/// with the `serde` feature of fsm-rs. They go in front of the other
+/// attributes of the item they derive for.
+pub(crate) fn serde_derive(snapshot: bool) -> TokenStream {
+    if snapshot && cfg!(feature = "serde") {
+        quote!(#[derive(::serde::Serialize, ::serde::Deserialize)])
+    } else {
+        quote!()
+    }
+}
+
+/// `Codec` and its implementations for the integers, `bool` and `Option`.
+pub(crate) fn codec() -> TokenStream {
+    let integers = [
+        quote!(u8),
+        quote!(u16),
+        quote!(u32),
+        quote!(u64),
+        quote!(i8),
+        quote!(i16),
+        quote!(i32),
+        quote!(i64),
+    ];
+
+    quote! {
+        /// The compact binary encoding of `Snapshot` and `Event`, which
+        /// doesn't allocate: an enum is the index of its variant in a byte
+        /// followed by the variant's data, integers are little endian. The
+        /// state and event types implement it for their fields.
+        pub trait Codec: Sized {
+            /// Write `self` to the start of `bytes` and return how many it
+            /// took, or `None` if they are too few.
+            fn encode(&self, bytes: &mut [u8]) -> Option<usize>;
+
+            /// Read a value from the start of `bytes`, with how many it took,
+            /// or `None` if they don't hold one.
+            fn decode(bytes: &[u8]) -> Option<(Self, usize)>;
+        }
+
+        #(
+            impl Codec for #integers {
+                fn encode(&self, bytes: &mut [u8]) -> Option<usize> {
+                    let raw = self.to_le_bytes();
+                    bytes.get_mut(..raw.len())?.copy_from_slice(&raw);
+                    Some(raw.len())
+                }
+
+                fn decode(bytes: &[u8]) -> Option<(Self, usize)> {
+                    const LEN: usize = core::mem::size_of::<#integers>();
+                    let mut raw = [0; LEN];
+                    raw.copy_from_slice(bytes.get(..LEN)?);
+                    Some((#integers::from_le_bytes(raw), LEN))
+                }
+            }
+        )*
+
+        impl Codec for bool {
+            fn encode(&self, bytes: &mut [u8]) -> Option<usize> {
+                (*self as u8).encode(bytes)
+            }
+
+            fn decode(bytes: &[u8]) -> Option<(Self, usize)> {
+                match bytes.first()? {
+                    0 => Some((false, 1)),
+                    1 => Some((true, 1)),
+                    _ => None,
+                }
+            }
+        }
+
+        impl<T: Codec> Codec for Option<T> {
+            fn encode(&self, bytes: &mut [u8]) -> Option<usize> {
+                match self {
+                    None => 0u8.encode(bytes),
+                    Some(value) => {
+                        *bytes.first_mut()? = 1;
+                        Some(1 + value.encode(bytes.get_mut(1..)?)?)
+                    }
+                }
+            }
+
+            fn decode(bytes: &[u8]) -> Option<(Self, usize)> {
+                match bytes.first()? {
+                    0 => Some((None, 1)),
+                    1 => {
+                        let (value, len) = T::decode(bytes.get(1..)?)?;
+                        Some((Some(value), 1 + len))
+                    }
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+/// `Codec` for an enum whose variants each hold one value.
+fn enum_codec(name: &Ident, variants: &[&Ident]) -> TokenStream {
+    let indices: Vec<_> = (0..variants.len())
+        .map(|v| Literal::u8_unsuffixed(v as u8))
+        .collect();
+
+    quote! {
+        impl Codec for #name {
+            fn encode(&self, bytes: &mut [u8]) -> Option<usize> {
+                let (index, len) = match self {
+                    #( #name::#variants(data) => (#indices, data.encode(bytes.get_mut(1..)?)?), )*
+                };
+                *bytes.first_mut()? = index;
+                Some(1 + len)
+            }
+
+            fn decode(bytes: &[u8]) -> Option<(Self, usize)> {
+                let (index, rest) = bytes.split_first()?;
+                let (value, len) = match index {
+                    #(
+                        #indices => {
+                            let (data, len) = Codec::decode(rest)?;
+                            (#name::#variants(data), len)
+                        }
+                    )*
+                    _ => return None,
+                };
+                Some((value, 1 + len))
+            }
+        }
+    }
+}
+
+/// `Codec` for a struct, its fields one after the other.
+fn struct_codec(name: &Ident, fields: &[Ident]) -> TokenStream {
+    let (first, rest) = fields
+        .split_first()
+        .expect("a snapshot has at least its state");
+
+    let (encode, decode) = if rest.is_empty() {
+        (
+            quote!(self.#first.encode(bytes)),
+            quote! {
+                let (#first, len) = Codec::decode(bytes)?;
+                Some((#name { #first }, len))
+            },
+        )
+    } else {
+        (
+            quote! {
+                let mut len = self.#first.encode(bytes)?;
+                #(
+                    let taken = self.#rest.encode(bytes.get_mut(len..)?)?;
+                    len += taken;
+                )*
+                Some(len)
+            },
+            quote! {
+                let (#first, mut len) = Codec::decode(bytes)?;
+                #(
+                    let (#rest, taken) = Codec::decode(bytes.get(len..)?)?;
+                    len += taken;
+                )*
+                Some((#name { #first, #( #rest, )* }, len))
+            },
+        )
+    };
+
+    quote! {
+        impl Codec for #name {
+            fn encode(&self, bytes: &mut [u8]) -> Option<usize> {
+                #encode
+            }
+
+            fn decode(bytes: &[u8]) -> Option<(Self, usize)> {
+                #decode
+            }
+        }
+    }
+}
+
+/// `Codec` for `Event`, to record event streams and feed them back.
+pub(crate) fn event_codec(events: &Events) -> TokenStream {
+    let event_names: Vec<_> = events.events.iter().map(|v| &v.event_name).collect();
+    enum_codec(&format_ident!("Event"), &event_names)
+}
+
+/// `Snapshot` of a machine without `Regions` or of one region, with
+/// `Codec` for it, `State` and `StateId`.
+pub(crate) fn region_snapshot(
+    region: &Region,
+    serde: &TokenStream,
+    derives: &TokenStream,
+) -> TokenStream {
+    let states = &region.states;
+    let state_names: Vec<_> = states.leaves().into_iter().map(|v| &v.state_name).collect();
+    let indices: Vec<_> = (0..state_names.len())
+        .map(|v| Literal::u8_unsuffixed(v as u8))
+        .collect();
+
+    let superstates = states.superstates();
+    let superstate_fields: Vec<_> = superstates.iter().map(|v| v.field_name()).collect();
+    let superstate_types: Vec<_> = superstates.iter().map(|v| &v.state_type).collect();
+    let history_fields: Vec<_> = region
+        .transitions
+        .histories(states)
+        .iter()
+        .map(|v| v.history_field_name())
+        .collect();
+    let deadline_fields: Vec<_> = region
+        .transitions
+        .timed(states)
+        .iter()
+        .map(|v| v.deadline_field_name())
+        .collect();
+
+    let mut fields = vec![format_ident!("state")];
+    fields.extend(superstate_fields.iter().cloned());
+    fields.extend(history_fields.iter().cloned());
+    fields.extend(deadline_fields.iter().cloned());
+
+    let state_codec = enum_codec(&format_ident!("State"), &state_names);
+    let snapshot_codec = struct_codec(&format_ident!("Snapshot"), &fields);
+
+    quote! {
+        #state_codec
+
+        impl Codec for StateId {
+            fn encode(&self, bytes: &mut [u8]) -> Option<usize> {
+                let index: u8 = match self {
+                    #( StateId::#state_names => #indices, )*
+                };
+                index.encode(bytes)
+            }
+
+            fn decode(bytes: &[u8]) -> Option<(Self, usize)> {
+                match bytes.first()? {
+                    #( #indices => Some((StateId::#state_names, 1)), )*
+                    _ => None,
+                }
+            }
+        }
+
+        /// What `Machine::restore` needs to pick up where `Machine::snapshot`
+        /// left off: the state, the data of the active superstates, the
+        /// leaves the histories remember and the time left of every running
+        /// delay. The context and the observer aren't part of it.
+        #serde
+        #derives
+        pub struct Snapshot {
+            state: State,
+            #( #superstate_fields: #superstate_types, )*
+            #( #history_fields: Option<StateId>, )*
+            #( #deadline_fields: Option<u64>, )*
+        }
+
+        impl Snapshot {
+            pub fn state(&self) -> State {
+                self.state.clone()
+            }
+        }
+
+        #snapshot_codec
+    }
+}
+
+/// `snapshot` and `restore` of a machine without `Regions` or of one region,
+/// which is handed the context if it has delays to measure.
+pub(crate) fn region_snapshot_fns(
+    region: &Region,
+    access: ContextAccess,
+    machine_context_type: &Type,
+) -> TokenStream {
+    let states = &region.states;
+    let superstate_fields: Vec<_> = states
+        .superstates()
+        .iter()
+        .map(|v| v.field_name())
+        .collect();
+    let history_fields: Vec<_> = region
+        .transitions
+        .histories(states)
+        .iter()
+        .map(|v| v.history_field_name())
+        .collect();
+    let deadline_fields: Vec<_> = region
+        .transitions
+        .timed(states)
+        .iter()
+        .map(|v| v.deadline_field_name())
+        .collect();
+
+    let (context_param, now) = match (access, deadline_fields.is_empty()) {
+        (_, true) => (quote!(), quote!()),
+        (ContextAccess::Field, false) => (quote!(), quote!(let now = Clock::now(&self.context);)),
+        (ContextAccess::Argument, false) => (
+            quote!(context: &#machine_context_type,),
+            quote!(let now = Clock::now(context);),
+        ),
+    };
+
+    quote! {
+        /// Everything `restore` needs to pick up from here, e.g. after a
+        /// reset.
+        pub fn snapshot(&self, #context_param) -> Snapshot {
+            #now
+            Snapshot {
+                state: self.current_state.clone(),
+                #( #superstate_fields: self.#superstate_fields.clone(), )*
+                #( #history_fields: self.#history_fields.clone(), )*
+                #( #deadline_fields: self.#deadline_fields.map(|v| v.saturating_sub(now)), )*
+            }
+        }
+
+        /// Pick up where `snapshot` was taken, without running any `exit`
+        /// or `entry` callback. Running delays get the time they had left.
+        pub fn restore(&mut self, #context_param snapshot: Snapshot) {
+            #now
+            self.current_state = snapshot.state;
+            #( self.#superstate_fields = snapshot.#superstate_fields; )*
+            #( self.#history_fields = snapshot.#history_fields; )*
+            #( self.#deadline_fields = snapshot.#deadline_fields.map(|v| now + v); )*
+        }
+    }
+}
+
+/// `Snapshot` of a machine with `Regions`, made of every region's, and the
+/// `snapshot` and `restore` of its `Machine`.
+pub(crate) fn regions_snapshot(
+    regions: &[Region],
+    serde: &TokenStream,
+    derives: &TokenStream,
+) -> (TokenStream, TokenStream) {
+    let fields: Vec<_> = regions.iter().filter_map(|v| v.module_name()).collect();
+    let context_args: Vec<_> = regions
+        .iter()
+        .map(|v| {
+            if v.transitions.timeouts.is_empty() {
+                quote!()
+            } else {
+                quote!(&self.context,)
+            }
+        })
+        .collect();
+
+    let snapshot_codec = struct_codec(&format_ident!("Snapshot"), &fields);
+
+    let items = quote! {
+        /// What `Machine::restore` needs of every region, see the
+        /// `Snapshot` of each.
+        #serde
+        #derives
+        pub struct Snapshot {
+            #( pub #fields: #fields::Snapshot, )*
+        }
+
+        #snapshot_codec
+    };
+    let fns = quote! {
+        /// Everything `restore` needs to pick up from here in every region,
+        /// e.g. after a reset.
+        pub fn snapshot(&self) -> Snapshot {
+            Snapshot {
+                #( #fields: self.#fields.snapshot(#context_args), )*
+            }
+        }
+
+        /// Pick up where `snapshot` was taken in every region, without
+        /// running any `exit` or `entry` callback.
+        pub fn restore(&mut self, snapshot: Snapshot) {
+            #( self.#fields.restore(#context_args snapshot.#fields); )*
+        }
+    };
+
+    (items, fns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serde_derive() {
+        assert!(serde_derive(false).is_empty());
+        assert_eq!(serde_derive(true).is_empty(), !cfg!(feature = "serde"));
+    }
+
+    #[test]
+    fn test_struct_codec() {
+        let codec = struct_codec(&format_ident!("Snapshot"), &[format_ident!("state")]);
+
+        let expected = quote! {
+            impl Codec for Snapshot {
+                fn encode(&self, bytes: &mut [u8]) -> Option<usize> {
+                    self.state.encode(bytes)
+                }
+
+                fn decode(bytes: &[u8]) -> Option<(Self, usize)> {
+                    let (state, len) = Codec::decode(bytes)?;
+                    Some((Snapshot { state }, len))
+                }
+            }
+        };
+
+        assert_eq!(codec.to_string(), expected.to_string());
+    }
+}
@@ -1,25 +1,45 @@
+use heck::SnakeCase;
 use proc_macro2::TokenStream;
 use quote::{quote, ToTokens};
 use syn::{
     braced,
-    parse::{Parse, ParseStream, Result},
+    parse::{Error, Parse, ParseStream, Result},
     punctuated::Punctuated,
-    Ident, Token, Type,
+    token, Attribute, Ident, Token, Type,
 };
 
+use crate::fsm::passthrough::Passthrough;
+
 #[derive(Debug, PartialEq)]
 pub(crate) struct State {
+    /// Attributes and doc comments of the variant of a leaf.
+    pub attrs: Vec<Attribute>,
     pub state_name: Ident,
     pub state_type: Type,
+    pub children: Vec<State>,
 }
 
 impl Parse for State {
     /// example state:
     ///
     /// ```text
+    /// /// Waiting for the trigger.
     /// S1 = S1
     /// ```
+    ///
+    /// example superstate:
+    ///
+    /// ```text
+    /// S1 = S1 {
+    ///     S2 = S2,
+    ///     S3 = S3
+    /// }
+    /// ```
     fn parse(input: ParseStream<'_>) -> Result<Self> {
+        // /// Waiting for the trigger.
+        // ____________________________
+        let attrs = input.call(Attribute::parse_outer)?;
+
         // S1 = S1
         // __
         let state_name: Ident = Ident::parse(input)?;
@@ -32,39 +52,137 @@ impl Parse for State {
         //      __
         let state_type: Type = Type::parse(input)?;
 
+        // S1 = S1 { ... }
+        //         ^^^^^^^
+        let children = if input.peek(token::Brace) {
+            let content;
+            braced!(content in input);
+
+            let children: Punctuated<State, Token![,]> = content.parse_terminated(State::parse)?;
+            if children.is_empty() {
+                return Err(Error::new(
+                    state_name.span(),
+                    format!("superstate {} needs at least one child state", state_name),
+                ));
+            }
+            // a superstate has no variant to put them on
+            if let Some(attr) = attrs.iter().find(|v| !v.path.is_ident("doc")) {
+                return Err(Error::new_spanned(
+                    attr,
+                    format!("superstate {} can only have doc comments", state_name),
+                ));
+            }
+            children.into_iter().collect()
+        } else {
+            Vec::new()
+        };
+
         Ok(State {
+            attrs,
             state_name,
             state_type,
+            children,
         })
     }
 }
 
 impl ToTokens for State {
     fn to_tokens(&self, tokens: &mut TokenStream) {
+        let attrs = &self.attrs;
         let state_name = &self.state_name;
         let state_type = &self.state_type;
         tokens.extend(quote!(
+            #( #attrs )*
             #state_name(#state_type)
         ));
     }
 }
 
+impl State {
+    pub fn is_superstate(&self) -> bool {
+        !self.children.is_empty()
+    }
+
+    /// The `Machine` field holding this superstate while it is active.
+    ///
+    /// The generated fields are prefixed by what they hold, so they can't
+    /// clash with `context`, `current_state` or `observer`, nor with each
+    /// other, e.g. for a superstate `FooHistory` next to the history of `Foo`.
+    pub fn field_name(&self) -> Ident {
+        self.prefixed("superstate")
+    }
+
+    /// The `Machine` field remembering the last active leaf inside this
+    /// superstate, for `History` and `DeepHistory` targets.
+    pub fn history_field_name(&self) -> Ident {
+        self.prefixed("history")
+    }
+
+    /// The `Machine` field holding when the `after` transition of this
+    /// state is due while it is active.
+    pub fn deadline_field_name(&self) -> Ident {
+        self.prefixed("deadline")
+    }
+
+    fn prefixed(&self, prefix: &str) -> Ident {
+        Ident::new(
+            &format!(
+                "__{}_{}",
+                prefix,
+                self.state_name.to_string().to_snake_case()
+            ),
+            self.state_name.span(),
+        )
+    }
+
+    fn flatten<'a>(&'a self, states: &mut Vec<&'a State>) {
+        states.push(self);
+        for child in &self.children {
+            child.flatten(states);
+        }
+    }
+
+    fn path<'a>(&'a self, state_name: &Ident, path: &mut Vec<&'a State>) -> bool {
+        path.push(self);
+        if &self.state_name == state_name || self.children.iter().any(|v| v.path(state_name, path))
+        {
+            return true;
+        }
+        let _ = path.pop();
+        false
+    }
+}
+
 #[derive(Debug, PartialEq)]
-pub(crate) struct States(Vec<State>);
+pub(crate) struct States {
+    /// Goes on `State`, and its visibility on `StateId` too.
+    pub passthrough: Passthrough,
+    /// The `States` keyword, where errors about the whole section point.
+    pub keyword: Ident,
+    /// The outermost states.
+    pub states: Vec<State>,
+}
 
 impl Parse for States {
     /// example states:
     ///
     /// ```text
-    /// States {
+    /// #[derive(Hash)]
+    /// pub(crate) States {
     ///     S1 = S1,
-    ///     S2 = S2,
-    ///     S3 = S3,
-    ///     S4 = S4,
+    ///     S2 = S2 {
+    ///         S3 = S3,
+    ///         S4 = S4
+    ///     },
     ///     S5 = S5
     /// }
     /// ```
     fn parse(input: ParseStream<'_>) -> Result<Self> {
+        // #[derive(Hash)]
+        // pub(crate) States { ... }
+        // __________
+        let passthrough = Passthrough::parse(input)?;
+
         // States { ... }
         // -----------
         let states_magic = Ident::parse(input)?;
@@ -77,16 +195,111 @@ impl Parse for States {
         braced!(content in input);
 
         let states: Punctuated<State, Token![,]> = content.parse_terminated(State::parse)?;
-        Ok(States(states.into_iter().collect()))
+        Ok(States {
+            passthrough,
+            keyword: states_magic,
+            states: states.into_iter().collect(),
+        })
+    }
+}
+
+impl States {
+    /// Every state, superstates before their children.
+    pub fn all(&self) -> Vec<&State> {
+        let mut states = Vec::new();
+        for state in &self.states {
+            state.flatten(&mut states);
+        }
+        states
+    }
+
+    /// The states the machine can actually be in.
+    pub fn leaves(&self) -> Vec<&State> {
+        self.all()
+            .into_iter()
+            .filter(|v| !v.is_superstate())
+            .collect()
+    }
+
+    pub fn superstates(&self) -> Vec<&State> {
+        self.all()
+            .into_iter()
+            .filter(|v| v.is_superstate())
+            .collect()
+    }
+
+    /// The outermost superstate first, `state_name` last.
+    pub fn path(&self, state_name: &Ident) -> Option<Vec<&State>> {
+        let mut path = Vec::new();
+        if self.states.iter().any(|v| v.path(state_name, &mut path)) {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    /// Like `path`, continued through the first child of every superstate
+    /// down to the leaf a transition to `state_name` ends up in.
+    pub fn entry_path(&self, state_name: &Ident) -> Option<Vec<&State>> {
+        let mut path = self.path(state_name)?;
+        while let Some(child) = path.last().and_then(|v| v.children.first()) {
+            path.push(child);
+        }
+        Some(path)
+    }
+}
+
+impl States {
+    /// `StateId` and the `State` queries built on it.
+    pub fn to_introspection_tokens(&self) -> TokenStream {
+        let state_names: Vec<_> = self.leaves().into_iter().map(|v| &v.state_name).collect();
+        let names: Vec<_> = state_names.iter().map(|v| v.to_string()).collect();
+        let vis = self.passthrough.vis();
+
+        quote! {
+            /// The states of `State` without their data.
+            #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+            #vis enum StateId {
+                #(#state_names),*
+            }
+
+            impl StateId {
+                pub fn name(self) -> &'static str {
+                    match self {
+                        #( StateId::#state_names => #names, )*
+                    }
+                }
+            }
+
+            impl State {
+                /// Every state the machine can be in, in declaration order.
+                pub const ALL: &'static [StateId] = &[#( StateId::#state_names ),*];
+
+                /// The names of `ALL`.
+                pub const NAMES: &'static [&'static str] = &[#( #names ),*];
+
+                pub fn id(&self) -> StateId {
+                    match self {
+                        #( State::#state_names(_) => StateId::#state_names, )*
+                    }
+                }
+
+                pub fn name(&self) -> &'static str {
+                    self.id().name()
+                }
+            }
+        }
     }
 }
 
 impl ToTokens for States {
     fn to_tokens(&self, tokens: &mut TokenStream) {
-        let states = &self.0;
+        let states = self.leaves();
+        let attrs = self.passthrough.attrs();
+        let vis = self.passthrough.vis();
         tokens.extend(quote!(
-            #[derive(Clone, Copy, Debug, PartialEq)]
-            pub enum State {
+            #attrs
+            #vis enum State {
                 #(#states),*
             }
         ));
@@ -97,11 +310,11 @@ impl ToTokens for States {
 mod tests {
     use super::*;
     use proc_macro2::TokenStream;
-    use syn::{self, parse_quote};
 
     #[test]
     fn test_states_parse_and_to_tokens() {
         let states: States = syn::parse2(quote! {
+            #[derive(Clone, Debug)]
             States {
                 S1 = S1,
                 S2 = S2
@@ -110,7 +323,7 @@ mod tests {
         .unwrap();
 
         let left = quote! {
-            #[derive(Clone, Debug, PartialEq)]
+            #[derive(Clone, Debug)]
             pub enum State {
                 S1(S1),
                 S2(S2)
@@ -122,4 +335,152 @@ mod tests {
 
         assert_eq!(format!("{}", left), format!("{}", right))
     }
+
+    #[test]
+    fn test_superstates_parse_and_to_tokens() {
+        let states: States = syn::parse2(quote! {
+            States {
+                S1 = S1,
+                S2 = S2 {
+                    S3 = S3,
+                    S4 = S4 {
+                        S5 = S5
+                    }
+                },
+            }
+        })
+        .unwrap();
+
+        let left = quote! {
+            pub enum State {
+                S1(S1),
+                S3(S3),
+                S5(S5)
+            }
+        };
+
+        let mut right = TokenStream::new();
+        states.to_tokens(&mut right);
+
+        assert_eq!(format!("{}", left), format!("{}", right));
+
+        let s5: Ident = syn::parse_str("S5").unwrap();
+        let path: Vec<_> = states
+            .path(&s5)
+            .unwrap()
+            .iter()
+            .map(|v| v.state_name.to_string())
+            .collect();
+        assert_eq!(path, vec!["S2", "S4", "S5"]);
+
+        let s2: Ident = syn::parse_str("S2").unwrap();
+        let entry_path: Vec<_> = states
+            .entry_path(&s2)
+            .unwrap()
+            .iter()
+            .map(|v| v.state_name.to_string())
+            .collect();
+        assert_eq!(entry_path, vec!["S2", "S3"]);
+    }
+
+    #[test]
+    fn test_states_passthrough() {
+        let states: States = syn::parse2(quote! {
+            #[derive(Hash)]
+            pub(crate) States {
+                /// The first.
+                S1 = S1,
+                /// Docs only.
+                S2 = S2 {
+                    #[allow(dead_code)]
+                    S3 = S3
+                }
+            }
+        })
+        .unwrap();
+
+        let left = quote! {
+            #[derive(Hash)]
+            pub(crate) enum State {
+                #[doc = r" The first."]
+                S1(S1),
+                #[allow(dead_code)]
+                S3(S3)
+            }
+        };
+
+        let mut right = TokenStream::new();
+        states.to_tokens(&mut right);
+
+        assert_eq!(format!("{}", left), format!("{}", right));
+
+        let result: Result<States> = syn::parse2(quote! {
+            States {
+                #[allow(dead_code)]
+                S2 = S2 {
+                    S3 = S3
+                }
+            }
+        });
+
+        assert_eq!(
+            result.err().map(|v| v.to_string()),
+            Some("superstate S2 can only have doc comments".to_string())
+        );
+    }
+
+    #[test]
+    fn test_states_to_introspection_tokens() {
+        let states: States = syn::parse2(quote! {
+            States {
+                S1 = S1,
+                S2 = S2 {
+                    S3 = S3
+                }
+            }
+        })
+        .unwrap();
+
+        let left = quote! {
+            /// The states of `State` without their data.
+            #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+            pub enum StateId {
+                S1,
+                S3
+            }
+
+            impl StateId {
+                pub fn name(self) -> &'static str {
+                    match self {
+                        StateId::S1 => "S1",
+                        StateId::S3 => "S3",
+                    }
+                }
+            }
+
+            impl State {
+                /// Every state the machine can be in, in declaration order.
+                pub const ALL: &'static [StateId] = &[StateId::S1, StateId::S3];
+
+                /// The names of `ALL`.
+                pub const NAMES: &'static [&'static str] = &["S1", "S3"];
+
+                pub fn id(&self) -> StateId {
+                    match self {
+                        State::S1(_) => StateId::S1,
+                        State::S3(_) => StateId::S3,
+                    }
+                }
+
+                pub fn name(&self) -> &'static str {
+                    self.id().name()
+                }
+            }
+        };
+
+        assert_eq!(
+            format!("{}", left),
+            format!("{}", states.to_introspection_tokens())
+        );
+    }
 }
@@ -0,0 +1,865 @@
+use proc_macro2::{Literal, TokenStream};
+use quote::quote;
+use syn::{Ident, Type};
+
+use crate::fsm::{
+    machine_context::ContextAccess,
+    states::States,
+    transitions::{History, TransitionTarget, Transitions},
+    unhandled_policy::UnhandledPolicy,
+};
+
+/// What `Machine::event`, `Machine::can_handle` and `Machine::tick` are made
+/// of with the `table` backend.
+pub(crate) struct Table {
+    /// The `table` module holding the transition table and the functions
+    /// walking it.
+    pub module: TokenStream,
+    pub event_fn: TokenStream,
+    pub can_handle_fn: TokenStream,
+    /// `tick` and `next_deadline`, if any state has an `after` transition.
+    pub tick_fns: TokenStream,
+    /// The private methods reaching the states, guards and actions the table
+    /// refers to by index.
+    pub helper_fns: TokenStream,
+}
+
+/// `states.all()` indexes the table: a state is referred to by its position
+/// there, superstates included.
+fn node_index(states: &States, state_name: &Ident) -> Literal {
+    let index = states
+        .all()
+        .iter()
+        .position(|v| &v.state_name == state_name)
+        .expect("the states of the table are validated while parsing");
+    Literal::u8_unsuffixed(index as u8)
+}
+
+fn target_tokens(states: &States, source: &Ident, target: &TransitionTarget) -> TokenStream {
+    let source = node_index(states, source);
+    let to = node_index(states, &target.to);
+    let resume = match target.history {
+        None => quote!(Entry),
+        Some(History::Shallow) => quote!(Shallow),
+        Some(History::Deep) => quote!(Deep),
+    };
+    let internal = target.internal;
+    quote!(Target { source: #source, to: #to, resume: Resume::#resume, internal: #internal })
+}
+
+/// The states some transition exits and those it enters, superstates
+/// included; only their `exit` and `entry` are ever called.
+fn crossed<'a>(
+    transitions: &'a Transitions,
+    states: &'a States,
+) -> (Vec<&'a Ident>, Vec<&'a Ident>) {
+    let mut exited = Vec::new();
+    let mut entered = Vec::new();
+
+    for leaf in states.leaves() {
+        let leaf_path = states.path(&leaf.state_name).unwrap_or_default();
+        for (source, target) in transitions.leaf_targets(states, &leaf.state_name) {
+            if target.internal {
+                continue;
+            }
+            let source_path = states.path(source).unwrap_or_default();
+            for (_, to) in target.resumed(states, &leaf.state_name) {
+                let target_path = states.entry_path(to).unwrap_or_default();
+                let target_depth = states.path(to).map_or(0, |v| v.len());
+
+                // the superstates enclosing both the source and the target
+                // are neither left nor entered, see `AfterExitCase`
+                let common = source_path[..source_path.len() - 1]
+                    .iter()
+                    .zip(&target_path[..target_depth - 1])
+                    .take_while(|(a, b)| a.state_name == b.state_name)
+                    .count();
+
+                for state in &leaf_path[common..] {
+                    if !exited.contains(&&state.state_name) {
+                        exited.push(&state.state_name);
+                    }
+                }
+                for state in &target_path[common..] {
+                    if !entered.contains(&&state.state_name) {
+                        entered.push(&state.state_name);
+                    }
+                }
+            }
+        }
+    }
+
+    (exited, entered)
+}
+
+impl Transitions {
+    /// Every guard of the table with the event it is called with, indexed by
+    /// `Row::guard`.
+    pub fn table_guards(&self) -> Vec<(&Ident, &Ident)> {
+        let mut guards = Vec::new();
+        for transition in &self.events {
+            for guard in transition
+                .pairs
+                .values()
+                .flatten()
+                .filter_map(|v| v.guard.as_ref())
+            {
+                if !guards.contains(&(&transition.event_name, guard)) {
+                    guards.push((&transition.event_name, guard));
+                }
+            }
+        }
+        guards
+    }
+
+    /// The `table` backend: every transition as a row of a `const` table,
+    /// walked by an interpreter that behaves like the nested `match`es of
+    /// `to_event_fn_tokens` and `to_tick_fn_tokens`.
+    pub fn to_table_tokens(
+        &self,
+        states: &States,
+        error_type: &TokenStream,
+        unhandled: UnhandledPolicy,
+        access: ContextAccess,
+        context_type: &Type,
+    ) -> Table {
+        let context = access.context();
+        let context_param = access.param(context_type, true);
+        let context_ref_param = access.param(context_type, false);
+        let context_arg = match access {
+            ContextAccess::Field => quote!(),
+            ContextAccess::Argument => quote!(context,),
+        };
+
+        let all = states.all();
+        let leaves = states.leaves();
+        let leaf_names: Vec<_> = leaves.iter().map(|v| &v.state_name).collect();
+        let superstates = states.superstates();
+        let histories = self.histories(states);
+        let timed = self.timed(states);
+        let guards = self.table_guards();
+
+        // 1. the table
+        // ____________
+        let nodes = all.iter().map(|state| {
+            let parent = match states.path(&state.state_name).as_deref() {
+                Some([.., parent, _]) => {
+                    let parent = node_index(states, &parent.state_name);
+                    quote!(Some(#parent))
+                }
+                _ => quote!(None),
+            };
+            let kind = match state.children.first() {
+                Some(child) => {
+                    let first_child = node_index(states, &child.state_name);
+                    quote!(Kind::Superstate { first_child: #first_child })
+                }
+                None => {
+                    let state_name = &state.state_name;
+                    quote!(Kind::Leaf(StateId::#state_name))
+                }
+            };
+            let timer = match self.timeouts.get(&state.state_name) {
+                Some(timeout) => {
+                    let target = target_tokens(states, &state.state_name, &timeout.target);
+                    quote!(Some(#target))
+                }
+                None => quote!(None),
+            };
+            quote!(Node { parent: #parent, kind: #kind, timer: #timer })
+        });
+        let leaf_nodes = leaves.iter().map(|v| node_index(states, &v.state_name));
+        let depth = leaves
+            .iter()
+            .filter_map(|v| states.path(&v.state_name))
+            .map(|v| v.len())
+            .max()
+            .unwrap_or(1);
+
+        let mut rows = Vec::new();
+        for transition in &self.events {
+            let event_name = &transition.event_name;
+            for leaf in &leaves {
+                let leaf_name = &leaf.state_name;
+                for (source, target) in transition.leaf_targets(states, leaf_name) {
+                    let guard = match &target.guard {
+                        Some(guard) => {
+                            let index = guards
+                                .iter()
+                                .position(|v| *v == (event_name, guard))
+                                .expect("every guard is listed by table_guards");
+                            let index = Literal::u8_unsuffixed(index as u8);
+                            quote!(Some(#index))
+                        }
+                        None => quote!(None),
+                    };
+                    let target = target_tokens(states, source, target);
+                    rows.push(quote! {
+                        Row {
+                            from: StateId::#leaf_name,
+                            event: EventId::#event_name,
+                            guard: #guard,
+                            target: #target,
+                        }
+                    });
+                }
+            }
+        }
+
+        let module = quote! {
+            /// The transition table walked by `Machine::event` and
+            /// `Machine::tick`, generated by the `table` backend.
+            #[doc(hidden)]
+            #[allow(dead_code)]
+            pub mod table {
+                use super::*;
+
+                /// How a target picks the state it enters.
+                #[derive(Clone, Copy, Debug, PartialEq)]
+                pub enum Resume {
+                    Entry,
+                    Shallow,
+                    Deep,
+                }
+
+                /// Where a transition owned by the `source` node goes.
+                #[derive(Clone, Copy, Debug)]
+                pub struct Target {
+                    pub source: u8,
+                    pub to: u8,
+                    pub resume: Resume,
+                    pub internal: bool,
+                }
+
+                /// A target a leaf can take for an event, if the `guard`th
+                /// guard passes.
+                #[derive(Clone, Copy, Debug)]
+                pub struct Row {
+                    pub from: StateId,
+                    pub event: EventId,
+                    pub guard: Option<u8>,
+                    pub target: Target,
+                }
+
+                #[derive(Clone, Copy, Debug)]
+                pub enum Kind {
+                    Leaf(StateId),
+                    Superstate { first_child: u8 },
+                }
+
+                /// A state, superstates included, with its `after` transition.
+                #[derive(Clone, Copy, Debug)]
+                pub struct Node {
+                    pub parent: Option<u8>,
+                    pub kind: Kind,
+                    pub timer: Option<Target>,
+                }
+
+                pub const NODES: &[Node] = &[#( #nodes ),*];
+
+                /// The node of every `StateId`.
+                pub const LEAVES: &[u8] = &[#( #leaf_nodes ),*];
+
+                /// For every leaf and event, the leaf's own targets first,
+                /// then those of each enclosing superstate, stopping at the
+                /// first unguarded one.
+                pub const ROWS: &[Row] = &[#( #rows ),*];
+
+                const DEPTH: usize = #depth;
+
+                /// A node and the superstates enclosing it, outermost first.
+                pub struct Path {
+                    nodes: [u8; DEPTH],
+                    len: usize,
+                }
+
+                impl Path {
+                    pub fn of(node: u8) -> Path {
+                        let mut path = Path { nodes: [0; DEPTH], len: 0 };
+                        let mut node = Some(node);
+                        while let Some(v) = node {
+                            path.nodes[path.len] = v;
+                            path.len += 1;
+                            node = NODES[v as usize].parent;
+                        }
+                        path.nodes[..path.len].reverse();
+                        path
+                    }
+
+                    /// Like `of`, continued through the first child of every
+                    /// superstate down to the leaf a transition to `node`
+                    /// ends up in.
+                    pub fn entry(node: u8) -> Path {
+                        let mut path = Path::of(node);
+                        while let Kind::Superstate { first_child } = NODES[path.nodes[path.len - 1] as usize].kind {
+                            path.nodes[path.len] = first_child;
+                            path.len += 1;
+                        }
+                        path
+                    }
+
+                    pub fn nodes(&self) -> &[u8] {
+                        &self.nodes[..self.len]
+                    }
+                }
+
+                /// The leaf a transition to `node` ends up in.
+                pub fn leaf(mut node: u8) -> StateId {
+                    loop {
+                        match NODES[node as usize].kind {
+                            Kind::Leaf(id) => return id,
+                            Kind::Superstate { first_child } => node = first_child,
+                        }
+                    }
+                }
+
+                /// The number of superstates enclosing both `source` and `to`;
+                /// leaving or entering those is not part of the transition.
+                pub fn common(source: u8, to: u8) -> usize {
+                    let source = Path::of(source);
+                    let to = Path::of(to);
+                    source.nodes()[..source.len - 1]
+                        .iter()
+                        .zip(&to.nodes()[..to.len - 1])
+                        .take_while(|(a, b)| a == b)
+                        .count()
+                }
+
+                /// The state `target` enters from `leaf`. A history target
+                /// resumes from `leaf` itself when taken from inside `to`, and
+                /// from the leaf `to` remembers otherwise.
+                pub fn resume(target: Target, leaf: u8, remembered: Option<StateId>) -> u8 {
+                    let depth = Path::of(target.to).len;
+                    let resume = |leaf: u8| {
+                        let path = Path::of(leaf);
+                        if path.len <= depth || path.nodes[depth - 1] != target.to {
+                            return None;
+                        }
+                        match target.resume {
+                            Resume::Entry => None,
+                            Resume::Shallow => Some(path.nodes[depth]),
+                            Resume::Deep => Some(leaf),
+                        }
+                    };
+                    resume(leaf)
+                        .or_else(|| remembered.and_then(|v| resume(LEAVES[v as usize])))
+                        .unwrap_or(target.to)
+                }
+            }
+        };
+
+        // 2. the methods reaching what the table refers to by index
+        // ___________________________________________________________
+        let has_guards = !guards.is_empty();
+        let guard_fn = if has_guards {
+            let guard_indices = (0..guards.len()).map(|v| Literal::u8_unsuffixed(v as u8));
+            let guard_events = guards.iter().map(|(event_name, _)| event_name);
+            let guard_names = guards.iter().map(|(_, guard)| guard);
+            quote! {
+                fn table_guard(context: &#context_type, guard: u8, event: &Event) -> bool {
+                    match (guard, event) {
+                        #( (#guard_indices, Event::#guard_events(data)) => context.#guard_names(data), )*
+                        _ => false,
+                    }
+                }
+            }
+        } else {
+            quote!()
+        };
+
+        let actions: Vec<_> = self
+            .events
+            .iter()
+            .filter(|v| !v.pairs.is_empty())
+            .map(|v| &v.event_name)
+            .collect();
+        let on_fn = if actions.is_empty() {
+            quote!()
+        } else {
+            quote! {
+                fn table_on(context: &mut #context_type, event: &Event) -> Result<(), #error_type> {
+                    match event {
+                        #( Event::#actions(data) => data.on(context), )*
+                        #[allow(unreachable_patterns)]
+                        _ => Ok(()),
+                    }
+                }
+            }
+        };
+
+        let (history_fn, resume) = if histories.is_empty() {
+            (quote!(), quote!(target.to))
+        } else {
+            let nodes = histories.iter().map(|v| node_index(states, &v.state_name));
+            let fields = histories.iter().map(|v| v.history_field_name());
+            let history_fn = quote! {
+                fn table_history(&self, node: u8) -> Option<StateId> {
+                    match node {
+                        #( #nodes => self.#fields, )*
+                        _ => None,
+                    }
+                }
+            };
+            (
+                history_fn,
+                quote!(table::resume(target, leaf, self.table_history(target.to))),
+            )
+        };
+
+        let timed_nodes: Vec<_> = timed
+            .iter()
+            .map(|v| node_index(states, &v.state_name))
+            .collect();
+        let deadline_fields: Vec<_> = timed.iter().map(|v| v.deadline_field_name()).collect();
+        let delays = timed.iter().map(|v| {
+            let timeout = &self.timeouts[&v.state_name];
+            Literal::u64_unsuffixed(timeout.millis)
+        });
+
+        // only the states some transition leaves or enters have their `exit`
+        // or `entry` called, as with the `match` backend
+        let (exited, entered) = crossed(self, states);
+        let exited_leaves: Vec<_> = leaf_names.iter().filter(|v| exited.contains(v)).collect();
+        let entered_leaves: Vec<_> = leaves
+            .iter()
+            .filter(|v| entered.contains(&&v.state_name))
+            .collect();
+        let entered_names = entered_leaves.iter().map(|v| &v.state_name);
+        let entered_types = entered_leaves.iter().map(|v| &v.state_type);
+        let entered_names_again = entered_leaves.iter().map(|v| &v.state_name);
+        let exited_superstates: Vec<_> = superstates
+            .iter()
+            .filter(|v| exited.contains(&&v.state_name))
+            .collect();
+        let entered_superstates: Vec<_> = superstates
+            .iter()
+            .filter(|v| entered.contains(&&v.state_name))
+            .collect();
+
+        // leaving a superstate exits it and records the leaf left in its
+        // history, entering one starts it over
+        let exits = if exited_superstates.is_empty() {
+            quote!()
+        } else {
+            let nodes = exited_superstates
+                .iter()
+                .map(|v| node_index(states, &v.state_name));
+            let fields = exited_superstates.iter().map(|v| v.field_name());
+            quote! {
+                for node in exited[..exited.len() - 1].iter().rev() {
+                    if result.is_ok() {
+                        result = match *node {
+                            #( #nodes => self.#fields.exit(&mut #context, event), )*
+                            _ => Ok(()),
+                        };
+                    }
+                }
+            }
+        };
+        let records = if histories.is_empty() {
+            quote!()
+        } else {
+            let nodes = histories.iter().map(|v| node_index(states, &v.state_name));
+            let fields = histories.iter().map(|v| v.history_field_name());
+            quote! {
+                let id = self.current_state.id();
+                for node in &exited[..exited.len() - 1] {
+                    match *node {
+                        #( #nodes => self.#fields = Some(id), )*
+                        _ => {}
+                    }
+                }
+            }
+        };
+        // a transition starts the timers it enters from the context's clock,
+        // `tick` from the `now` it was handed
+        let (now_param, event_now, tick_now) = if timed.is_empty() {
+            (quote!(), quote!(), quote!())
+        } else {
+            (quote!(now: Option<u64>,), quote!(None,), quote!(Some(now),))
+        };
+        let deadlines = if timed.is_empty() {
+            quote!()
+        } else {
+            quote! {
+                for node in exited {
+                    match *node {
+                        #( #timed_nodes => self.#deadline_fields = None, )*
+                        _ => {}
+                    }
+                }
+                if entered.iter().any(|v| table::NODES[*v as usize].timer.is_some()) {
+                    let now = now.unwrap_or_else(|| Clock::now(&#context));
+                    for node in entered {
+                        match *node {
+                            #( #timed_nodes => self.#deadline_fields = Some(now + #delays), )*
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        };
+        let (resets, entries) = if entered_superstates.is_empty() {
+            (quote!(), quote!())
+        } else {
+            let nodes: Vec<_> = entered_superstates
+                .iter()
+                .map(|v| node_index(states, &v.state_name))
+                .collect();
+            let fields: Vec<_> = entered_superstates.iter().map(|v| v.field_name()).collect();
+            let types = entered_superstates.iter().map(|v| &v.state_type);
+            (
+                quote! {
+                    for node in &entered[..entered.len() - 1] {
+                        match *node {
+                            #( #nodes => self.#fields = <#types as Default>::default(), )*
+                            _ => {}
+                        }
+                    }
+                },
+                quote! {
+                    for node in &entered[..entered.len() - 1] {
+                        if result.is_ok() {
+                            result = match *node {
+                                #( #nodes => self.#fields.entry(&mut #context, event), )*
+                                _ => Ok(()),
+                            };
+                        }
+                    }
+                },
+            )
+        };
+
+        let cross_fn = quote! {
+            /// Exit from the current leaf up to the innermost state containing
+            /// both `source` and `to`, then enter down to `to_id`.
+            fn table_cross(
+                &mut self,
+                #context_param
+                source: u8,
+                to: u8,
+                to_id: StateId,
+                #now_param
+                event: Option<&Event>,
+            ) -> Result<(), #error_type> {
+                let common = table::common(source, to);
+                let exited = table::Path::of(table::LEAVES[self.current_state.id() as usize]);
+                let exited = &exited.nodes()[common..];
+                let entered = table::Path::entry(to);
+                let entered = &entered.nodes()[common..];
+
+                let mut result = match &self.current_state {
+                    #( State::#exited_leaves(state) => state.exit(&mut #context, event), )*
+                    #[allow(unreachable_patterns)]
+                    _ => Ok(()),
+                };
+                #exits
+                if result.is_ok() {
+                    #records
+                    #deadlines
+                    #resets
+                    let mut next = match to_id {
+                        #( StateId::#entered_names => State::#entered_names(<#entered_types as Default>::default()), )*
+                        #[allow(unreachable_patterns)]
+                        _ => self.current_state.clone(),
+                    };
+                    #entries
+                    if result.is_ok() {
+                        result = match &mut next {
+                            #( State::#entered_names_again(next) => next.entry(&mut #context, event), )*
+                            #[allow(unreachable_patterns)]
+                            _ => Ok(()),
+                        };
+                    }
+                    self.current_state = next;
+                }
+                result
+            }
+        };
+
+        let internal = if self
+            .events
+            .iter()
+            .flat_map(|v| v.pairs.values().flatten())
+            .any(|v| v.internal)
+        {
+            quote! {
+                if target.internal {
+                    self.observer.before_transition(&from, event, from.id());
+                    let result = Self::table_on(&mut #context, event)
+                        .map(|_| Transition::Internal { state: from.clone() })
+                        .map_err(TransitionError::Callback);
+                    self.observer.after_transition(&from, event, from.id(), &result);
+                    return result;
+                }
+            }
+        } else {
+            quote!()
+        };
+
+        let take_fn = if actions.is_empty() {
+            quote!()
+        } else {
+            quote! {
+                fn table_take(
+                    &mut self,
+                    #context_param
+                    target: table::Target,
+                    event: &Event,
+                ) -> Result<Transition, TransitionError<#error_type>> {
+                    let from = self.current_state.clone();
+                    #internal
+                    let leaf = table::LEAVES[from.id() as usize];
+                    let to = #resume;
+                    let to_id = table::leaf(to);
+                    self.observer.before_transition(&from, event, to_id);
+                    let mut result = Self::table_on(&mut #context, event);
+                    if result.is_ok() {
+                        result = self.table_cross(#context_arg target.source, to, to_id, #event_now Some(event));
+                    }
+                    let result = result
+                        .map(|_| Transition::Taken {
+                            from: from.clone(),
+                            to: self.current_state.clone(),
+                        })
+                        .map_err(TransitionError::Callback);
+                    self.observer.after_transition(&from, event, to_id, &result);
+                    result
+                }
+            }
+        };
+
+        // 3. the public methods walking the table
+        // _________________________________________
+        let unhandled = unhandled.to_unhandled_tokens();
+        let (select, handled) = if has_guards {
+            (
+                quote! {
+                    rows()
+                        .find(|v| matches!(v.guard, Some(guard) if Self::table_guard(&#context, guard, &event)))
+                        .or_else(|| rows().find(|v| v.guard.is_none()))
+                },
+                quote! {
+                    rows().any(|v| v.guard.is_none())
+                        || rows().any(|v| matches!(v.guard, Some(guard) if Self::table_guard(&#context, guard, event)))
+                },
+            )
+        } else {
+            (
+                quote!(rows().find(|v| v.guard.is_none())),
+                quote!(rows().any(|v| v.guard.is_none())),
+            )
+        };
+
+        let event_fn = if actions.is_empty() {
+            quote! {
+                fn event(&mut self, #context_param event: Event) -> Result<Transition, TransitionError<#error_type>> {
+                    let result = #unhandled;
+                    self.observer.rejected(&self.current_state, &event, &result);
+                    result
+                }
+            }
+        } else {
+            quote! {
+                fn event(&mut self, #context_param event: Event) -> Result<Transition, TransitionError<#error_type>> {
+                    let from = self.current_state.id();
+                    let id = event.id();
+                    let rows = || table::ROWS.iter().filter(move |v| v.from == from && v.event == id);
+                    let result = if rows().next().is_none() {
+                        #unhandled
+                    } else {
+                        let row = #select;
+                        match row {
+                            Some(row) => self.table_take(#context_arg row.target, &event),
+                            None => Err(TransitionError::NoGuardPassed {
+                                state: self.current_state.clone(),
+                                event: event.clone(),
+                            }),
+                        }
+                    };
+                    if let Ok(Transition::Ignored { .. })
+                    | Err(TransitionError::NoGuardPassed { .. })
+                    | Err(TransitionError::Unhandled { .. }) = &result
+                    {
+                        self.observer.rejected(&self.current_state, &event, &result);
+                    }
+                    result
+                }
+            }
+        };
+
+        let can_handle_fn = quote! {
+            /// Whether `event` would take a transition from the current state.
+            pub fn can_handle(&self, #context_ref_param event: &Event) -> bool {
+                let from = self.current_state.id();
+                let id = event.id();
+                let rows = || table::ROWS.iter().filter(move |v| v.from == from && v.event == id);
+                #handled
+            }
+        };
+
+        let (tick_fns, deadline_fn) = if timed.is_empty() {
+            (quote!(), quote!())
+        } else {
+            let tick_fns = quote! {
+                /// Take the `after` transition whose delay is up at `now`, as told
+                /// by the same clock as `Clock::now`. The innermost state's comes
+                /// first if several are due, and at most one is taken per call.
+                /// The delays of the states it enters start at `now` as well.
+                pub fn tick(
+                    &mut self,
+                    #context_param
+                    now: u64
+                ) -> Option<Result<Transition, TransitionError<#error_type>>> {
+                    let from = self.current_state.clone();
+                    let leaf = table::LEAVES[from.id() as usize];
+                    let target = table::Path::of(leaf)
+                        .nodes()
+                        .iter()
+                        .rev()
+                        .filter_map(|v| table::NODES[*v as usize].timer)
+                        .find(|v| matches!(self.table_deadline(v.source), Some(deadline) if deadline <= now))?;
+                    let to = #resume;
+                    let to_id = table::leaf(to);
+                    let result = self
+                        .table_cross(#context_arg target.source, to, to_id, #tick_now None)
+                        .map(|_| Transition::Taken {
+                            from: from.clone(),
+                            to: self.current_state.clone(),
+                        })
+                        .map_err(TransitionError::Callback);
+                    self.observer.timed_out(&from, to_id, &result);
+                    Some(result)
+                }
+
+                /// When `tick` has an `after` transition to take next, if the
+                /// current state is waiting for any.
+                pub fn next_deadline(&self) -> Option<u64> {
+                    [#( self.#deadline_fields ),*].iter().flatten().min().copied()
+                }
+            };
+            let deadline_fn = quote! {
+                fn table_deadline(&self, node: u8) -> Option<u64> {
+                    match node {
+                        #( #timed_nodes => self.#deadline_fields, )*
+                        _ => None,
+                    }
+                }
+            };
+            (tick_fns, deadline_fn)
+        };
+
+        // `table_cross` is only reached through a transition
+        let cross_fn = if actions.is_empty() && timed.is_empty() {
+            quote!()
+        } else {
+            cross_fn
+        };
+
+        Table {
+            module,
+            event_fn,
+            can_handle_fn,
+            tick_fns,
+            helper_fns: quote! {
+                #guard_fn
+                #on_fn
+                #history_fn
+                #deadline_fn
+                #cross_fn
+                #take_fn
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fsm::machine::Machine;
+
+    #[test]
+    fn test_table_rows() {
+        let machine: Machine = syn::parse2(quote! {
+            #[fsm(backend = "table")]
+
+            Context = Door;
+
+            States {
+                Closed = Closed,
+                Open = Open {
+                    Ajar = Ajar,
+                    Wide = Wide,
+                },
+            }
+
+            Events {
+                Push = Push,
+            }
+
+            Transitions {
+                Push [
+                    Closed => Open if unlocked,
+                    Ajar => Wide,
+                    Open => Closed,
+                ],
+                Wide => Closed after 5s,
+            }
+        })
+        .unwrap();
+
+        let region = &machine.regions[0];
+        let table = region.transitions.to_table_tokens(
+            &region.states,
+            &quote!(()),
+            UnhandledPolicy::Error,
+            ContextAccess::Field,
+            &syn::parse_quote!(Door),
+        );
+        let module = table.module.to_string();
+
+        let nodes = quote! {
+            pub const NODES: &[Node] = &[
+                Node { parent: None, kind: Kind::Leaf(StateId::Closed), timer: None },
+                Node { parent: None, kind: Kind::Superstate { first_child: 2 }, timer: None },
+                Node { parent: Some(1), kind: Kind::Leaf(StateId::Ajar), timer: None },
+                Node {
+                    parent: Some(1),
+                    kind: Kind::Leaf(StateId::Wide),
+                    timer: Some(Target { source: 3, to: 0, resume: Resume::Entry, internal: false })
+                }
+            ];
+        };
+        assert!(module.contains(&nodes.to_string()));
+
+        let rows = quote! {
+            pub const ROWS: &[Row] = &[
+                Row {
+                    from: StateId::Closed,
+                    event: EventId::Push,
+                    guard: Some(0),
+                    target: Target { source: 0, to: 1, resume: Resume::Entry, internal: false },
+                },
+                Row {
+                    from: StateId::Ajar,
+                    event: EventId::Push,
+                    guard: None,
+                    target: Target { source: 2, to: 3, resume: Resume::Entry, internal: false },
+                },
+                Row {
+                    from: StateId::Wide,
+                    event: EventId::Push,
+                    guard: None,
+                    target: Target { source: 1, to: 0, resume: Resume::Entry, internal: false },
+                }
+            ];
+        };
+        assert!(module.contains(&rows.to_string()));
+        assert!(module.contains(
+            &quote!(
+                const DEPTH: usize = 2usize;
+            )
+            .to_string()
+        ));
+    }
+}
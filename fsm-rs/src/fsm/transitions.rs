@@ -1,45 +1,333 @@
-use proc_macro2::TokenStream;
+use proc_macro2::{Literal, Span, TokenStream};
 use quote::{quote, ToTokens};
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::BTreeMap;
 use syn::{
-    braced, bracketed,
-    parse::{Parse, ParseStream, Result},
+    braced, bracketed, parenthesized,
+    parse::{Error, Parse, ParseStream, Result},
     punctuated::Punctuated,
-    Ident, Token,
+    token, Ident, LitInt, Token, Type,
 };
 
+use crate::fsm::{
+    backend::Backend,
+    final_states::FinalStates,
+    machine_context::ContextAccess,
+    states::{State, States},
+    unhandled_policy::UnhandledPolicy,
+};
+
+/// The states a transition row leaves from.
+#[derive(Debug, PartialEq)]
+pub(crate) enum TransitionSource {
+    /// `S1`
+    State(Ident),
+    /// `[S1, S2]`
+    List(Vec<Ident>),
+    /// `*` or `* - [S1, S2]`, every leaf state that isn't final or inside
+    /// an excluded state
+    Any {
+        star: Token![*],
+        excluded: Vec<Ident>,
+    },
+}
+
+impl Parse for TransitionSource {
+    /// example transition sources:
+    ///
+    /// ```text
+    /// S1
+    /// [S1, S2]
+    /// *
+    /// * - [S1, S2]
+    /// ```
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        let parse_list = |input: ParseStream<'_>| -> Result<Vec<Ident>> {
+            let content;
+            bracketed!(content in input);
+            let states: Punctuated<Ident, Token![,]> = content.parse_terminated(Ident::parse)?;
+            Ok(states.into_iter().collect())
+        };
+
+        if input.peek(Token![*]) {
+            // `* - [S1, S2]`
+            //  ^
+            let star: Token![*] = input.parse()?;
+
+            // `* - [S1, S2]`
+            //    ^^^^^^^^^^
+            let excluded = if input.peek(Token![-]) && !input.peek(Token![->]) {
+                let _: Token![-] = input.parse()?;
+                parse_list(input)?
+            } else {
+                Vec::new()
+            };
+
+            Ok(TransitionSource::Any { star, excluded })
+        } else if input.peek(token::Bracket) {
+            // `[S1, S2]`
+            //  ^^^^^^^^
+            Ok(TransitionSource::List(parse_list(input)?))
+        } else {
+            Ok(TransitionSource::State(Ident::parse(input)?))
+        }
+    }
+}
+
+impl TransitionSource {
+    /// The states this source stands for, in declaration order.
+    fn expand(&self, states: &States, final_states: &FinalStates) -> Vec<Ident> {
+        match self {
+            TransitionSource::State(state_name) => vec![state_name.clone()],
+            TransitionSource::List(state_names) => state_names.clone(),
+            TransitionSource::Any { excluded, .. } => states
+                .leaves()
+                .into_iter()
+                .filter(|leaf| !final_states.contains(&leaf.state_name))
+                .filter(|leaf| match states.path(&leaf.state_name) {
+                    Some(path) => !path.iter().any(|v| excluded.contains(&v.state_name)),
+                    None => false,
+                })
+                .map(|v| v.state_name.clone())
+                .collect(),
+        }
+    }
+
+    /// Every state named in the source, including excluded ones.
+    pub fn state_names(&self) -> &[Ident] {
+        match self {
+            TransitionSource::State(state_name) => core::slice::from_ref(state_name),
+            TransitionSource::List(state_names) => state_names,
+            TransitionSource::Any { excluded, .. } => excluded,
+        }
+    }
+
+    fn span(&self) -> Span {
+        match self {
+            TransitionSource::State(state_name) => state_name.span(),
+            TransitionSource::List(state_names) => state_names
+                .first()
+                .map_or_else(Span::call_site, |v| v.span()),
+            TransitionSource::Any { star, .. } => star.span,
+        }
+    }
+}
+
+/// What a `History(S)` or `DeepHistory(S)` target resumes when `S` was
+/// active before.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum History {
+    /// `History(S)`, the child of `S` that was last active, entered from
+    /// its first child again
+    Shallow,
+    /// `DeepHistory(S)`, the leaf inside `S` that was last active
+    Deep,
+}
+
+impl History {
+    pub fn keyword(self) -> &'static str {
+        match self {
+            History::Shallow => "History",
+            History::Deep => "DeepHistory",
+        }
+    }
+
+    /// The UML notation of the history pseudo-state.
+    pub fn marker(self) -> &'static str {
+        match self {
+            History::Shallow => "H",
+            History::Deep => "H*",
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub(crate) struct TransitionPair {
-    pub from: Ident,
-    pub to: Ident,
+    pub from: TransitionSource,
+    /// `None` for an internal transition, which stays in the source state.
+    pub to: Option<Ident>,
+    /// Set when `to` is wrapped in `History(..)` or `DeepHistory(..)`.
+    pub history: Option<History>,
+    pub guard: Option<Ident>,
+}
+
+impl TransitionPair {
+    fn describe(&self) -> String {
+        match (&self.to, self.history) {
+            (Some(to), Some(history)) => format!("transition to {}({})", history.keyword(), to),
+            (Some(to), None) => format!("transition to {}", to),
+            (None, _) => "internal transition".to_string(),
+        }
+    }
+
+    fn span(&self) -> Span {
+        match &self.to {
+            Some(to) => to.span(),
+            None => self.from.span(),
+        }
+    }
 }
 
 impl Parse for TransitionPair {
     /// example transition pair:
     ///
     /// ```text
-    /// S1 => S2
+    /// S1 => S2 if guard
+    /// ```
+    ///
+    /// example internal transition:
+    ///
+    /// ```text
+    /// S1 -> internal if guard
+    /// ```
+    ///
+    /// example history transitions:
+    ///
+    /// ```text
+    /// S1 => History(S2) if guard
+    /// S1 => DeepHistory(S2)
     /// ```
     fn parse(input: ParseStream<'_>) -> Result<Self> {
-        // `S1 => S2`
+        // `S1 => S2 if guard`
         //  ^^
-        let from = Ident::parse(&input)?;
-        // `S1 => S2`
-        //     ^^
-        let _: Token![=>] = input.parse()?;
+        let from = TransitionSource::parse(input)?;
+
+        let (to, history) = if input.peek(Token![->]) {
+            // `S1 -> internal if guard`
+            //     ^^^^^^^^^^^
+            let _: Token![->] = input.parse()?;
+            let internal: Ident = Ident::parse(input)?;
+            if internal != "internal" {
+                return Err(Error::new(internal.span(), "expected internal"));
+            }
+            (None, None)
+        } else {
+            // `S1 => S2 if guard`
+            //     ^^^^^
+            let _: Token![=>] = input.parse()?;
+            let to = Ident::parse(input)?;
+
+            // `S1 => History(S2) if guard`
+            //               ^^^^
+            if input.peek(token::Paren) {
+                let history = if to == "History" {
+                    History::Shallow
+                } else if to == "DeepHistory" {
+                    History::Deep
+                } else {
+                    return Err(Error::new(
+                        to.span(),
+                        "expected History(..) or DeepHistory(..)",
+                    ));
+                };
+
+                let content;
+                parenthesized!(content in input);
+                (Some(content.parse()?), Some(history))
+            } else {
+                (Some(to), None)
+            }
+        };
+
+        // `S1 => S2 if guard`
+        //           ^^^^^^^^
+        let guard = if input.peek(Token![if]) {
+            let _: Token![if] = input.parse()?;
+            Some(Ident::parse(input)?)
+        } else {
+            None
+        };
+
+        Ok(TransitionPair {
+            from,
+            to,
+            history,
+            guard,
+        })
+    }
+}
+
+/// One possible target of a state for an event, in declaration order.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TransitionTarget {
+    /// The source state itself for an internal transition.
+    pub to: Ident,
+    pub guard: Option<Ident>,
+    pub internal: bool,
+    pub history: Option<History>,
+}
+
+impl TransitionTarget {
+    /// The states taking this target from `leaf` may enter, each with the
+    /// remembered leaves that select it. `to` itself comes first, it is
+    /// entered when nothing inside it was active yet.
+    ///
+    /// Taken from inside `to`, a history target resumes from `leaf` itself.
+    pub fn resumed<'a>(
+        &'a self,
+        states: &'a States,
+        leaf: &'a Ident,
+    ) -> Vec<(Vec<&'a Ident>, &'a Ident)> {
+        let history = match self.history {
+            Some(history) => history,
+            None => return vec![(Vec::new(), &self.to)],
+        };
 
-        // `S1 => S2`
-        //        ^^
-        let to = Ident::parse(&input)?;
+        let depth = states.path(&self.to).map_or(0, |v| v.len());
+        let resume = |leaf: &Ident| -> Option<&'a Ident> {
+            let path = states.path(leaf)?;
+            if path.len() <= depth || path[depth - 1].state_name != self.to {
+                return None;
+            }
+            match history {
+                History::Shallow => Some(&path[depth].state_name),
+                History::Deep => Some(&path[path.len() - 1].state_name),
+            }
+        };
+
+        if let Some(resumed) = resume(leaf) {
+            return vec![(Vec::new(), resumed)];
+        }
 
-        Ok(TransitionPair { from, to })
+        let mut resumed: Vec<(Vec<&Ident>, &Ident)> = vec![(Vec::new(), &self.to)];
+        for candidate in states.leaves() {
+            let to = match resume(&candidate.state_name) {
+                Some(to) => to,
+                None => continue,
+            };
+            match resumed.iter_mut().skip(1).find(|(_, v)| *v == to) {
+                Some((leaves, _)) => leaves.push(&candidate.state_name),
+                None => resumed.push((vec![&candidate.state_name], to)),
+            }
+        }
+        resumed
+    }
+
+    /// The leaves the machine may be in after taking this target from
+    /// `leaf`, the one entered without any history first.
+    pub fn entered<'a>(&'a self, states: &'a States, leaf: &'a Ident) -> Vec<&'a Ident> {
+        if self.internal {
+            return vec![leaf];
+        }
+
+        let mut entered = Vec::new();
+        for (_, to) in self.resumed(states, leaf) {
+            let entry = states.entry_path(to).and_then(|mut v| v.pop());
+            if let Some(entry) = entry {
+                if !entered.contains(&&entry.state_name) {
+                    entered.push(&entry.state_name);
+                }
+            }
+        }
+        entered
     }
 }
 
 #[derive(Debug, PartialEq)]
 pub(crate) struct Transition {
     pub event_name: Ident,
-    pub pairs: BTreeMap<Ident, BTreeSet<Ident>>,
+    pub rows: Vec<TransitionPair>,
+    /// `rows` with every source expanded, filled in by `expand`.
+    pub pairs: BTreeMap<Ident, Vec<TransitionTarget>>,
 }
 
 impl Parse for Transition {
@@ -53,80 +341,555 @@ impl Parse for Transition {
         let block_transition;
         bracketed!(block_transition in input);
 
-        let mut transition_pairs: BTreeMap<Ident, BTreeSet<Ident>> = BTreeMap::new();
-
-        // EVENT1 [ S1 => S2, S1 => S3, ]
-        //          ^^^^^^^^^^^^^^^^^^^
+        // EVENT1 [ S1 => S2 if guard, [S1, S3] => S4, * => S5, ]
+        //          ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
         let punctuated_block_transition: Punctuated<TransitionPair, Token![,]> =
             block_transition.parse_terminated(TransitionPair::parse)?;
 
-        for pair in punctuated_block_transition {
-            if let Some(v) = transition_pairs.get_mut(&pair.from) {
-                let _= v.insert(pair.to);
-            } else {
-                let mut v = BTreeSet::new();
-                let _= v.insert(pair.to);
-                let _= transition_pairs.insert(pair.from, v);
-            }
-        }
-
         Ok(Transition {
             event_name,
-            pairs: transition_pairs,
+            rows: punctuated_block_transition.into_iter().collect(),
+            pairs: BTreeMap::new(),
         })
     }
 }
 
+impl Transition {
+    /// Expand every row into one pair per source state.
+    fn expand(&mut self, states: &States, final_states: &FinalStates) -> Result<()> {
+        let mut transition_pairs: BTreeMap<Ident, Vec<TransitionTarget>> = BTreeMap::new();
+
+        for pair in &self.rows {
+            let sources = pair.from.expand(states, final_states);
+
+            if sources.is_empty() {
+                return Err(Error::new(
+                    pair.from.span(),
+                    format!(
+                        "{} for {} has no source states",
+                        pair.describe(),
+                        self.event_name
+                    ),
+                ));
+            }
+
+            for from in sources {
+                let targets = transition_pairs.entry(from.clone()).or_default();
+
+                // guards are evaluated in declaration order, so nothing after an
+                // unguarded target can ever be taken
+                if targets.iter().any(|v| v.guard.is_none()) {
+                    return Err(Error::new(
+                        pair.span(),
+                        format!(
+                            "unreachable transition: {} already has an unguarded target for {}",
+                            from, self.event_name
+                        ),
+                    ));
+                }
+
+                targets.push(TransitionTarget {
+                    to: pair.to.clone().unwrap_or_else(|| from.clone()),
+                    guard: pair.guard.clone(),
+                    internal: pair.to.is_none(),
+                    history: pair.history,
+                });
+            }
+        }
+
+        self.pairs = transition_pairs;
+        Ok(())
+    }
+
+    /// The targets a leaf can take for this event, each with the state
+    /// owning the transition: its own first, then those of each enclosing
+    /// superstate, stopping at the first unguarded one.
+    pub fn leaf_targets<'a>(
+        &'a self,
+        states: &'a States,
+        leaf: &Ident,
+    ) -> Vec<(&'a Ident, &'a TransitionTarget)> {
+        let mut targets = Vec::new();
+
+        for source in states.path(leaf).into_iter().flatten().rev() {
+            for target in self.pairs.get(&source.state_name).into_iter().flatten() {
+                targets.push((&source.state_name, target));
+
+                if target.guard.is_none() {
+                    return targets;
+                }
+            }
+        }
+
+        targets
+    }
+}
+
+/// `S1 => S2 after 200ms`, taken by the generated `tick` once the source
+/// state has been active for that long.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Timer {
+    pub row: TransitionPair,
+    pub millis: u64,
+}
+
+impl Parse for Timer {
+    /// example timers:
+    ///
+    /// ```text
+    /// S1 => S2 after 200ms
+    /// [S1, S2] => History(S3) after 2s
+    /// ```
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        // `S1 => S2 after 200ms`
+        //  ^^^^^^^^
+        let row = TransitionPair::parse(input)?;
+
+        if row.to.is_none() {
+            return Err(Error::new(
+                row.span(),
+                "an internal transition can't be taken after a delay",
+            ));
+        }
+        if let Some(guard) = &row.guard {
+            return Err(Error::new(
+                guard.span(),
+                "a transition taken after a delay can't have a guard",
+            ));
+        }
+
+        // `S1 => S2 after 200ms`
+        //           ^^^^^
+        let after = Ident::parse(input)?;
+        if after != "after" {
+            return Err(Error::new(
+                after.span(),
+                "expected after, e.g. S1 => S2 after 200ms",
+            ));
+        }
+
+        // `S1 => S2 after 200ms`
+        //                 ^^^^^
+        let duration: LitInt = input.parse()?;
+        let value: u64 = duration.base10_parse()?;
+        let millis = match duration.suffix() {
+            "ms" => Some(value),
+            "s" => value.checked_mul(1000),
+            _ => {
+                return Err(Error::new(
+                    duration.span(),
+                    "expected a duration in ms or s, e.g. 200ms",
+                ))
+            }
+        };
+        let millis = match millis {
+            Some(millis) if millis > 0 => millis,
+            _ => {
+                return Err(Error::new(
+                    duration.span(),
+                    "expected a duration between 1ms and u64::MAX ms",
+                ))
+            }
+        };
+
+        Ok(Timer { row, millis })
+    }
+}
+
+/// The `after` transition of one state, see `Timer`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Timeout {
+    pub target: TransitionTarget,
+    pub millis: u64,
+}
+
+impl Timeout {
+    /// `after 200ms`, or `after 2s` for whole seconds.
+    pub fn label(&self) -> String {
+        if self.millis % 1000 == 0 {
+            format!("after {}s", self.millis / 1000)
+        } else {
+            format!("after {}ms", self.millis)
+        }
+    }
+}
+
+/// What every case of the generated `event` shares.
+struct Dispatch<'a> {
+    states: &'a States,
+    /// The superstates with a history field, see `Transitions::histories`.
+    histories: Vec<&'a State>,
+    /// The context, see `ContextAccess::context`.
+    context: TokenStream,
+    /// The `after` transitions, whose deadlines are armed on entry.
+    timeouts: &'a BTreeMap<Ident, Timeout>,
+    /// Whether the cases are for `tick`, which has no event.
+    timed: bool,
+    /// `.await` after every callback, see `Backend::awaited`.
+    awaited: TokenStream,
+}
+
 struct AfterExitCase {
-    pub from: Ident,
+    pub context: TokenStream,
+    pub timed: bool,
+    pub awaited: TokenStream,
+    pub exits: Vec<Ident>,
+    /// The history fields of the exited superstates, set to `leaf`.
+    pub records: Vec<Ident>,
+    pub leaf: Ident,
+    /// The deadline fields of the exited states with a timer.
+    pub disarms: Vec<Ident>,
+    /// The deadline fields of the entered states with a timer, and the
+    /// delay of each.
+    pub arms: Vec<(Ident, Literal)>,
+    pub entries: Vec<(Ident, Type)>,
     pub to: Ident,
+    pub to_type: Type,
+}
+
+impl AfterExitCase {
+    /// Exit from the current leaf up to the innermost state containing both
+    /// the state owning the transition and its target, then enter down to
+    /// the target leaf.
+    fn new(dispatch: &Dispatch<'_>, leaf: &Ident, source: &Ident, target: &Ident) -> Result<Self> {
+        let states = dispatch.states;
+        let unknown = |state_name: &Ident| {
+            Error::new(state_name.span(), format!("unknown state {}", state_name))
+        };
+
+        let leaf_path = states.path(leaf).ok_or_else(|| unknown(leaf))?;
+        let source_path = states.path(source).ok_or_else(|| unknown(source))?;
+        let target_path = states.entry_path(target).ok_or_else(|| unknown(target))?;
+
+        // the number of superstates enclosing both the source and the
+        // target; leaving or entering those is not part of the transition
+        let target_depth = states.path(target).map_or(0, |v| v.len());
+        let common = source_path[..source_path.len() - 1]
+            .iter()
+            .zip(&target_path[..target_depth - 1])
+            .take_while(|(a, b)| a.state_name == b.state_name)
+            .count();
+
+        let exited = &leaf_path[common..leaf_path.len() - 1];
+        let exits = exited.iter().rev().map(|v| v.field_name()).collect();
+        let records = exited
+            .iter()
+            .filter(|v| {
+                dispatch
+                    .histories
+                    .iter()
+                    .any(|h| h.state_name == v.state_name)
+            })
+            .map(|v| v.history_field_name())
+            .collect();
+
+        let to = target_path[target_path.len() - 1];
+        let entries = target_path[common..target_path.len() - 1]
+            .iter()
+            .map(|v| (v.field_name(), v.state_type.clone()))
+            .collect();
+
+        let disarms = leaf_path[common..]
+            .iter()
+            .filter(|v| dispatch.timeouts.contains_key(&v.state_name))
+            .map(|v| v.deadline_field_name())
+            .collect();
+        let arms = target_path[common..]
+            .iter()
+            .filter_map(|v| {
+                let timeout = dispatch.timeouts.get(&v.state_name)?;
+                Some((
+                    v.deadline_field_name(),
+                    Literal::u64_unsuffixed(timeout.millis),
+                ))
+            })
+            .collect();
+
+        Ok(AfterExitCase {
+            context: dispatch.context.clone(),
+            timed: dispatch.timed,
+            awaited: dispatch.awaited.clone(),
+            exits,
+            records,
+            leaf: leaf.clone(),
+            disarms,
+            arms,
+            entries,
+            to: to.state_name.clone(),
+            to_type: to.state_type.clone(),
+        })
+    }
 }
 
 impl ToTokens for AfterExitCase {
     fn to_tokens(&self, tokens: &mut TokenStream) {
+        let context = &self.context;
+        let awaited = &self.awaited;
+        let exits = &self.exits;
+        let records = &self.records;
+        let leaf = &self.leaf;
+        let entry_fields: Vec<_> = self.entries.iter().map(|(field, _)| field).collect();
+        let entry_types = self.entries.iter().map(|(_, state_type)| state_type);
         let to = &self.to;
+        let to_type = &self.to_type;
+        let disarms = &self.disarms;
+        let arm_fields = self.arms.iter().map(|(field, _)| field);
+        let arm_delays = self.arms.iter().map(|(_, millis)| millis);
+
+        // entering a state with a timer starts it from the context's clock,
+        // or from the `now` that `tick` was handed
+        let now = if self.arms.is_empty() || self.timed {
+            quote!()
+        } else {
+            quote!(let now = Clock::now(&#context);)
+        };
+
+        // `tick` has no event, so no `on` action to run before the exit and
+        // no event for the callbacks and the observer
+        let (event, before, exited, after) = if self.timed {
+            let event = quote!(None::<&Event>);
+            (
+                event.clone(),
+                quote!(),
+                quote!(state.exit(&mut #context, #event) #awaited;),
+                quote!(self.observer.timed_out(&from, StateId::#to, &result);),
+            )
+        } else {
+            let event = quote!(Some(&event));
+            (
+                event.clone(),
+                quote!(self.observer.before_transition(&from, &event, StateId::#to);),
+                quote! {
+                    data.on(&mut #context) #awaited;
+                    if result.is_ok() {
+                        result = state.exit(&mut #context, #event) #awaited;
+                    }
+                },
+                quote!(self.observer.after_transition(&from, &event, StateId::#to, &result);),
+            )
+        };
+
+        // an async callback can't be awaited in `and_then`
+        let entered = match entry_fields.split_first() {
+            Some((first, rest)) if awaited.is_empty() => quote! {
+                result = self.#first.entry(&mut #context, #event)
+                    #( .and_then(|_| self.#rest.entry(&mut #context, #event)) )*
+                    .and_then(|_| next.entry(&mut #context, #event));
+            },
+            Some(_) => quote! {
+                result = Ok(());
+                #(
+                    if result.is_ok() {
+                        result = self.#entry_fields.entry(&mut #context, #event).await;
+                    }
+                )*
+                if result.is_ok() {
+                    result = next.entry(&mut #context, #event).await;
+                }
+            },
+            None => quote!(result = next.entry(&mut #context, #event) #awaited;),
+        };
+
+        // callbacks run in order until one fails, and the observer hears
+        // about the outcome either way
+        tokens.extend(quote! {
+            let from = self.current_state.clone();
+            #before
+            let mut result = #exited
+            #(
+                if result.is_ok() {
+                    result = self.#exits.exit(&mut #context, #event) #awaited;
+                }
+            )*
+            if result.is_ok() {
+                #(
+                    self.#records = Some(StateId::#leaf);
+                )*
+                #(
+                    self.#disarms = None;
+                )*
+                #now
+                #(
+                    self.#arm_fields = Some(now + #arm_delays);
+                )*
+                #(
+                    self.#entry_fields = <#entry_types as Default>::default();
+                )*
+                let mut next = <#to_type as Default>::default();
+                #entered
+                self.current_state = State::#to(next);
+            }
+            let result = result
+                .map(|_| Transition::Taken {
+                    from: from.clone(),
+                    to: self.current_state.clone(),
+                })
+                .map_err(TransitionError::Callback);
+            #after
+            result
+        })
+    }
+}
+
+/// A history target resumes whichever state the remembered leaf selects, or
+/// enters the superstate normally when nothing is remembered yet.
+struct HistoryCase {
+    pub field: Ident,
+    pub resumed: Vec<(Vec<Ident>, AfterExitCase)>,
+    pub default: AfterExitCase,
+}
+
+impl ToTokens for HistoryCase {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let field = &self.field;
+        let leaves = self.resumed.iter().map(|(leaves, _)| leaves);
+        let cases = self.resumed.iter().map(|(_, case)| case);
+        let default = &self.default;
+
         tokens.extend(quote! {
-            State::#to(state) => {
-                self.current_state = State::#to(state);
-                state.entry();
-                Ok(true)
+            match self.#field {
+                #(
+                    #( Some(StateId::#leaves) )|* => {
+                        #cases
+                    }
+                )*
+                _ => {
+                    #default
+                }
             }
         })
     }
 }
 
+/// An internal transition only runs the event's `on` action, the state is
+/// neither left nor entered.
+struct InternalCase {
+    pub context: TokenStream,
+    pub awaited: TokenStream,
+}
+
+impl ToTokens for InternalCase {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let context = &self.context;
+        let awaited = &self.awaited;
+        tokens.extend(quote! {
+            let state = self.current_state.clone();
+            self.observer.before_transition(&state, &event, state.id());
+            let result = data
+                .on(&mut #context)
+                #awaited
+                .map(|_| Transition::Internal { state: state.clone() })
+                .map_err(TransitionError::Callback);
+            self.observer.after_transition(&state, &event, state.id(), &result);
+            result
+        })
+    }
+}
+
 struct StateCase {
+    pub context: TokenStream,
     pub from: Ident,
-    pub tos: BTreeSet<Ident>,
+    pub targets: Vec<(Option<Ident>, TokenStream)>,
+    /// Whether any target leaves the state and so calls its `exit`.
+    pub exits: bool,
 }
 
-impl ToTokens for StateCase {
-    fn to_tokens(&self, tokens: &mut TokenStream) {
-        let from = &self.from;
+impl StateCase {
+    fn new(dispatch: &Dispatch<'_>, transition: &Transition, leaf: &Ident) -> Option<Self> {
+        let leaf_targets = transition.leaf_targets(dispatch.states, leaf);
 
-        let after_exit_cases: Vec<_> = self
-            .tos
+        let targets: Vec<_> = leaf_targets
             .iter()
-            .map(|v| AfterExitCase {
-                from: from.clone(),
-                to: v.clone(),
+            .map(|(source, target)| {
+                let case = if target.internal {
+                    Ok(InternalCase {
+                        context: dispatch.context.clone(),
+                        awaited: dispatch.awaited.clone(),
+                    }
+                    .into_token_stream())
+                } else {
+                    StateCase::external(dispatch, leaf, source, target)
+                };
+                let case = case.unwrap_or_else(|err| err.to_compile_error());
+                (target.guard.clone(), case)
             })
             .collect();
 
+        if targets.is_empty() {
+            None
+        } else {
+            Some(StateCase {
+                context: dispatch.context.clone(),
+                from: leaf.clone(),
+                targets,
+                exits: leaf_targets.iter().any(|(_, target)| !target.internal),
+            })
+        }
+    }
+
+    fn external(
+        dispatch: &Dispatch<'_>,
+        leaf: &Ident,
+        source: &Ident,
+        target: &TransitionTarget,
+    ) -> Result<TokenStream> {
+        let states = dispatch.states;
+        let case = |to: &Ident| AfterExitCase::new(dispatch, leaf, source, to);
+
+        // the first resumed state is the one entered without history
+        let mut resumed = target.resumed(states, leaf);
+        let default = case(resumed.remove(0).1)?;
+        let resumed = resumed
+            .into_iter()
+            .map(|(leaves, to)| Ok((leaves.into_iter().cloned().collect(), case(to)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let field = states.path(&target.to).and_then(|mut v| v.pop());
+        match field {
+            Some(field) if !resumed.is_empty() => Ok(HistoryCase {
+                field: field.history_field_name(),
+                resumed,
+                default,
+            }
+            .into_token_stream()),
+            _ => Ok(default.into_token_stream()),
+        }
+    }
+}
+
+impl ToTokens for StateCase {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let context = &self.context;
+        let from = &self.from;
+
+        // the last unguarded target (if any) is the fallback taken when every
+        // guard before it failed
+        let (guarded, fallback): (Vec<_>, Vec<_>) =
+            self.targets.iter().partition(|(guard, _)| guard.is_some());
+
+        let guards = guarded.iter().map(|(guard, _)| guard);
+        let guarded_cases = guarded.iter().map(|(_, case)| case);
+
+        let fallback = match fallback.first() {
+            Some((_, case)) => quote!(#case),
+            None => quote! {
+                Err(TransitionError::NoGuardPassed {
+                    state: self.current_state.clone(),
+                    event: event.clone(),
+                })
+            },
+        };
+
+        let state = if self.exits { quote!(state) } else { quote!(_) };
+
         tokens.extend(quote! {
-            State::#from(state) => {
-                match state.exit() {
-                    Ok(r) =>  {
-                        match r {
-                            #( #after_exit_cases )*
-                            _ => {
-                                panic!("cant't go to state from current state")
-                            }
-                        }
-                    }
-                    Err(err) => {
-                        Err(err)
-                    }
+            State::#from(#state) => {
+                #(
+                    if #context.#guards(data) {
+                        #guarded_cases
+                    } else
+                )* {
+                    #fallback
                 }
             }
         })
@@ -135,31 +898,23 @@ impl ToTokens for StateCase {
 
 struct EventCase {
     pub event_name: Ident,
-    pub pairs: BTreeMap<Ident, BTreeSet<Ident>>,
+    pub state_cases: Vec<StateCase>,
+    pub unhandled: TokenStream,
 }
 
 impl ToTokens for EventCase {
     fn to_tokens(&self, tokens: &mut TokenStream) {
         let event_name = &self.event_name;
-
-        let state_cases: Vec<_> = self
-            .pairs
-            .iter()
-            .map(|v| StateCase {
-                from: v.0.clone(),
-                tos: v.1.clone(),
-            })
-            .collect();
+        let state_cases = &self.state_cases;
+        let unhandled = &self.unhandled;
 
         tokens.extend(quote! {
-            Event::#event_name(event) => {
-                if let Err(err) = event.on() {
-                    return Err(err);
-                }
+            Event::#event_name(data) => {
                 match &self.current_state {
                     #( #state_cases )*
+                    #[allow(unreachable_patterns)]
                     _ => {
-                        unreachable!()
+                        #unhandled
                     }
                 }
             }
@@ -168,7 +923,12 @@ impl ToTokens for EventCase {
 }
 
 #[derive(Debug, PartialEq)]
-pub(crate) struct Transitions(pub Vec<Transition>);
+pub(crate) struct Transitions {
+    pub events: Vec<Transition>,
+    pub timers: Vec<Timer>,
+    /// `timers` with every source expanded, filled in by `expand`.
+    pub timeouts: BTreeMap<Ident, Timeout>,
+}
 
 impl Parse for Transitions {
     /// example transitions tokens:
@@ -176,13 +936,20 @@ impl Parse for Transitions {
     /// ```text
     /// Transitions {
     ///     EVENT1 [
-    ///         S1 => S2,
+    ///         S1 => S2 if guard,
     ///         S1 => S3,
     ///     ],
     ///
     ///     EVENT2 [
     ///         S4 => S5,
+    ///         [S1, S2] => S5,
     ///     ],
+    ///
+    ///     EVENT3 [
+    ///         * - [S5] => S1,
+    ///     ],
+    ///
+    ///     S2 => S1 after 200ms,
     /// }
     /// ```
     fn parse(input: ParseStream<'_>) -> Result<Self> {
@@ -197,28 +964,355 @@ impl Parse for Transitions {
         let content;
         braced!(content in input);
 
-        let transitions: Punctuated<Transition, Token![,]> =
-            content.parse_terminated(Transition::parse)?;
-        Ok(Transitions(transitions.into_iter().collect()))
+        let mut events = Vec::new();
+        let mut timers = Vec::new();
+        while !content.is_empty() {
+            // `EVENT1 [ ... ]` or `S2 => S1 after 200ms`
+            if content.peek(Ident) && content.peek2(token::Bracket) {
+                events.push(Transition::parse(&content)?);
+            } else {
+                timers.push(Timer::parse(&content)?);
+            }
+
+            if content.is_empty() {
+                break;
+            }
+            let _: Token![,] = content.parse()?;
+        }
+
+        Ok(Transitions {
+            events,
+            timers,
+            timeouts: BTreeMap::new(),
+        })
     }
 }
 
 impl Transitions {
-    pub fn to_event_fn_tokens(&self) -> TokenStream {
+    /// Expand wildcard and multi-source rows against the declared states.
+    pub fn expand(&mut self, states: &States, final_states: &FinalStates) -> Result<()> {
+        for transition in &mut self.events {
+            transition.expand(states, final_states)?;
+        }
+
+        for timer in &self.timers {
+            let row = &timer.row;
+            let sources = row.from.expand(states, final_states);
+
+            if sources.is_empty() {
+                return Err(Error::new(
+                    row.from.span(),
+                    format!("{} after a delay has no source states", row.describe()),
+                ));
+            }
+
+            for from in sources {
+                // a state is only ever waiting for one delay
+                if self.timeouts.contains_key(&from) {
+                    return Err(Error::new(
+                        row.span(),
+                        format!("{} already has a transition taken after a delay", from),
+                    ));
+                }
+
+                let target = TransitionTarget {
+                    to: row.to.clone().unwrap_or_else(|| from.clone()),
+                    guard: None,
+                    internal: false,
+                    history: row.history,
+                };
+                let timeout = Timeout {
+                    target,
+                    millis: timer.millis,
+                };
+                let _ = self.timeouts.insert(from, timeout);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The `after` transitions running while in a leaf, each with the state
+    /// owning it: its own first, then those of each enclosing superstate.
+    pub fn leaf_timeouts<'a>(
+        &'a self,
+        states: &'a States,
+        leaf: &Ident,
+    ) -> Vec<(&'a Ident, &'a Timeout)> {
+        states
+            .path(leaf)
+            .into_iter()
+            .flatten()
+            .rev()
+            .filter_map(|v| Some((&v.state_name, self.timeouts.get(&v.state_name)?)))
+            .collect()
+    }
+
+    /// Every target out of the leaf, events' and timers' alike.
+    pub fn leaf_targets<'a>(
+        &'a self,
+        states: &'a States,
+        leaf: &Ident,
+    ) -> Vec<(&'a Ident, &'a TransitionTarget)> {
+        let mut targets: Vec<_> = self
+            .events
+            .iter()
+            .flat_map(|v| v.leaf_targets(states, leaf))
+            .collect();
+        targets.extend(
+            self.leaf_timeouts(states, leaf)
+                .into_iter()
+                .map(|(source, timeout)| (source, &timeout.target)),
+        );
+        targets
+    }
+
+    /// The superstates some transition resumes the history of, each needs a
+    /// `Machine` field remembering the last leaf active inside it.
+    pub fn histories<'a>(&self, states: &'a States) -> Vec<&'a State> {
+        let targets: Vec<_> = self
+            .events
+            .iter()
+            .flat_map(|v| v.pairs.values().flatten())
+            .chain(self.timeouts.values().map(|v| &v.target))
+            .collect();
+
+        states
+            .superstates()
+            .into_iter()
+            .filter(|state| {
+                targets
+                    .iter()
+                    .any(|v| v.history.is_some() && v.to == state.state_name)
+            })
+            .collect()
+    }
+
+    /// The states with an `after` transition, each needs a `Machine` field
+    /// holding when it is due.
+    pub fn timed<'a>(&self, states: &'a States) -> Vec<&'a State> {
+        states
+            .all()
+            .into_iter()
+            .filter(|v| self.timeouts.contains_key(&v.state_name))
+            .collect()
+    }
+
+    /// `Machine::TRANSITIONS` and `Machine::can_handle`, from the same
+    /// per-leaf targets `event` dispatches on.
+    pub fn to_introspection_tokens(
+        &self,
+        states: &States,
+        access: ContextAccess,
+        context_type: &Type,
+    ) -> (TokenStream, TokenStream) {
+        let context = access.context();
+        let context_param = access.param(context_type, false);
+        let mut rows = Vec::new();
+        let mut event_cases = Vec::new();
+
+        for transition in &self.events {
+            let event_name = &transition.event_name;
+            let mut state_cases = Vec::new();
+            let mut uses_data = false;
+
+            for leaf in states.leaves() {
+                let targets = transition.leaf_targets(states, &leaf.state_name);
+                if targets.is_empty() {
+                    continue;
+                }
+
+                let leaf_name = &leaf.state_name;
+                let entered = targets
+                    .iter()
+                    .flat_map(|(_, target)| target.entered(states, leaf_name));
+                rows.push(quote! {
+                    (StateId::#leaf_name, EventId::#event_name, &[#( StateId::#entered ),*])
+                });
+
+                // the event is handled unless every target is guarded and
+                // every guard fails
+                let handled = if targets.iter().any(|(_, target)| target.guard.is_none()) {
+                    quote!(true)
+                } else {
+                    uses_data = true;
+                    let guards = targets
+                        .iter()
+                        .filter_map(|(_, target)| target.guard.as_ref());
+                    quote!(#( #context.#guards(data) )||*)
+                };
+                state_cases.push(quote!(State::#leaf_name(_) => #handled,));
+            }
+
+            let data = if uses_data { quote!(data) } else { quote!(_) };
+            event_cases.push(quote! {
+                Event::#event_name(#data) => match &self.current_state {
+                    #( #state_cases )*
+                    #[allow(unreachable_patterns)]
+                    _ => false,
+                },
+            });
+        }
+
+        let transitions_const = quote! {
+            /// For every state and event with a transition, the states the
+            /// machine may end up in, guarded targets first.
+            pub const TRANSITIONS: &'static [(StateId, EventId, &'static [StateId])] = &[
+                #( #rows ),*
+            ];
+        };
+
+        let can_handle_fn = quote! {
+            /// Whether `event` would take a transition from the current state.
+            pub fn can_handle(&self, #context_param event: &Event) -> bool {
+                match event {
+                    #( #event_cases )*
+                    #[allow(unreachable_patterns)]
+                    _ => false,
+                }
+            }
+        };
+
+        (transitions_const, can_handle_fn)
+    }
+
+    pub fn to_event_fn_tokens(
+        &self,
+        states: &States,
+        error_type: &TokenStream,
+        unhandled: UnhandledPolicy,
+        access: ContextAccess,
+        context_type: &Type,
+        backend: Backend,
+    ) -> TokenStream {
+        let unhandled = unhandled.to_unhandled_tokens();
+        let leaves = states.leaves();
+        let dispatch = Dispatch {
+            states,
+            histories: self.histories(states),
+            context: access.context(),
+            timeouts: &self.timeouts,
+            timed: false,
+            awaited: backend.awaited(),
+        };
+        let asyncness = backend.asyncness();
+        let context_param = access.param(context_type, true);
+
         let event_cases: Vec<_> = self
-            .0
+            .events
             .iter()
             .map(|v| EventCase {
                 event_name: v.event_name.clone(),
-                pairs: v.pairs.clone(),
+                state_cases: leaves
+                    .iter()
+                    .filter_map(|leaf| StateCase::new(&dispatch, v, &leaf.state_name))
+                    .collect(),
+                unhandled: unhandled.clone(),
             })
             .collect();
 
         quote! {
-            fn event(&mut self, event: Event) -> Result<bool, &'static str> {
-                match event {
+            #asyncness fn event(&mut self, #context_param event: Event) -> Result<Transition, TransitionError<#error_type>> {
+                let result = match &event {
                     #( #event_cases )*
+                    #[allow(unreachable_patterns)]
+                    _ => {
+                        #unhandled
+                    }
+                };
+                if let Ok(Transition::Ignored { .. })
+                | Err(TransitionError::NoGuardPassed { .. })
+                | Err(TransitionError::Unhandled { .. }) = &result
+                {
+                    self.observer.rejected(&self.current_state, &event, &result);
                 }
+                result
+            }
+        }
+    }
+
+    /// `tick` and `next_deadline`, for a machine with `after` transitions.
+    pub fn to_tick_fn_tokens(
+        &self,
+        states: &States,
+        error_type: &TokenStream,
+        access: ContextAccess,
+        context_type: &Type,
+        backend: Backend,
+    ) -> TokenStream {
+        let dispatch = Dispatch {
+            states,
+            histories: self.histories(states),
+            context: access.context(),
+            timeouts: &self.timeouts,
+            timed: true,
+            awaited: backend.awaited(),
+        };
+        let asyncness = backend.asyncness();
+        let context_param = access.param(context_type, true);
+
+        let mut state_cases = Vec::new();
+        for leaf in states.leaves() {
+            let timeouts = self.leaf_timeouts(states, &leaf.state_name);
+            if timeouts.is_empty() {
+                continue;
+            }
+
+            let (fields, cases): (Vec<_>, Vec<_>) = timeouts
+                .iter()
+                .map(|(source, timeout)| {
+                    let field = states
+                        .path(source)
+                        .and_then(|mut v| v.pop())
+                        .map(|v| v.deadline_field_name());
+                    let case =
+                        StateCase::external(&dispatch, &leaf.state_name, source, &timeout.target)
+                            .unwrap_or_else(|err| err.to_compile_error());
+                    (field, case)
+                })
+                .unzip();
+
+            let leaf_name = &leaf.state_name;
+            state_cases.push(quote! {
+                State::#leaf_name(state) => {
+                    #(
+                        if matches!(self.#fields, Some(deadline) if deadline <= now) {
+                            #cases
+                        } else
+                    )* {
+                        return None;
+                    }
+                }
+            });
+        }
+
+        let deadline_fields = self
+            .timed(states)
+            .into_iter()
+            .map(|v| v.deadline_field_name());
+
+        quote! {
+            /// Take the `after` transition whose delay is up at `now`, as told
+            /// by the same clock as `Clock::now`. The innermost state's comes
+            /// first if several are due, and at most one is taken per call.
+            /// The delays of the states it enters start at `now` as well.
+            pub #asyncness fn tick(
+                &mut self,
+                #context_param
+                now: u64
+            ) -> Option<Result<Transition, TransitionError<#error_type>>> {
+                let result = match &self.current_state {
+                    #( #state_cases )*
+                    #[allow(unreachable_patterns)]
+                    _ => return None,
+                };
+                Some(result)
+            }
+
+            /// When `tick` has an `after` transition to take next, if the
+            /// current state is waiting for any.
+            pub fn next_deadline(&self) -> Option<u64> {
+                [#( self.#deadline_fields ),*].iter().flatten().min().copied()
             }
         }
     }
@@ -227,81 +1321,291 @@ impl Transitions {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use proc_macro2::TokenStream;
-    use syn::{self, parse_quote};
-
-    //    #[test]
-    //    fn test_transition_parse_and_to_tokens() {
-    //        let transition: Transition = syn::parse2(quote! {
-    //            EVENT1 [
-    //               S1 => S2,
-    //               S1 => S3,
-    //            ]
-    //        })
-    //        .unwrap();
-    //
-    //        let left = quote! {
-    //            mod event1 {
-    //                pub enum AfterExitS1 {
-    //                    S2,
-    //                    S3,
-    //                }
-    //                pub trait Callback {
-    //                    fn on_event1(&self, data: (&str)) -> Result<(), &'static str>;
-    //                    fn exit_s1(&self, data: (&str)) -> Result<AfterExitS1, &'static str>;
-    //                    fn entry_s2_from_s1(&self, data: (&str));
-    //                    fn entry_s3_from_s1(&self, data: (&str));
-    //                }
-    //            }
-    //        };
-    //
-    //        let mut right = transition.to_def_tokens();
-    //
-    //        assert_eq!(format!("{}", left), format!("{}", right))
-    //    }
-
-    //    #[test]
-    //    fn test_transitions_parse_and_to_tokens() {
-    //        let transitions: Transitions = syn::parse2(quote! {
-    //            Transitions {
-    //                EVENT1 [
-    //                   S1 => S2,
-    //                   S1 => S3,
-    //                ],
-    //                EVENT2 [
-    //                   S2 => S4,
-    //                ]
-    //            }
-    //        })
-    //        .unwrap();
-    //
-    //        let left = quote! {
-    //            mod event1 {
-    //                pub enum AfterExitS1 {
-    //                    S2,
-    //                    S3,
-    //                }
-    //                pub trait Callback {
-    //                    fn on_event1(&self, data: (&str)) -> Result<(), &'static str>;
-    //                    fn exit_s1(&self, data: (&str)) -> Result<AfterExitS1, &'static str>;
-    //                    fn entry_s2_from_s1(&self, data: (&str));
-    //                    fn entry_s3_from_s1(&self, data: (&str));
-    //                }
-    //            }
-    //            mod event2 {
-    //                pub enum AfterExitS2 {
-    //                    S4,
-    //                }
-    //                pub trait Callback {
-    //                    fn on_event2(&self, data: (&str)) -> Result<(), &'static str>;
-    //                    fn exit_s2(&self, data: (&str)) -> Result<AfterExitS2, &'static str>;
-    //                    fn entry_s4_from_s2(&self, data: (&str));
-    //                }
-    //            }
-    //        };
-    //
-    //        let mut right = transitions.to_def_tokens();
-    //
-    //        assert_eq!(format!("{}", left), format!("{}", right))
-    //    }
+
+    fn states() -> States {
+        syn::parse2(quote! {
+            States {
+                S1 = S1,
+                S2 = S2 {
+                    S3 = S3,
+                    S4 = S4
+                },
+                S5 = S5
+            }
+        })
+        .unwrap()
+    }
+
+    fn expand(tokens: TokenStream) -> Result<Transition> {
+        let mut transition: Transition = syn::parse2(tokens)?;
+        transition.expand(&states(), &FinalStates(vec![syn::parse_str("S5")?]))?;
+        Ok(transition)
+    }
+
+    fn sources(transition: &Transition) -> Vec<String> {
+        transition.pairs.keys().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_transition_parse_keeps_declaration_order() {
+        let transition = expand(quote! {
+            EVENT1 [
+               S1 => S3 if second,
+               S1 => S2 if first,
+               S1 => S4,
+            ]
+        })
+        .unwrap();
+
+        let targets: Vec<_> = transition.pairs[&syn::parse_str::<Ident>("S1").unwrap()]
+            .iter()
+            .map(|v| (v.to.to_string(), v.guard.as_ref().map(|v| v.to_string())))
+            .collect();
+
+        assert_eq!(
+            targets,
+            vec![
+                ("S3".to_string(), Some("second".to_string())),
+                ("S2".to_string(), Some("first".to_string())),
+                ("S4".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transition_parse_rejects_unreachable_target() {
+        let result = expand(quote! {
+            EVENT1 [
+               S1 => S2,
+               S1 => S3 if guard,
+            ]
+        });
+
+        assert!(result.is_err());
+
+        let result = expand(quote! {
+            EVENT1 [
+               * => S1,
+               S3 => S5,
+            ]
+        });
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_transition_expands_sources() {
+        let transition = expand(quote! {
+            EVENT1 [
+               [S1, S2] => S5,
+            ]
+        })
+        .unwrap();
+
+        assert_eq!(sources(&transition), vec!["S1", "S2"]);
+
+        // a wildcard covers leaves that aren't final only, and excluding a
+        // superstate excludes everything inside it
+        let transition = expand(quote! {
+            EVENT1 [
+               * => S1,
+            ]
+        })
+        .unwrap();
+
+        assert_eq!(sources(&transition), vec!["S1", "S3", "S4"]);
+
+        let transition = expand(quote! {
+            EVENT1 [
+               S3 => S1 if guard,
+               * - [S2] => S5,
+            ]
+        })
+        .unwrap();
+
+        assert_eq!(sources(&transition), vec!["S1", "S3"]);
+    }
+
+    #[test]
+    fn test_transition_parse_internal() {
+        let transition = expand(quote! {
+            EVENT1 [
+               S2 -> internal if guard,
+               S1 -> internal,
+            ]
+        })
+        .unwrap();
+
+        let targets: Vec<_> = transition
+            .pairs
+            .iter()
+            .flat_map(|(from, targets)| targets.iter().map(move |v| (from, v)))
+            .map(|(from, v)| (from.to_string(), v.to.to_string(), v.internal))
+            .collect();
+
+        assert_eq!(
+            targets,
+            vec![
+                ("S1".to_string(), "S1".to_string(), true),
+                ("S2".to_string(), "S2".to_string(), true),
+            ]
+        );
+
+        // the `-` of `->` doesn't start a list of excluded states
+        let transition = expand(quote! {
+            EVENT1 [
+               * -> internal,
+            ]
+        })
+        .unwrap();
+
+        assert_eq!(sources(&transition), vec!["S1", "S3", "S4"]);
+
+        let result: Result<Transition> = syn::parse2(quote! {
+            EVENT1 [
+               S1 -> external,
+            ]
+        });
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_transition_parse_history() {
+        let transition = expand(quote! {
+            EVENT1 [
+               S1 => DeepHistory(S2) if guard,
+               S1 => History(S2),
+            ]
+        })
+        .unwrap();
+
+        let s1 = syn::parse_str::<Ident>("S1").unwrap();
+        let histories: Vec<_> = transition.pairs[&s1]
+            .iter()
+            .map(|v| (v.to.to_string(), v.history))
+            .collect();
+
+        assert_eq!(
+            histories,
+            vec![
+                ("S2".to_string(), Some(History::Deep)),
+                ("S2".to_string(), Some(History::Shallow)),
+            ]
+        );
+
+        // outside of S2 whatever was active last is resumed, inside it the
+        // current leaf is
+        let resumed = |leaf: &str| -> Vec<(Vec<String>, String)> {
+            let leaf = syn::parse_str::<Ident>(leaf).unwrap();
+            transition.pairs[&s1][1]
+                .resumed(&states(), &leaf)
+                .into_iter()
+                .map(|(leaves, to)| {
+                    let leaves = leaves.into_iter().map(|v| v.to_string()).collect();
+                    (leaves, to.to_string())
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            resumed("S1"),
+            vec![
+                (vec![], "S2".to_string()),
+                (vec!["S3".to_string()], "S3".to_string()),
+                (vec!["S4".to_string()], "S4".to_string()),
+            ]
+        );
+        assert_eq!(resumed("S4"), vec![(vec![], "S4".to_string())]);
+
+        let result: Result<Transition> = syn::parse2(quote! {
+            EVENT1 [
+               S1 => Resume(S2),
+            ]
+        });
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_transitions_parse_timers() {
+        let mut transitions: Transitions = syn::parse2(quote! {
+            Transitions {
+                EVENT1 [
+                    S1 => S2,
+                ],
+                [S1, S3] => S5 after 200ms,
+                S2 => History(S2) after 2s,
+            }
+        })
+        .unwrap();
+        transitions
+            .expand(&states(), &FinalStates(vec![syn::parse_str("S5").unwrap()]))
+            .unwrap();
+
+        let timeouts: Vec<_> = transitions
+            .timeouts
+            .iter()
+            .map(|(from, v)| (from.to_string(), v.target.to.to_string(), v.label()))
+            .collect();
+
+        assert_eq!(transitions.events.len(), 1);
+        assert_eq!(
+            timeouts,
+            vec![
+                (
+                    "S1".to_string(),
+                    "S5".to_string(),
+                    "after 200ms".to_string()
+                ),
+                ("S2".to_string(), "S2".to_string(), "after 2s".to_string()),
+                (
+                    "S3".to_string(),
+                    "S5".to_string(),
+                    "after 200ms".to_string()
+                ),
+            ]
+        );
+
+        for tokens in [
+            quote!(S1 => S2 after 0ms),
+            quote!(S1 => S2 after 200),
+            quote!(S1 => S2 if guard after 200ms),
+            quote!(S1 -> internal after 200ms),
+            quote!(S1 => S2 within 200ms),
+        ] {
+            assert!(syn::parse2::<Timer>(tokens).is_err());
+        }
+
+        let mut transitions: Transitions = syn::parse2(quote! {
+            Transitions {
+                S1 => S2 after 200ms,
+                [S1, S3] => S4 after 1s,
+            }
+        })
+        .unwrap();
+
+        assert!(transitions
+            .expand(&states(), &FinalStates(Vec::new()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_transition_rejects_empty_expansion() {
+        let result = expand(quote! {
+            EVENT1 [
+               * - [S1, S2] => S1,
+            ]
+        });
+
+        assert!(result.is_err());
+
+        let result = expand(quote! {
+            EVENT1 [
+               [] => S1,
+            ]
+        });
+
+        assert!(result.is_err());
+    }
 }
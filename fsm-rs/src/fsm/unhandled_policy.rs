@@ -0,0 +1,101 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{
+    parse::{Parse, ParseStream, Result},
+    Ident, Token,
+};
+
+/// What the generated `Machine::event` does with an event the current state
+/// has no transition for.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum UnhandledPolicy {
+    #[default]
+    Error,
+    Ignore,
+}
+
+impl Parse for UnhandledPolicy {
+    /// example unhandled policy:
+    ///
+    /// ```text
+    /// Unhandled = Ignore;
+    /// ```
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        // Unhandled = Ignore;
+        // _________
+        let unhandled_magic: Ident = Ident::parse(input)?;
+
+        if unhandled_magic != "Unhandled" {
+            return Err(input.error("expected Unhandled = Error | Ignore"));
+        }
+
+        // Unhandled = Ignore;
+        //           _
+        let _: Token![=] = input.parse()?;
+
+        // Unhandled = Ignore;
+        //             ______
+        let policy: Ident = Ident::parse(input)?;
+
+        let policy = if policy == "Error" {
+            UnhandledPolicy::Error
+        } else if policy == "Ignore" {
+            UnhandledPolicy::Ignore
+        } else {
+            return Err(syn::Error::new(policy.span(), "expected Error or Ignore"));
+        };
+
+        // Unhandled = Ignore;
+        //                   _
+        let _: Token![;] = input.parse()?;
+
+        Ok(policy)
+    }
+}
+
+impl UnhandledPolicy {
+    pub fn peek(input: ParseStream<'_>) -> bool {
+        matches!(input.fork().parse::<Ident>(), Ok(v) if v == "Unhandled")
+    }
+
+    /// The `Machine::event` arm taken when the current state has no
+    /// transition for the event.
+    pub fn to_unhandled_tokens(self) -> TokenStream {
+        match self {
+            UnhandledPolicy::Error => quote! {
+                Err(TransitionError::Unhandled {
+                    state: self.current_state.clone(),
+                    event: event.clone(),
+                })
+            },
+            UnhandledPolicy::Ignore => quote! {
+                Ok(Transition::Ignored {
+                    state: self.current_state.clone(),
+                    event: event.clone(),
+                })
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse2;
+
+    #[test]
+    fn test_unhandled_policy_parse() {
+        let policy: UnhandledPolicy = parse2(quote! {
+            Unhandled = Ignore;
+        })
+        .unwrap();
+
+        assert_eq!(policy, UnhandledPolicy::Ignore);
+
+        let result: Result<UnhandledPolicy> = parse2(quote! {
+            Unhandled = Panic;
+        });
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,707 @@
+use std::{collections::BTreeSet, iter};
+use syn::{
+    parse::{Error, Result},
+    Ident,
+};
+
+use crate::{
+    error,
+    fsm::{
+        events::Events,
+        lints::{Level, Lints},
+        region::Region,
+        transitions::TransitionPair,
+    },
+};
+
+/// Fold every error into one so they are all reported together.
+fn combine(errors: Vec<Error>) -> Result<()> {
+    let mut errors = errors.into_iter();
+    match errors.next() {
+        Some(mut first) => {
+            for error in errors {
+                first.combine(error);
+            }
+            Err(first)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Report every name after the first occurrence of it.
+fn duplicates(kind: &str, names: &[&Ident], errors: &mut Vec<Error>) {
+    let mut seen = BTreeSet::new();
+    for name in names.iter().copied() {
+        if !seen.insert(name) {
+            errors.push(Error::new(
+                name.span(),
+                format!("duplicate {} {}", kind, name),
+            ));
+        }
+    }
+}
+
+/// Check every name in the definition, before `Transitions` is expanded
+/// against `States`.
+pub(crate) fn check_names(regions: &[Region], events: &Events) -> Result<()> {
+    let mut errors = Vec::new();
+
+    let region_names: Vec<_> = regions.iter().filter_map(|v| v.name.as_ref()).collect();
+    let event_names: Vec<_> = events.events.iter().map(|v| &v.event_name).collect();
+
+    duplicates("region", &region_names, &mut errors);
+    duplicates("event", &event_names, &mut errors);
+
+    for region in regions {
+        check_region_names(region, &event_names, &mut errors);
+    }
+
+    combine(errors)
+}
+
+fn check_region_names(region: &Region, event_names: &[&Ident], errors: &mut Vec<Error>) {
+    let Region {
+        initial,
+        states,
+        final_states,
+        transitions,
+        ..
+    } = region;
+
+    let state_names: Vec<_> = states.all().into_iter().map(|v| &v.state_name).collect();
+    let final_names: Vec<_> = final_states.0.iter().collect();
+    let transition_names: Vec<_> = transitions.events.iter().map(|v| &v.event_name).collect();
+
+    duplicates("state", &state_names, errors);
+    duplicates("final state", &final_names, errors);
+    duplicates("transitions for event", &transition_names, errors);
+
+    let unknown_state =
+        |state_name: &Ident| Error::new(state_name.span(), format!("unknown state {}", state_name));
+
+    if !state_names.contains(&initial) {
+        errors.push(unknown_state(initial));
+    }
+
+    for state_name in &final_states.0 {
+        match states.path(state_name).and_then(|mut v| v.pop()) {
+            None => errors.push(unknown_state(state_name)),
+            Some(state) if state.is_superstate() => errors.push(Error::new(
+                state_name.span(),
+                format!("final state {} can't be a superstate", state_name),
+            )),
+            Some(_) => {}
+        }
+    }
+
+    for transition in &transitions.events {
+        if !event_names.contains(&&transition.event_name) {
+            errors.push(Error::new(
+                transition.event_name.span(),
+                format!("unknown event {}", transition.event_name),
+            ));
+        }
+
+        for pair in &transition.rows {
+            check_row_names(region, pair, &state_names, errors);
+        }
+    }
+
+    for timer in &transitions.timers {
+        check_row_names(region, &timer.row, &state_names, errors);
+    }
+
+    for deferral in &region.defer.0 {
+        if !state_names.contains(&&deferral.state_name) {
+            errors.push(unknown_state(&deferral.state_name));
+        }
+
+        for event_name in &deferral.event_names {
+            if !event_names.contains(&event_name) {
+                errors.push(Error::new(
+                    event_name.span(),
+                    format!("unknown event {}", event_name),
+                ));
+            }
+        }
+    }
+
+    for invariant in &region.invariants.0 {
+        for state_name in invariant.state_names() {
+            if !state_names.contains(&state_name) {
+                errors.push(unknown_state(state_name));
+            }
+        }
+    }
+}
+
+fn check_row_names(
+    region: &Region,
+    pair: &TransitionPair,
+    state_names: &[&Ident],
+    errors: &mut Vec<Error>,
+) {
+    for state_name in pair.from.state_names().iter().chain(&pair.to) {
+        if !state_names.contains(&state_name) {
+            errors.push(Error::new(
+                state_name.span(),
+                format!("unknown state {}", state_name),
+            ));
+        }
+    }
+
+    // only a superstate has anything to remember
+    if let (Some(history), Some(to)) = (pair.history, &pair.to) {
+        let state = region.states.path(to).and_then(|mut v| v.pop());
+        if matches!(state, Some(v) if !v.is_superstate()) {
+            errors.push(Error::new(
+                to.span(),
+                format!("{}({}) needs a superstate", history.keyword(), to),
+            ));
+        }
+    }
+}
+
+/// Check the expanded definition for transitions out of final states, and
+/// for events and states that can never be used. Returns the problems
+/// relaxed to warnings.
+pub(crate) fn check_definition(
+    regions: &[Region],
+    events: &Events,
+    lints: Lints,
+) -> Result<Vec<error::Error>> {
+    let mut problems = Vec::new();
+
+    for region in regions {
+        check_final_states(region, &mut problems);
+    }
+
+    for event in &events.events {
+        let used = regions
+            .iter()
+            .flat_map(|v| &v.transitions.events)
+            .any(|v| v.event_name == event.event_name && !v.pairs.is_empty());
+
+        if !used {
+            problems.push((
+                lints.unused_events,
+                error::Error::new(
+                    event.event_name.span(),
+                    format!("event {} has no transitions", event.event_name),
+                ),
+            ));
+        }
+    }
+
+    for region in regions {
+        check_reachability(region, lints, &mut problems);
+    }
+
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    for (level, problem) in problems {
+        match level {
+            Level::Deny => errors.push(problem.into()),
+            Level::Warn => warnings.push(problem),
+            Level::Allow => {}
+        }
+    }
+
+    combine(errors)?;
+    Ok(warnings)
+}
+
+/// Prove the `Invariants` of every region against its expanded transitions.
+pub(crate) fn check_invariants(regions: &[Region]) -> Result<()> {
+    let errors = regions.iter().flat_map(|v| v.invariants.check(v)).collect();
+
+    combine(errors)
+}
+
+/// The `table` backend refers to states and guards by `u8` index.
+pub(crate) fn check_table(regions: &[Region]) -> Result<()> {
+    let mut errors = Vec::new();
+
+    for region in regions {
+        let states = region.states.all();
+        if let Some(state) = states.get(usize::from(u8::MAX) + 1) {
+            errors.push(Error::new(
+                state.state_name.span(),
+                "the table backend supports at most 256 states per region",
+            ));
+        }
+
+        let guards = region.transitions.table_guards();
+        if let Some((_, guard)) = guards.get(usize::from(u8::MAX) + 1) {
+            errors.push(Error::new(
+                guard.span(),
+                "the table backend supports at most 256 guards per region",
+            ));
+        }
+    }
+
+    combine(errors)
+}
+
+/// The generated code clones states and events. Without Clone it would
+/// fail to compile with nothing but "no method named `clone`".
+pub(crate) fn check_derives(regions: &[Region], events: &Events) -> Result<()> {
+    let sections = regions
+        .iter()
+        .map(|v| (&v.states.passthrough, &v.states.keyword))
+        .chain(iter::once((&events.passthrough, &events.keyword)));
+
+    let errors = sections
+        .filter(|(passthrough, _)| !passthrough.derives().iter().any(|v| v == "Clone"))
+        .map(|(_, keyword)| Error::new(keyword.span(), "State and Event must derive Clone"))
+        .collect();
+
+    combine(errors)
+}
+
+/// Final states can only have internal transitions, including those of
+/// their superstates.
+fn check_final_states(region: &Region, problems: &mut Vec<(Level, error::Error)>) {
+    let Region {
+        states,
+        final_states,
+        transitions,
+        ..
+    } = region;
+
+    for transition in &transitions.events {
+        for state_name in &final_states.0 {
+            // internal transitions don't leave the state
+            let outgoing = transition
+                .leaf_targets(states, state_name)
+                .into_iter()
+                .find(|(_, target)| !target.internal);
+
+            if let Some((source, _)) = outgoing {
+                let message = if source == state_name {
+                    format!("final state {} can't have transitions", state_name)
+                } else {
+                    format!(
+                        "transition out of {} for {} would leave final state {}",
+                        source, transition.event_name, state_name
+                    )
+                };
+                problems.push((Level::Deny, error::Error::new(source.span(), message)));
+            }
+        }
+    }
+
+    // a final state never leaves, so its delays never expire either
+    for state_name in &final_states.0 {
+        if let Some((source, _)) = transitions.leaf_timeouts(states, state_name).first() {
+            let message = if *source == state_name {
+                format!("final state {} can't have transitions", state_name)
+            } else {
+                format!(
+                    "transition out of {} after a delay would leave final state {}",
+                    source, state_name
+                )
+            };
+            problems.push((Level::Deny, error::Error::new(source.span(), message)));
+        }
+    }
+}
+
+/// Report the states no sequence of events and delays leads to from the
+/// initial one.
+fn check_reachability(region: &Region, lints: Lints, problems: &mut Vec<(Level, error::Error)>) {
+    let Region {
+        initial,
+        states,
+        transitions,
+        ..
+    } = region;
+
+    if let Some(initial_leaf) = states.entry_path(initial).and_then(|mut v| v.pop()) {
+        // every leaf some sequence of events and delays leads to from the
+        // initial one
+        let mut reachable = BTreeSet::new();
+        let mut pending = vec![&initial_leaf.state_name];
+        while let Some(leaf) = pending.pop() {
+            if !reachable.insert(leaf) {
+                continue;
+            }
+
+            for (_, target) in transitions.leaf_targets(states, leaf) {
+                // a history target only resumes leaves that were active
+                // before, so just the one it enters without history
+                // becomes reachable
+                pending.extend(target.entered(states, leaf).into_iter().take(1));
+            }
+        }
+
+        // report the outermost unreachable state only, not everything in it
+        let mut reported: Vec<&Ident> = Vec::new();
+        for state in states.all() {
+            let path = states.path(&state.state_name).unwrap_or_default();
+            if path.iter().any(|v| reported.contains(&&v.state_name)) {
+                continue;
+            }
+
+            let used = states.leaves().into_iter().any(|leaf| {
+                reachable.contains(&leaf.state_name)
+                    && match states.path(&leaf.state_name) {
+                        Some(path) => path.iter().any(|v| v.state_name == state.state_name),
+                        None => false,
+                    }
+            });
+
+            if !used {
+                reported.push(&state.state_name);
+                problems.push((
+                    lints.unreachable_states,
+                    error::Error::new(
+                        state.state_name.span(),
+                        format!(
+                            "state {} is unreachable from the initial state {}",
+                            state.state_name, initial
+                        ),
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fsm::machine::Machine;
+    use quote::quote;
+    use syn::parse::Result;
+
+    fn messages(result: Result<Machine>) -> Vec<String> {
+        match result {
+            Ok(_) => Vec::new(),
+            Err(err) => err.into_iter().map(|v| v.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_check_names() {
+        let result = syn::parse2(quote! {
+            #[fsm(queue = 4)]
+
+            Context = Context;
+
+            States {
+                S1 = S1,
+                S2 = S2 {
+                    S1 = S1,
+                },
+            }
+
+            Events {
+                E1 = E1,
+                E1 = E1,
+            }
+
+            Transitions {
+                E1 [
+                    S1 => S3,
+                    * - [S4] => S1,
+                ],
+                E2 [
+                    S1 => S2,
+                    S2 => History(S1),
+                ],
+                E1 [
+                    S2 => S1,
+                ],
+            }
+
+            Defer {
+                S6: [E1, E3],
+            }
+
+            Invariants {
+                through(S1 => S2, S5),
+            }
+        });
+
+        assert_eq!(
+            messages(result),
+            vec![
+                "duplicate event E1",
+                "duplicate state S1",
+                "duplicate transitions for event E1",
+                "unknown state S3",
+                "unknown state S4",
+                "unknown event E2",
+                "History(S1) needs a superstate",
+                "unknown state S6",
+                "unknown event E3",
+                "unknown state S5",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_region_names() {
+        let result = syn::parse2(quote! {
+            Context = Context;
+
+            Regions {
+                Power {
+                    States {
+                        S1 = S1,
+                    }
+
+                    Transitions {
+                        E1 [
+                            S1 => S2,
+                        ],
+                    }
+                },
+                Power {
+                    States {
+                        S2 = S2,
+                    }
+
+                    Transitions {}
+                },
+            }
+
+            Events {
+                E1 = E1,
+            }
+        });
+
+        assert_eq!(
+            messages(result),
+            vec!["duplicate region Power", "unknown state S2"]
+        );
+    }
+
+    #[test]
+    fn test_check_definition() {
+        let tokens = |lints| {
+            quote! {
+                Context = Context;
+
+                #lints
+
+                States {
+                    S1 = S1,
+                    S2 = S2,
+                    S3 = S3 {
+                        S4 = S4,
+                        S5 = S5,
+                    },
+                    S6 = S6,
+                }
+
+                Events {
+                    E1 = E1,
+                    E2 = E2,
+                }
+
+                Transitions {
+                    E1 [
+                        S1 => S2,
+                        S4 => S6,
+                    ],
+                    E2 [],
+                }
+            }
+        };
+
+        assert_eq!(
+            messages(syn::parse2(tokens(quote!()))),
+            vec![
+                "event E2 has no transitions",
+                "state S3 is unreachable from the initial state S1",
+                "state S6 is unreachable from the initial state S1",
+            ]
+        );
+
+        let machine: Machine = syn::parse2(tokens(quote! {
+            Warn = [unused_events];
+            Allow = [unreachable_states];
+        }))
+        .unwrap();
+
+        let warnings: Vec<_> = machine.warnings.iter().map(|v| v.to_string()).collect();
+        assert_eq!(warnings, vec!["event E2 has no transitions"]);
+    }
+
+    #[test]
+    fn test_check_final_states() {
+        let result = syn::parse2(quote! {
+            Context = Context;
+
+            Initial = S3;
+
+            States {
+                S1 = S1,
+                S2 = S2 {
+                    S4 = S4,
+                    S5 = S5,
+                },
+            }
+
+            Final { S1, S2, S5, S6 }
+
+            Events {
+                E1 = E1,
+            }
+
+            Transitions {
+                E1 [],
+            }
+        });
+
+        assert_eq!(
+            messages(result),
+            vec![
+                "unknown state S3",
+                "final state S2 can't be a superstate",
+                "unknown state S6",
+            ]
+        );
+
+        let result = syn::parse2(quote! {
+            Context = Context;
+
+            Allow = [unreachable_states];
+
+            States {
+                S1 = S1,
+                S2 = S2 {
+                    S3 = S3,
+                    S4 = S4,
+                },
+            }
+
+            Final { S1, S4 }
+
+            Events {
+                E1 = E1,
+            }
+
+            Transitions {
+                E1 [
+                    S1 => S3,
+                    S2 => S1,
+                ],
+            }
+        });
+
+        assert_eq!(
+            messages(result),
+            vec![
+                "final state S1 can't have transitions",
+                "transition out of S2 for E1 would leave final state S4",
+            ]
+        );
+
+        // internal transitions never leave the final state
+        let result = syn::parse2(quote! {
+            Context = Context;
+
+            States {
+                S1 = S1,
+                S2 = S2,
+            }
+
+            Final { S2 }
+
+            Events {
+                E1 = E1,
+                E2 = E2,
+            }
+
+            Transitions {
+                E1 [
+                    S1 => S2,
+                ],
+                E2 [
+                    S2 -> internal,
+                ],
+            }
+        });
+
+        assert!(messages(result).is_empty());
+
+        // and neither does a delay expire in one
+        let result = syn::parse2(quote! {
+            Context = Context;
+
+            States {
+                S1 = S1,
+                S2 = S2 {
+                    S3 = S3,
+                },
+            }
+
+            Final { S3 }
+
+            Events {
+                E1 = E1,
+            }
+
+            Transitions {
+                E1 [
+                    S1 => S2,
+                ],
+                S2 => S1 after 1s,
+            }
+        });
+
+        assert_eq!(
+            messages(result),
+            vec!["transition out of S2 after a delay would leave final state S3"]
+        );
+    }
+
+    #[test]
+    fn test_check_derives() {
+        let check = |states_attrs, events_attrs| {
+            let machine: Machine = syn::parse2(quote! {
+                Context = Context;
+
+                #states_attrs
+                States {
+                    S1 = S1,
+                }
+
+                #events_attrs
+                Events {
+                    E1 = E1,
+                }
+
+                Transitions {
+                    E1 [
+                        S1 -> internal,
+                    ],
+                }
+            })
+            .unwrap();
+            match super::check_derives(&machine.regions, &machine.events) {
+                Ok(()) => Vec::new(),
+                Err(err) => err.into_iter().map(|v| v.to_string()).collect(),
+            }
+        };
+
+        assert!(check(
+            quote!(#[derive(Clone, Copy)]),
+            quote!(#[derive(Debug)] #[derive(core::clone::Clone)]),
+        )
+        .is_empty());
+        assert_eq!(
+            check(quote!(#[derive(Debug)]), quote!()),
+            vec![
+                "State and Event must derive Clone",
+                "State and Event must derive Clone",
+            ]
+        );
+        assert_eq!(
+            check(quote!(#[derive(Clone)]), quote!(#[derive(Copy)])),
+            vec!["State and Event must derive Clone"]
+        );
+    }
+}
@@ -3,6 +3,23 @@
 //! crate.
 //!
 //! [sm]: https://docs.rs/sm
+//!
+//! # Derives
+//!
+//! The attributes written in front of `States` and `Events` go on the
+//! generated `State` and `Event` as they are. The generated code clones
+//! states and events, so both must derive `Clone`, and `fsm!` reports a
+//! section that doesn't. The types built around them, such as `Transition`
+//! and `TransitionError`, derive those of `Clone`, `Copy`, `Debug` and
+//! `PartialEq` that `State` and `Event` both derive.
+//!
+//! # Features
+//!
+//! - `serde`: derive serde's `Serialize` and `Deserialize` for the `State`,
+//!   `Event`, `StateId` and `Snapshot` of machines with `#[fsm(snapshot)]`.
+//!   The derives name `::serde`, so the crate using the machine has to
+//!   depend on serde itself, with its `derive` feature, and implement both
+//!   traits for its own states and events.
 
 // quote! macro needs a higher recursion limit
 #![recursion_limit = "512"]
@@ -20,7 +37,6 @@
 )]
 #![warn(
     missing_docs,
-    non_snake_case,
     rust_2018_idioms,
     single_use_lifetimes,
     unused_import_braces,
@@ -31,13 +47,12 @@
 )]
 #![deny(clippy::all)]
 
-extern crate proc_macro;
-
 use crate::fsm::machine::Machine;
 use proc_macro::TokenStream;
 use quote::quote;
 use syn::parse_macro_input;
 
+mod compat;
 mod error;
 mod fsm;
 
@@ -48,5 +63,43 @@ mod fsm;
 pub fn fsm(input: TokenStream) -> TokenStream {
     let machines: Machine = parse_macro_input!(input as Machine);
 
+    // checked here rather than when parsing, so the unit tests can expand
+    // machines that are never compiled
+    if let Err(err) = fsm::validation::check_derives(&machines.regions, &machines.events) {
+        return err.to_compile_error().into();
+    }
+
     quote!(#machines).into()
 }
+
+/// Generate the states of a [machine] crate `machine!`: a struct per
+/// variant and an enum over them with an extra `Error` state.
+///
+/// [machine]: https://docs.rs/machine
+#[proc_macro]
+pub fn machine(input: TokenStream) -> TokenStream {
+    let machine = parse_macro_input!(input as compat::machine::Machine);
+
+    quote!(#machine).into()
+}
+
+/// Generate the `on_<message>` methods of a [machine] crate `transitions!`.
+///
+/// [machine]: https://docs.rs/machine
+#[proc_macro]
+pub fn transitions(input: TokenStream) -> TokenStream {
+    let transitions = parse_macro_input!(input as compat::transitions::Transitions);
+
+    quote!(#transitions).into()
+}
+
+/// Generate the accessors and forwarded methods of a [machine] crate
+/// `methods!`.
+///
+/// [machine]: https://docs.rs/machine
+#[proc_macro]
+pub fn methods(input: TokenStream) -> TokenStream {
+    let methods = parse_macro_input!(input as compat::methods::Methods);
+
+    quote!(#methods).into()
+}
@@ -0,0 +1,397 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+
+use fsm_rs::fsm;
+
+/// A waker that does nothing, the futures are polled until they are ready.
+struct Noop;
+
+impl Wake for Noop {
+    fn wake(self: Arc<Self>) {}
+}
+
+/// A single-threaded executor, polling `future` until it is ready. Enough
+/// for futures that wake themselves before they return `Pending`, as the
+/// mock peripherals here do.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = Waker::from(Arc::new(Noop));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+/// Ready on the `polls`th poll, like a conversion or a timer in progress.
+struct Pending {
+    polls: u32,
+}
+
+impl Future for Pending {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.polls == 0 {
+            Poll::Ready(())
+        } else {
+            self.polls -= 1;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Adc {
+    millivolts: u16,
+    conversions: u32,
+}
+
+impl Adc {
+    async fn read(&mut self) -> u16 {
+        Pending { polls: 3 }.await;
+        self.conversions += 1;
+        self.millivolts
+    }
+}
+
+macro_rules! logged_state {
+    ($context:ident, $($name:ident),*) => {
+        $(
+            #[derive(Clone, Copy, Debug, Default, PartialEq)]
+            pub struct $name {}
+
+            impl $name {
+                pub async fn entry(&mut self, log: &mut $context, _: Option<&Event>) -> Result<(), &'static str> {
+                    Pending { polls: 1 }.await;
+                    log.entries.push(format!("entry {}", stringify!($name)));
+                    Ok(())
+                }
+
+                pub async fn exit(&self, log: &mut $context, _: Option<&Event>) -> Result<(), &'static str> {
+                    Pending { polls: 1 }.await;
+                    log.entries.push(format!("exit {}", stringify!($name)));
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! logged_event {
+    ($context:ident, $($name:ident),*) => {
+        $(
+            #[derive(Clone, Copy, Debug, PartialEq)]
+            pub struct $name;
+
+            impl $name {
+                pub async fn on(&self, log: &mut $context) -> Result<(), &'static str> {
+                    Pending { polls: 1 }.await;
+                    log.entries.push(format!("on {}", stringify!($name)));
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+mod trigger {
+    use super::*;
+
+    #[derive(Default)]
+    pub struct FireControl {
+        adc: Adc,
+        millis: u64,
+        entries: Vec<String>,
+        queue: Queue,
+    }
+
+    impl Clock for FireControl {
+        fn now(&self) -> u64 {
+            self.millis
+        }
+    }
+
+    impl EventQueue for FireControl {
+        fn queue(&mut self) -> &mut Queue {
+            &mut self.queue
+        }
+    }
+
+    /// Checks the battery before the trigger can fire.
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct Preloading {}
+
+    impl Preloading {
+        pub async fn entry(
+            &mut self,
+            fire_control: &mut FireControl,
+            _: Option<&Event>,
+        ) -> Result<(), &'static str> {
+            let millivolts = fire_control.adc.read().await;
+            fire_control.entries.push("entry Preloading".to_string());
+            if millivolts < 3000 {
+                Err("battery low")
+            } else {
+                Ok(())
+            }
+        }
+
+        pub async fn exit(
+            &self,
+            fire_control: &mut FireControl,
+            _: Option<&Event>,
+        ) -> Result<(), &'static str> {
+            fire_control.entries.push("exit Preloading".to_string());
+            Ok(())
+        }
+    }
+
+    logged_state!(FireControl, Ready, Armed, Firing);
+    logged_event!(FireControl, Half, Pull, Release, Check);
+
+    fsm! {
+        #[fsm(backend = "async", queue = 4)]
+
+        Context = FireControl;
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        States {
+            Ready = Ready,
+            Armed = Armed {
+                Preloading = Preloading,
+                Firing = Firing,
+            },
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        Events {
+            Half = Half,
+            Pull = Pull,
+            Release = Release,
+            Check = Check,
+        }
+
+        Transitions {
+            Half [
+                Ready => Preloading,
+            ],
+            Pull [
+                Preloading => Firing,
+            ],
+            Release [
+                Armed => Ready,
+            ],
+            Check [
+                Armed -> internal,
+            ],
+            Firing => Ready after 100ms,
+        }
+
+        Defer {
+            Ready: [Pull],
+        }
+    }
+
+    fn take(machine: &mut Machine) -> Vec<String> {
+        std::mem::take(&mut machine.context_mut().entries)
+    }
+
+    fn charged() -> Machine {
+        let mut fire_control = FireControl::default();
+        fire_control.adc.millivolts = 3700;
+        Machine::with_context(fire_control)
+    }
+
+    #[test]
+    fn callbacks_are_awaited_in_order() {
+        let mut machine = charged();
+
+        let result = block_on(machine.event(Event::Half(Half)));
+        assert_eq!(
+            result,
+            Ok(Transition::Taken {
+                from: State::Ready(Ready {}),
+                to: State::Preloading(Preloading {}),
+            })
+        );
+        assert_eq!(machine.context().adc.conversions, 1);
+
+        assert_eq!(
+            block_on(machine.event(Event::Check(Check))),
+            Ok(Transition::Internal {
+                state: State::Preloading(Preloading {}),
+            })
+        );
+        let _ = block_on(machine.event(Event::Release(Release)));
+        assert_eq!(
+            take(&mut machine),
+            vec![
+                "on Half",
+                "exit Ready",
+                "entry Armed",
+                "entry Preloading",
+                "on Check",
+                "on Release",
+                "exit Preloading",
+                "exit Armed",
+                "entry Ready",
+            ]
+        );
+    }
+
+    #[test]
+    fn a_failed_async_callback_is_returned() {
+        let mut machine = Machine::new();
+
+        assert_eq!(
+            block_on(machine.event(Event::Half(Half))),
+            Err(TransitionError::Callback("battery low"))
+        );
+        assert_eq!(
+            block_on(machine.event(Event::Half(Half))),
+            Err(TransitionError::Unhandled {
+                state: State::Preloading(Preloading {}),
+                event: Event::Half(Half),
+            })
+        );
+    }
+
+    #[test]
+    fn queued_and_timed_transitions_are_awaited() {
+        let mut machine = charged();
+
+        machine.post(Event::Pull(Pull)).unwrap();
+        machine.post(Event::Half(Half)).unwrap();
+        block_on(machine.run()).unwrap();
+        assert_eq!(machine.state(), State::Firing(Firing {}));
+
+        machine.context_mut().millis = 100;
+        let _ = take(&mut machine);
+        assert!(block_on(machine.tick(100)).is_some());
+        assert_eq!(machine.state(), State::Ready(Ready {}));
+        assert_eq!(
+            take(&mut machine),
+            vec!["exit Firing", "exit Armed", "entry Ready"]
+        );
+    }
+}
+
+mod regions {
+    use super::*;
+
+    #[derive(Default)]
+    pub struct Log {
+        millis: u64,
+        entries: Vec<String>,
+    }
+
+    impl Clock for Log {
+        fn now(&self) -> u64 {
+            self.millis
+        }
+    }
+
+    logged_state!(Log, Healthy, Low, Released, Pulled, Cooling);
+    logged_event!(Log, Drop, Charge, Pull, Release);
+
+    fsm! {
+        #[fsm(backend = "async")]
+
+        Context = Log;
+
+        Unhandled = Ignore;
+
+        Regions {
+            Power {
+                #[derive(Clone, Copy, Debug, PartialEq)]
+                States {
+                    Healthy = Healthy,
+                    Low = Low,
+                }
+
+                Transitions {
+                    Drop [
+                        Healthy => Low,
+                    ],
+                    Charge [
+                        Low => Healthy,
+                    ],
+                }
+            },
+            Trigger {
+                #[derive(Clone, Copy, Debug, PartialEq)]
+                States {
+                    Released = Released,
+                    Pulled = Pulled,
+                    Cooling = Cooling,
+                }
+
+                Transitions {
+                    Pull [
+                        Released => Pulled,
+                    ],
+                    Release [
+                        Pulled => Cooling,
+                    ],
+                    Cooling => Released after 50ms,
+                }
+            },
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        Events {
+            Drop = Drop,
+            Charge = Charge,
+            Pull = Pull,
+            Release = Release,
+        }
+    }
+
+    #[test]
+    fn every_region_is_awaited() {
+        let mut machine = Machine::new();
+
+        assert!(block_on(machine.event(Event::Drop(Drop))).is_ok());
+        assert!(block_on(machine.event(Event::Charge(Charge))).is_ok());
+        assert!(block_on(machine.event(Event::Drop(Drop))).is_ok());
+        assert!(block_on(machine.event(Event::Pull(Pull))).is_ok());
+        assert!(block_on(machine.event(Event::Release(Release))).is_ok());
+
+        let timeouts = block_on(machine.tick(50));
+        assert!(timeouts.trigger.is_some());
+        assert_eq!(
+            machine.state(),
+            State {
+                power: power::State::Low(Low {}),
+                trigger: trigger::State::Released(Released {}),
+            }
+        );
+        assert_eq!(
+            machine.context_mut().entries,
+            vec![
+                "on Drop",
+                "exit Healthy",
+                "entry Low",
+                "on Charge",
+                "exit Low",
+                "entry Healthy",
+                "on Drop",
+                "exit Healthy",
+                "entry Low",
+                "on Pull",
+                "exit Released",
+                "entry Pulled",
+                "on Release",
+                "exit Pulled",
+                "entry Cooling",
+                "exit Cooling",
+                "entry Released",
+            ]
+        );
+    }
+}
@@ -0,0 +1,40 @@
+//! The states and events the integration tests only need to be there: their
+//! callbacks always pass, whatever the context and the event type.
+
+#[allow(unused_macros)]
+macro_rules! state {
+    ($($name:ident),*) => {
+        $(
+            #[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+            #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+            pub struct $name {}
+
+            impl $name {
+                pub fn entry<C, E>(&mut self, _: &mut C, _: Option<&E>) -> Result<(), &'static str> {
+                    Ok(())
+                }
+
+                pub fn exit<C, E>(&self, _: &mut C, _: Option<&E>) -> Result<(), &'static str> {
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+#[allow(unused_macros)]
+macro_rules! event {
+    ($($name:ident),*) => {
+        $(
+            #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+            #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+            pub struct $name;
+
+            impl $name {
+                pub fn on<C>(&self, _: &mut C) -> Result<(), &'static str> {
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
@@ -0,0 +1,127 @@
+use fsm_rs::fsm;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Off {}
+
+impl Off {
+    pub fn entry(&mut self, motor: &mut Motor, _: Option<&Event>) -> Result<(), MotorError> {
+        motor.running = false;
+        motor.log.push("entry off");
+        Ok(())
+    }
+
+    pub fn exit(&self, motor: &mut Motor, _: Option<&Event>) -> Result<(), MotorError> {
+        motor.log.push("exit off");
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct On {}
+
+impl On {
+    pub fn entry(&mut self, motor: &mut Motor, _: Option<&Event>) -> Result<(), MotorError> {
+        if motor.stalled {
+            return Err(MotorError::Stalled {
+                milliamps: motor.milliamps,
+            });
+        }
+        motor.running = true;
+        motor.log.push("entry on");
+        Ok(())
+    }
+
+    pub fn exit(&self, motor: &mut Motor, _: Option<&Event>) -> Result<(), MotorError> {
+        motor.log.push("exit on");
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Toggle {}
+
+impl Toggle {
+    pub fn on(&self, motor: &mut Motor) -> Result<(), MotorError> {
+        motor.toggles += 1;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MotorError {
+    Stalled { milliamps: u16 },
+}
+
+#[derive(Debug, Default)]
+pub struct Motor {
+    running: bool,
+    stalled: bool,
+    milliamps: u16,
+    toggles: u32,
+    log: Vec<&'static str>,
+}
+
+fsm! {
+    Context = Motor;
+
+    Error = MotorError;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    States {
+        Off = Off,
+        On = On,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    Events {
+        Toggle = Toggle,
+    }
+
+    Transitions {
+        Toggle [
+            Off => On,
+            On => Off,
+        ],
+    }
+}
+
+#[test]
+fn callbacks_receive_the_context() {
+    let mut machine = Machine::new();
+
+    assert_eq!(
+        machine.event(Event::Toggle(Toggle {})),
+        Ok(Transition::Taken {
+            from: State::Off(Off {}),
+            to: State::On(On {}),
+        })
+    );
+    assert!(machine.context().running);
+
+    assert!(machine.event(Event::Toggle(Toggle {})).is_ok());
+    assert!(!machine.context().running);
+
+    assert_eq!(machine.context().toggles, 2);
+    assert_eq!(
+        machine.context().log,
+        vec!["exit off", "entry on", "exit on", "entry off"]
+    );
+}
+
+#[test]
+fn machine_can_be_built_with_a_context() {
+    let mut machine = Machine::with_context(Motor {
+        stalled: true,
+        milliamps: 1800,
+        ..Motor::default()
+    });
+
+    assert_eq!(
+        machine.event(Event::Toggle(Toggle {})),
+        Err(TransitionError::Callback(MotorError::Stalled {
+            milliamps: 1800
+        }))
+    );
+    assert_eq!(machine.state(), State::On(On {}));
+    assert!(!machine.context().running);
+}
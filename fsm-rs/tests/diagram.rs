@@ -0,0 +1,74 @@
+use fsm_rs::fsm;
+
+#[macro_use]
+mod common;
+
+state!(PowerOn, PostError, Ready, Armed, Preloading, Safe);
+event!(Post, Pull, Release);
+
+#[derive(Default)]
+pub struct Context {}
+
+impl Context {
+    fn post_passed(&self, _: &Post) -> bool {
+        true
+    }
+}
+
+fsm! {
+    Context = Context;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    States {
+        PowerOn = PowerOn,
+        PostError = PostError,
+        Ready = Ready,
+        Armed = Armed {
+            Preloading = Preloading,
+            Safe = Safe,
+        },
+    }
+
+    Final { PostError }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    Events {
+        Post = Post,
+        Pull = Pull,
+        Release = Release,
+    }
+
+    Transitions {
+        Post [
+            PowerOn => Ready if post_passed,
+            PowerOn => PostError,
+        ],
+        Pull [
+            Ready => Armed,
+            Preloading => Safe,
+        ],
+        Release [
+            Armed => Ready,
+        ],
+    }
+}
+
+// the diagrams are plain constants, usable in const context
+const DIAGRAMS: [&str; 3] = [Machine::DOT, Machine::MERMAID, Machine::PLANTUML];
+
+#[test]
+fn test_diagrams() {
+    let [dot, mermaid, plantuml] = DIAGRAMS;
+
+    assert!(dot.starts_with("digraph Machine {\n"));
+    assert!(dot.contains("    PostError [peripheries=2];\n"));
+    assert!(dot.contains("    PowerOn -> Ready [label=\"Post [post_passed]\"];\n"));
+    assert!(dot.contains("    Preloading -> Ready [label=\"Release\", ltail=cluster_Armed];\n"));
+
+    assert!(mermaid.starts_with("stateDiagram-v2\n    [*] --> PowerOn\n"));
+    assert!(mermaid.contains("    PostError --> [*]\n"));
+
+    assert!(plantuml.starts_with("@startuml\n"));
+    assert!(plantuml.contains("    Ready --> Armed : Pull\n"));
+    assert!(plantuml.ends_with("@enduml\n"));
+}
@@ -0,0 +1,167 @@
+//! The firmware's machine from src/fsm.rs, driven on the host.
+
+// the firmware isn't built with clippy, so its baseline names and impls
+// get a pass here
+#[allow(dead_code, clippy::upper_case_acronyms, clippy::derivable_impls)]
+#[path = "../../src/fsm.rs"]
+mod fsm;
+
+use std::cell::Cell;
+
+use fsm::*;
+
+/// Keeps the motor's state, and reads back whatever current the test set.
+#[derive(Default)]
+struct MockBoard {
+    motor: Cell<bool>,
+    milliamps: Cell<u16>,
+}
+
+impl Board for MockBoard {
+    fn set_motor(&self, on: bool) {
+        self.motor.set(on);
+    }
+
+    fn motor_milliamps(&self) -> u16 {
+        self.milliamps.get()
+    }
+}
+
+fn board() -> &'static MockBoard {
+    Box::leak(Box::default())
+}
+
+fn ready(board: &'static MockBoard) -> Machine {
+    let mut machine = Machine::with_context(FireControl::new(board));
+    assert!(machine
+        .event(Event::POST(Post { millivolts: 3300 }))
+        .is_ok());
+    assert_eq!(machine.state(), State::Ready(Ready {}));
+    machine
+}
+
+#[test]
+fn the_selector_picks_where_a_full_pull_goes() {
+    for (mode, fired) in [
+        (TriggerMode::SAFE, State::Safe(Safe {})),
+        (TriggerMode::SEMI, State::HalfAutoNFire(HalfAutoNFire {})),
+        (TriggerMode::AUTO, State::FullAutoFire(FullAutoFire {})),
+    ] {
+        let mut machine = ready(board());
+
+        let change = TriggerModeChange { mode };
+        assert!(machine.event(Event::TriggerModeChange(change)).is_ok());
+        assert_eq!(machine.state(), State::Ready(Ready {}));
+
+        assert!(machine
+            .event(Event::PullHalfTrigger(PullHalfTrigger {}))
+            .is_ok());
+        assert!(machine
+            .event(Event::PullFullTrigger(PullFullTrigger {}))
+            .is_ok());
+        assert_eq!(machine.state(), fired);
+
+        assert!(machine
+            .event(Event::ReleaseTrigger(ReleaseTrigger {}))
+            .is_ok());
+        assert_eq!(machine.state(), State::Ready(Ready {}));
+    }
+}
+
+#[test]
+fn the_selector_can_move_while_armed() {
+    let mut machine = ready(board());
+
+    assert!(machine
+        .event(Event::PullHalfTrigger(PullHalfTrigger {}))
+        .is_ok());
+    let change = TriggerModeChange {
+        mode: TriggerMode::AUTO,
+    };
+    assert!(machine.event(Event::TriggerModeChange(change)).is_ok());
+    assert_eq!(machine.state(), State::Preloading(Preloading {}));
+
+    assert!(machine
+        .event(Event::PullFullTrigger(PullFullTrigger {}))
+        .is_ok());
+    assert_eq!(machine.state(), State::FullAutoFire(FullAutoFire {}));
+}
+
+#[test]
+fn the_motor_runs_while_firing() {
+    for mode in [TriggerMode::SEMI, TriggerMode::AUTO] {
+        let board = board();
+        let mut machine = ready(board);
+
+        let change = TriggerModeChange { mode };
+        assert!(machine.event(Event::TriggerModeChange(change)).is_ok());
+        assert!(!board.motor.get());
+
+        assert!(machine
+            .event(Event::PullHalfTrigger(PullHalfTrigger {}))
+            .is_ok());
+        assert!(board.motor.get());
+
+        assert!(machine
+            .event(Event::PullFullTrigger(PullFullTrigger {}))
+            .is_ok());
+        assert!(board.motor.get());
+
+        assert!(machine
+            .event(Event::ReleaseTrigger(ReleaseTrigger {}))
+            .is_ok());
+        assert!(!board.motor.get());
+    }
+}
+
+#[test]
+fn an_overcurrent_stops_the_motor_until_it_clears() {
+    let board = board();
+    let mut machine = ready(board);
+
+    let change = TriggerModeChange {
+        mode: TriggerMode::AUTO,
+    };
+    assert!(machine.event(Event::TriggerModeChange(change)).is_ok());
+    assert!(machine
+        .event(Event::PullHalfTrigger(PullHalfTrigger {}))
+        .is_ok());
+    assert!(machine
+        .event(Event::PullFullTrigger(PullFullTrigger {}))
+        .is_ok());
+
+    // a reading under the limit is nothing new
+    board.milliamps.set(800);
+    assert!(machine.context_mut().sample_current().is_ok());
+    assert!(machine.run().is_ok());
+    assert_eq!(machine.state(), State::FullAutoFire(FullAutoFire {}));
+
+    board.milliamps.set(CURRENT_LIMIT_MILLIAMPS + 500);
+    assert!(machine.context_mut().sample_current().is_ok());
+    assert!(machine.run().is_ok());
+    assert!(matches!(machine.state(), State::Overcurrent(_)));
+    assert!(!board.motor.get());
+
+    board.milliamps.set(0);
+    assert!(machine.context_mut().sample_current().is_ok());
+    assert!(machine.run().is_ok());
+    assert_eq!(machine.state(), State::FullAutoFire(FullAutoFire {}));
+    assert!(board.motor.get());
+}
+
+#[test]
+fn the_motor_needs_a_board() {
+    let mut machine = Machine::new();
+    assert!(machine
+        .event(Event::POST(Post { millivolts: 3300 }))
+        .is_ok());
+
+    assert_eq!(
+        machine.event(Event::PullHalfTrigger(PullHalfTrigger {})),
+        Err(TransitionError::Callback(FireControlError::NoBoard))
+    );
+    assert_eq!(
+        machine.context_mut().sample_current(),
+        Err(FireControlError::NoBoard)
+    );
+}
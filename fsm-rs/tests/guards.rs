@@ -0,0 +1,148 @@
+use fsm_rs::fsm;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Idle {}
+
+impl Idle {
+    pub fn entry(&mut self, _: &mut Selector, _: Option<&Event>) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    pub fn exit(&self, _: &mut Selector, _: Option<&Event>) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Single {}
+
+impl Single {
+    pub fn entry(&mut self, _: &mut Selector, _: Option<&Event>) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    pub fn exit(&self, _: &mut Selector, _: Option<&Event>) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Burst {}
+
+impl Burst {
+    pub fn entry(&mut self, _: &mut Selector, _: Option<&Event>) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    pub fn exit(&self, _: &mut Selector, _: Option<&Event>) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pull {}
+
+impl Pull {
+    pub fn on(&self, _: &mut Selector) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Release {}
+
+impl Release {
+    pub fn on(&self, _: &mut Selector) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Safe,
+    Single,
+    Burst,
+}
+
+pub struct Selector {
+    mode: Mode,
+}
+
+impl Default for Selector {
+    fn default() -> Self {
+        Selector { mode: Mode::Safe }
+    }
+}
+
+impl Selector {
+    fn is_single(&self, _: &Pull) -> bool {
+        self.mode == Mode::Single
+    }
+
+    fn is_burst(&self, _: &Pull) -> bool {
+        self.mode == Mode::Burst
+    }
+}
+
+fsm! {
+    Context = Selector;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    States {
+        Idle = Idle,
+        Single = Single,
+        Burst = Burst,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    Events {
+        Pull = Pull,
+        Release = Release,
+    }
+
+    Transitions {
+        Pull [
+            Idle => Single if is_single,
+            Idle => Burst if is_burst,
+        ],
+        Release [
+            Single => Idle,
+            Burst => Idle,
+        ],
+    }
+}
+
+#[test]
+fn guards_are_evaluated_in_declaration_order() {
+    let mut machine = Machine::new();
+
+    machine.context_mut().mode = Mode::Burst;
+    assert_eq!(
+        machine.event(Event::Pull(Pull {})),
+        Ok(Transition::Taken {
+            from: State::Idle(Idle {}),
+            to: State::Burst(Burst {}),
+        })
+    );
+
+    assert!(machine.event(Event::Release(Release {})).is_ok());
+    assert_eq!(machine.state(), State::Idle(Idle {}));
+
+    machine.context_mut().mode = Mode::Single;
+    assert!(machine.event(Event::Pull(Pull {})).is_ok());
+    assert_eq!(machine.state(), State::Single(Single {}));
+}
+
+#[test]
+fn no_guard_passed_is_an_error() {
+    let mut machine = Machine::new();
+
+    assert_eq!(
+        machine.event(Event::Pull(Pull {})),
+        Err(TransitionError::NoGuardPassed {
+            state: State::Idle(Idle {}),
+            event: Event::Pull(Pull {}),
+        })
+    );
+    assert_eq!(machine.state(), State::Idle(Idle {}));
+}
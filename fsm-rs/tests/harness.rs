@@ -0,0 +1,348 @@
+use fsm_rs::fsm;
+
+#[macro_use]
+mod common;
+
+macro_rules! arbitrary {
+    ($($name:ident),*) => {
+        $(
+            impl harness::Arbitrary for $name {
+                fn arbitrary(_: &mut harness::Random) -> Self {
+                    $name
+                }
+            }
+        )*
+    };
+}
+
+mod trigger {
+    use super::*;
+
+    #[derive(Default)]
+    pub struct Trigger {
+        auto: bool,
+        millis: u64,
+    }
+
+    impl Trigger {
+        fn is_auto(&self, _: &Pull) -> bool {
+            self.auto
+        }
+
+        fn is_hot(&self, change: &CurrentChange) -> bool {
+            change.milliamps > 2000
+        }
+    }
+
+    impl Clock for Trigger {
+        fn now(&self) -> u64 {
+            self.millis
+        }
+    }
+
+    impl harness::Arbitrary for Trigger {
+        fn arbitrary(random: &mut harness::Random) -> Self {
+            Trigger {
+                auto: random.bool(),
+                millis: 0,
+            }
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct CurrentChange {
+        milliamps: u16,
+    }
+
+    impl CurrentChange {
+        pub fn on(&self, _: &mut Trigger) -> Result<(), &'static str> {
+            Ok(())
+        }
+    }
+
+    impl harness::Arbitrary for CurrentChange {
+        fn arbitrary(random: &mut harness::Random) -> Self {
+            CurrentChange {
+                milliamps: random.below(4000) as u16,
+            }
+        }
+    }
+
+    state!(Ready, Armed, Preloading, Single, FullAuto, Overcurrent);
+    event!(Half, Pull, Release);
+    arbitrary!(Half, Pull, Release);
+
+    fsm! {
+        #[fsm(harness)]
+
+        Context = Trigger;
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        States {
+            Ready = Ready,
+            Armed = Armed {
+                Preloading = Preloading,
+                Single = Single,
+                FullAuto = FullAuto,
+            },
+            Overcurrent = Overcurrent,
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        Events {
+            Half = Half,
+            Pull = Pull,
+            Release = Release,
+            CurrentChange = CurrentChange,
+        }
+
+        Transitions {
+            Half [
+                Ready => Preloading,
+            ],
+            Pull [
+                Preloading => FullAuto if is_auto,
+                Preloading => Single,
+                FullAuto -> internal,
+            ],
+            Release [
+                Armed => Ready,
+            ],
+            CurrentChange [
+                Armed => Overcurrent if is_hot,
+                Overcurrent => History(Armed),
+            ],
+            Preloading => Ready after 200ms,
+        }
+    }
+
+    #[test]
+    fn every_run_matches_the_transition_table() {
+        harness::check(harness::Config::default());
+    }
+
+    #[test]
+    fn runs_are_replayed_from_their_seed() {
+        use harness::{Arbitrary, Random, Step};
+
+        let mut random = Random::new(7);
+        let steps: Vec<Step> = (0..32).map(|_| Step::arbitrary(&mut random)).collect();
+        let mut random = Random::new(7);
+        let again: Vec<Step> = (0..32).map(|_| Step::arbitrary(&mut random)).collect();
+
+        assert_eq!(steps, again);
+        assert!(steps.contains(&Step::Tick));
+        assert_eq!(harness::replay(7, &steps), Ok(()));
+    }
+}
+
+mod jammed {
+    use super::*;
+
+    #[derive(Default)]
+    pub struct Gun {
+        shots: u32,
+    }
+
+    impl harness::Arbitrary for Gun {
+        fn arbitrary(_: &mut harness::Random) -> Self {
+            Gun::default()
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct Idle {}
+
+    impl Idle {
+        pub fn entry(&mut self, gun: &mut Gun, _: Option<&Event>) -> Result<(), &'static str> {
+            // the bug the harness has to find
+            if gun.shots == 3 {
+                panic!("jammed");
+            }
+            Ok(())
+        }
+
+        pub fn exit(&self, _: &mut Gun, _: Option<&Event>) -> Result<(), &'static str> {
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct Fire;
+
+    impl Fire {
+        pub fn on(&self, gun: &mut Gun) -> Result<(), &'static str> {
+            gun.shots += 1;
+            Ok(())
+        }
+    }
+
+    impl harness::Arbitrary for Fire {
+        fn arbitrary(_: &mut harness::Random) -> Self {
+            Fire
+        }
+    }
+
+    state!(Loaded);
+    event!(Load, Noise);
+    arbitrary!(Load, Noise);
+
+    fsm! {
+        #[fsm(harness)]
+
+        Context = Gun;
+
+        Initial = Idle;
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        States {
+            Idle = Idle,
+            Loaded = Loaded,
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        Events {
+            Load = Load,
+            Fire = Fire,
+            Noise = Noise,
+        }
+
+        Transitions {
+            Load [
+                Idle => Loaded,
+            ],
+            Fire [
+                Loaded => Idle,
+            ],
+            Noise [
+                [Idle, Loaded] -> internal,
+            ],
+        }
+    }
+
+    #[test]
+    fn failing_runs_are_shrunk() {
+        let result = std::panic::catch_unwind(|| harness::check(harness::Config::default()));
+        let payload = result.unwrap_err();
+        let message = payload.downcast_ref::<String>().unwrap();
+
+        let steps = "    Event(Load(Load))\n    Event(Fire(Fire))";
+        assert!(
+            message.ends_with(&format!(
+                "failed at step 5: panicked: jammed\n{}\n{}\n{}",
+                steps, steps, steps
+            )),
+            "{}",
+            message
+        );
+    }
+}
+
+mod regions {
+    use super::*;
+
+    #[derive(Default)]
+    pub struct Log {
+        millis: u64,
+    }
+
+    impl Log {
+        fn is_low(&self, change: &VoltageChange) -> bool {
+            change.millivolts < 3300
+        }
+    }
+
+    impl Clock for Log {
+        fn now(&self) -> u64 {
+            self.millis
+        }
+    }
+
+    impl harness::Arbitrary for Log {
+        fn arbitrary(_: &mut harness::Random) -> Self {
+            Log::default()
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct VoltageChange {
+        millivolts: u16,
+    }
+
+    impl VoltageChange {
+        pub fn on(&self, _: &mut Log) -> Result<(), &'static str> {
+            Ok(())
+        }
+    }
+
+    impl harness::Arbitrary for VoltageChange {
+        fn arbitrary(random: &mut harness::Random) -> Self {
+            VoltageChange {
+                millivolts: 3000 + random.below(600) as u16,
+            }
+        }
+    }
+
+    state!(Healthy, Low, Released, Pulled, Cooling);
+    event!(Pull, Release);
+    arbitrary!(Pull, Release);
+
+    fsm! {
+        #[fsm(backend = "table", harness)]
+
+        Context = Log;
+
+        Unhandled = Ignore;
+
+        Regions {
+            Power {
+                #[derive(Clone, Copy, Debug, PartialEq)]
+                States {
+                    Healthy = Healthy,
+                    Low = Low,
+                }
+
+                Transitions {
+                    VoltageChange [
+                        Healthy => Low if is_low,
+                        Low => Healthy,
+                    ],
+                }
+            },
+            Trigger {
+                #[derive(Clone, Copy, Debug, PartialEq)]
+                States {
+                    Released = Released,
+                    Pulled = Pulled,
+                    Cooling = Cooling,
+                }
+
+                Transitions {
+                    Pull [
+                        Released => Pulled,
+                    ],
+                    Release [
+                        Pulled => Cooling,
+                    ],
+                    Cooling => Released after 50ms,
+                }
+            },
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        Events {
+            VoltageChange = VoltageChange,
+            Pull = Pull,
+            Release = Release,
+        }
+    }
+
+    #[test]
+    fn every_region_matches_its_transition_table() {
+        harness::check(harness::Config {
+            runs: 64,
+            steps: 128,
+            seed: 42,
+        });
+    }
+}
@@ -1,5 +1,4 @@
-#[macro_use]
-extern crate machine;
+use fsm_rs::{machine, transitions};
 
 pub trait Transitions {
     fn next(&mut self);
@@ -0,0 +1,196 @@
+use fsm_rs::fsm;
+
+#[macro_use]
+mod common;
+
+#[derive(Default)]
+pub struct Log(Vec<String>);
+
+impl Log {
+    fn take(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.0)
+    }
+}
+
+macro_rules! logged_state {
+    ($($name:ident),*) => {
+        $(
+            #[derive(Clone, Copy, Debug, Default, PartialEq)]
+            pub struct $name {}
+
+            impl $name {
+                pub fn entry(&mut self, log: &mut Log, _: Option<&Event>) -> Result<(), &'static str> {
+                    log.0.push(format!("entry {}", stringify!($name)));
+                    Ok(())
+                }
+
+                pub fn exit(&self, log: &mut Log, _: Option<&Event>) -> Result<(), &'static str> {
+                    log.0.push(format!("exit {}", stringify!($name)));
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+logged_state!(Idle, Armed, Loading, Firing, Single, Burst, Fault);
+event!(Arm, Fire, Switch, Overload, Reset, Rearm);
+
+fsm! {
+    Context = Log;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    States {
+        Idle = Idle,
+        Armed = Armed {
+            Loading = Loading,
+            Firing = Firing {
+                Single = Single,
+                Burst = Burst,
+            },
+        },
+        Fault = Fault,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    Events {
+        Arm = Arm,
+        Fire = Fire,
+        Switch = Switch,
+        Overload = Overload,
+        Reset = Reset,
+        Rearm = Rearm,
+    }
+
+    Transitions {
+        Arm [
+            Idle => Armed,
+        ],
+        Fire [
+            Loading => Firing,
+        ],
+        Switch [
+            Single => Burst,
+            Burst => Single,
+        ],
+        Overload [
+            Armed => Fault,
+        ],
+        Reset [
+            Fault => Idle,
+            Firing => Loading,
+            Armed => Idle,
+        ],
+        Rearm [
+            Armed => Armed,
+        ],
+    }
+}
+
+#[test]
+fn entering_a_superstate_enters_its_first_child() {
+    let mut machine = Machine::new();
+
+    assert!(machine.event(Event::Arm(Arm)).is_ok());
+    assert_eq!(machine.state(), State::Loading(Loading {}));
+    assert_eq!(
+        machine.context_mut().take(),
+        vec!["exit Idle", "entry Armed", "entry Loading"]
+    );
+
+    assert!(machine.event(Event::Fire(Fire)).is_ok());
+    assert_eq!(machine.state(), State::Single(Single {}));
+    assert_eq!(
+        machine.context_mut().take(),
+        vec!["exit Loading", "entry Firing", "entry Single"]
+    );
+}
+
+#[test]
+fn sibling_transition_keeps_the_superstate() {
+    let mut machine = Machine::new();
+    machine.event(Event::Arm(Arm)).unwrap();
+    machine.event(Event::Fire(Fire)).unwrap();
+    let _ = machine.context_mut().take();
+
+    assert!(machine.event(Event::Switch(Switch)).is_ok());
+    assert_eq!(machine.state(), State::Burst(Burst {}));
+    assert_eq!(
+        machine.context_mut().take(),
+        vec!["exit Single", "entry Burst"]
+    );
+}
+
+#[test]
+fn superstate_transition_applies_to_every_child() {
+    let mut machine = Machine::new();
+    machine.event(Event::Arm(Arm)).unwrap();
+    machine.event(Event::Fire(Fire)).unwrap();
+    machine.event(Event::Switch(Switch)).unwrap();
+    let _ = machine.context_mut().take();
+
+    assert_eq!(
+        machine.event(Event::Overload(Overload)),
+        Ok(Transition::Taken {
+            from: State::Burst(Burst {}),
+            to: State::Fault(Fault {}),
+        })
+    );
+    assert_eq!(
+        machine.context_mut().take(),
+        vec!["exit Burst", "exit Firing", "exit Armed", "entry Fault"]
+    );
+
+    machine.event(Event::Reset(Reset)).unwrap();
+    machine.event(Event::Arm(Arm)).unwrap();
+    let _ = machine.context_mut().take();
+
+    assert!(machine.event(Event::Overload(Overload)).is_ok());
+    assert_eq!(
+        machine.context_mut().take(),
+        vec!["exit Loading", "exit Armed", "entry Fault"]
+    );
+}
+
+#[test]
+fn innermost_transition_wins() {
+    let mut machine = Machine::new();
+    machine.event(Event::Arm(Arm)).unwrap();
+    machine.event(Event::Fire(Fire)).unwrap();
+    let _ = machine.context_mut().take();
+
+    assert!(machine.event(Event::Reset(Reset)).is_ok());
+    assert_eq!(machine.state(), State::Loading(Loading {}));
+    assert_eq!(
+        machine.context_mut().take(),
+        vec!["exit Single", "exit Firing", "entry Loading"]
+    );
+
+    assert!(machine.event(Event::Reset(Reset)).is_ok());
+    assert_eq!(machine.state(), State::Idle(Idle {}));
+    assert_eq!(
+        machine.context_mut().take(),
+        vec!["exit Loading", "exit Armed", "entry Idle"]
+    );
+}
+
+#[test]
+fn superstate_self_transition_is_external() {
+    let mut machine = Machine::new();
+    machine.event(Event::Arm(Arm)).unwrap();
+    machine.event(Event::Fire(Fire)).unwrap();
+    let _ = machine.context_mut().take();
+
+    assert!(machine.event(Event::Rearm(Rearm)).is_ok());
+    assert_eq!(machine.state(), State::Loading(Loading {}));
+    assert_eq!(
+        machine.context_mut().take(),
+        vec![
+            "exit Single",
+            "exit Firing",
+            "exit Armed",
+            "entry Armed",
+            "entry Loading"
+        ]
+    );
+}
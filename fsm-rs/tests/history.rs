@@ -0,0 +1,277 @@
+use fsm_rs::fsm;
+
+#[macro_use]
+mod common;
+
+#[derive(Default)]
+pub struct Log(Vec<String>);
+
+impl Log {
+    fn take(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.0)
+    }
+}
+
+macro_rules! logged_state {
+    ($($name:ident),*) => {
+        $(
+            #[derive(Clone, Copy, Debug, Default, PartialEq)]
+            pub struct $name {}
+
+            impl $name {
+                pub fn entry(&mut self, log: &mut Log, _: Option<&Event>) -> Result<(), &'static str> {
+                    log.0.push(format!("entry {}", stringify!($name)));
+                    Ok(())
+                }
+
+                pub fn exit(&self, log: &mut Log, _: Option<&Event>) -> Result<(), &'static str> {
+                    log.0.push(format!("exit {}", stringify!($name)));
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+logged_state!(Idle, Armed, Loading, Firing, Single, Burst, Fault);
+event!(Arm, Fire, Switch, Overload, Recover, DeepRecover, Reset);
+
+fsm! {
+    Context = Log;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    States {
+        Idle = Idle,
+        Armed = Armed {
+            Loading = Loading,
+            Firing = Firing {
+                Single = Single,
+                Burst = Burst,
+            },
+        },
+        Fault = Fault,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    Events {
+        Arm = Arm,
+        Fire = Fire,
+        Switch = Switch,
+        Overload = Overload,
+        Recover = Recover,
+        DeepRecover = DeepRecover,
+        Reset = Reset,
+    }
+
+    Transitions {
+        Arm [
+            Idle => Armed,
+        ],
+        Fire [
+            Loading => Firing,
+        ],
+        Switch [
+            Single => Burst,
+        ],
+        Overload [
+            [Idle, Armed] => Fault,
+        ],
+        Recover [
+            Fault => History(Armed),
+        ],
+        DeepRecover [
+            Fault => DeepHistory(Armed),
+        ],
+        Reset [
+            Armed => Idle,
+        ],
+    }
+}
+
+fn burst_then_overload() -> Machine {
+    let mut machine = Machine::new();
+    machine.event(Event::Arm(Arm)).unwrap();
+    machine.event(Event::Fire(Fire)).unwrap();
+    machine.event(Event::Switch(Switch)).unwrap();
+    machine.event(Event::Overload(Overload)).unwrap();
+    let _ = machine.context_mut().take();
+    machine
+}
+
+#[test]
+fn history_without_memory_enters_the_first_child() {
+    let mut machine = Machine::new();
+    machine.event(Event::Overload(Overload)).unwrap();
+    let _ = machine.context_mut().take();
+
+    assert!(machine.event(Event::Recover(Recover)).is_ok());
+    assert_eq!(machine.state(), State::Loading(Loading {}));
+    assert_eq!(
+        machine.context_mut().take(),
+        vec!["exit Fault", "entry Armed", "entry Loading"]
+    );
+}
+
+#[test]
+fn shallow_history_resumes_the_last_child() {
+    let mut machine = burst_then_overload();
+
+    assert_eq!(
+        machine.event(Event::Recover(Recover)),
+        Ok(Transition::Taken {
+            from: State::Fault(Fault {}),
+            to: State::Single(Single {}),
+        })
+    );
+    assert_eq!(
+        machine.context_mut().take(),
+        vec!["exit Fault", "entry Armed", "entry Firing", "entry Single"]
+    );
+}
+
+#[test]
+fn deep_history_resumes_the_last_leaf() {
+    let mut machine = burst_then_overload();
+
+    assert_eq!(
+        machine.event(Event::DeepRecover(DeepRecover)),
+        Ok(Transition::Taken {
+            from: State::Fault(Fault {}),
+            to: State::Burst(Burst {}),
+        })
+    );
+    assert_eq!(
+        machine.context_mut().take(),
+        vec!["exit Fault", "entry Armed", "entry Firing", "entry Burst"]
+    );
+}
+
+#[test]
+fn history_remembers_the_latest_exit() {
+    let mut machine = burst_then_overload();
+    machine.event(Event::DeepRecover(DeepRecover)).unwrap();
+    machine.event(Event::Reset(Reset)).unwrap();
+    machine.event(Event::Arm(Arm)).unwrap();
+    machine.event(Event::Overload(Overload)).unwrap();
+
+    assert!(machine.event(Event::DeepRecover(DeepRecover)).is_ok());
+    assert_eq!(machine.state(), State::Loading(Loading {}));
+}
+
+#[test]
+fn history_targets_list_every_state_they_may_resume() {
+    let targets = |event: EventId| {
+        Machine::TRANSITIONS
+            .iter()
+            .find(|(from, on, _)| *from == StateId::Fault && *on == event)
+            .map(|(_, _, to)| *to)
+    };
+
+    assert_eq!(
+        targets(EventId::Recover),
+        Some(&[StateId::Loading, StateId::Single][..])
+    );
+    assert_eq!(
+        targets(EventId::DeepRecover),
+        Some(&[StateId::Loading, StateId::Single, StateId::Burst][..])
+    );
+}
+
+// superstates named after the fields every machine has, and after the history
+// and the delay of another superstate
+mod clashing_names {
+    use super::Log;
+    use fsm_rs::fsm;
+
+    logged_state!(
+        Idle,
+        Context,
+        CurrentState,
+        Watched,
+        Foo,
+        FooHistory,
+        FooDeadline
+    );
+    logged_state!(Loading, Firing, Fault);
+    event!(Fire, Overload, Recover);
+
+    impl Clock for Log {
+        fn now(&self) -> u64 {
+            0
+        }
+    }
+
+    fsm! {
+        Context = Log;
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        States {
+            Idle = Idle,
+            Context = Context {
+                CurrentState = CurrentState {
+                    Observer = Watched {
+                        Foo = Foo {
+                            Loading = Loading,
+                            Firing = Firing,
+                        },
+                    },
+                },
+            },
+            FooHistory = FooHistory {
+                FooDeadline = FooDeadline {
+                    Fault = Fault,
+                },
+            },
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        Events {
+            Fire = Fire,
+            Overload = Overload,
+            Recover = Recover,
+        }
+
+        Transitions {
+            Fire [
+                Idle => Loading,
+                Loading => Firing,
+            ],
+            Overload [
+                Foo => Fault,
+            ],
+            Recover [
+                Fault => History(Foo),
+            ],
+            Foo => Idle after 1s,
+        }
+    }
+
+    #[test]
+    fn generated_fields_dont_clash_with_state_names() {
+        let mut machine = Machine::new();
+        machine.event(Event::Fire(Fire)).unwrap();
+        machine.event(Event::Fire(Fire)).unwrap();
+        machine.event(Event::Overload(Overload)).unwrap();
+        let _ = machine.context_mut().take();
+
+        assert!(machine.event(Event::Recover(Recover)).is_ok());
+        assert_eq!(machine.state(), State::Firing(Firing {}));
+        assert_eq!(machine.next_deadline(), Some(1000));
+        assert_eq!(
+            machine.context_mut().take(),
+            vec![
+                "exit Fault",
+                "exit FooDeadline",
+                "exit FooHistory",
+                "entry Context",
+                "entry CurrentState",
+                "entry Watched",
+                "entry Foo",
+                "entry Firing",
+            ]
+        );
+
+        assert!(machine.tick(1000).is_some());
+        assert_eq!(machine.state(), State::Idle(Idle {}));
+    }
+}
@@ -1,5 +1,4 @@
-#[macro_use]
-extern crate machine;
+use fsm_rs::{machine, methods, transitions};
 
 machine!(
     enum HttpRequest {
@@ -152,3 +151,57 @@ impl HasHostAndLength {
         }
     }
 }
+
+fn headers_read(length: Option<LengthInfo>) -> HttpRequest {
+    let mut request = HttpRequest::initial().on_request_line(RequestLine);
+    request = request.on_host_header(HostHeader("example.com".to_string()));
+    if let Some(length) = length {
+        request = request.on_length_header(LengthHeader(length));
+    }
+    request.on_header_end(HeaderEnd)
+}
+
+#[test]
+fn request_without_length() {
+    match headers_read(None) {
+        HttpRequest::Request(Request { request, host }) => {
+            assert_eq!(request, RequestLine);
+            assert_eq!(host, "example.com");
+        }
+        _ => panic!("expected Request"),
+    }
+}
+
+#[test]
+fn request_with_length_has_a_body() {
+    let request = headers_read(Some(LengthInfo::Length(42)));
+    assert_eq!(request.host(), Some("example.com"));
+
+    match request {
+        HttpRequest::RequestWithBody(RequestWithBody {
+            request, remaining, ..
+        }) => {
+            assert_eq!(request, RequestLine);
+            assert_eq!(remaining, 42);
+        }
+        _ => panic!("expected RequestWithBody"),
+    }
+}
+
+#[test]
+fn chunked_request_has_chunks() {
+    match headers_read(Some(LengthInfo::Chunked)) {
+        HttpRequest::RequestWithChunks(RequestWithChunks { request, chunk, .. }) => {
+            assert_eq!(request, RequestLine);
+            assert_eq!(chunk, ChunkState);
+        }
+        _ => panic!("expected RequestWithChunks"),
+    }
+}
+
+#[test]
+fn unexpected_header_is_an_error() {
+    let request = HttpRequest::initial().on_header_end(HeaderEnd);
+    assert!(matches!(request, HttpRequest::Error));
+    assert_eq!(request.host(), None);
+}
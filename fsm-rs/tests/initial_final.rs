@@ -0,0 +1,84 @@
+use fsm_rs::fsm;
+
+#[macro_use]
+mod common;
+
+state!(Crashed, Booting, Loader, Kernel, Running, Halted);
+event!(Boot, Crash, Halt);
+
+#[derive(Default)]
+pub struct Context {}
+
+fsm! {
+    Context = Context;
+
+    Initial = Booting;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    States {
+        Crashed = Crashed,
+        Booting = Booting {
+            Loader = Loader,
+            Kernel = Kernel,
+        },
+        Running = Running,
+        Halted = Halted,
+    }
+
+    Final { Crashed, Halted }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    Events {
+        Boot = Boot,
+        Crash = Crash,
+        Halt = Halt,
+    }
+
+    Transitions {
+        Boot [
+            Loader => Kernel,
+            Kernel => Running,
+        ],
+        Crash [
+            * => Crashed,
+        ],
+        Halt [
+            Running => Halted,
+        ],
+    }
+}
+
+#[test]
+fn test_initial_state_is_declared() {
+    let machine = Machine::new();
+
+    // entering a superstate starts in its first child
+    assert_eq!(machine.state(), State::Loader(Loader {}));
+    assert!(!machine.is_finished());
+}
+
+#[test]
+fn test_final_states() {
+    let mut machine = Machine::new();
+
+    assert!(machine.event(Event::Boot(Boot)).is_ok());
+    assert!(machine.event(Event::Boot(Boot)).is_ok());
+    assert!(machine.event(Event::Halt(Halt)).is_ok());
+    assert_eq!(machine.state(), State::Halted(Halted {}));
+    assert!(machine.is_finished());
+
+    // the wildcard leaves out final states
+    assert_eq!(
+        machine.event(Event::Crash(Crash)),
+        Err(TransitionError::Unhandled {
+            state: State::Halted(Halted {}),
+            event: Event::Crash(Crash),
+        })
+    );
+
+    let mut machine = Machine::new();
+
+    assert!(machine.event(Event::Crash(Crash)).is_ok());
+    assert_eq!(machine.state(), State::Crashed(Crashed {}));
+    assert!(machine.is_finished());
+}
@@ -0,0 +1,185 @@
+use fsm_rs::fsm;
+
+#[derive(Default)]
+pub struct Log {
+    entries: Vec<String>,
+    budget: u8,
+}
+
+impl Log {
+    fn take(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.entries)
+    }
+
+    pub fn has_budget(&self, _: &Shot) -> bool {
+        self.budget > 0
+    }
+}
+
+macro_rules! logged_state {
+    ($($name:ident),*) => {
+        $(
+            #[derive(Clone, Copy, Debug, Default, PartialEq)]
+            pub struct $name {}
+
+            impl $name {
+                pub fn entry(&mut self, log: &mut Log, _: Option<&Event>) -> Result<(), &'static str> {
+                    log.entries.push(format!("entry {}", stringify!($name)));
+                    Ok(())
+                }
+
+                pub fn exit(&self, log: &mut Log, _: Option<&Event>) -> Result<(), &'static str> {
+                    log.entries.push(format!("exit {}", stringify!($name)));
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! logged_event {
+    ($($name:ident),*) => {
+        $(
+            #[derive(Clone, Copy, Debug, PartialEq)]
+            pub struct $name;
+
+            impl $name {
+                pub fn on(&self, log: &mut Log) -> Result<(), &'static str> {
+                    log.entries.push(format!("on {}", stringify!($name)));
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Shot;
+
+impl Shot {
+    pub fn on(&self, log: &mut Log) -> Result<(), &'static str> {
+        log.entries.push("on Shot".to_string());
+        log.budget = log.budget.saturating_sub(1);
+        Ok(())
+    }
+}
+
+logged_state!(Idle, Armed, Loading, Firing);
+logged_event!(Arm, Fire, Tick, Reload);
+
+fsm! {
+    Context = Log;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    States {
+        Idle = Idle,
+        Armed = Armed {
+            Loading = Loading,
+            Firing = Firing,
+        },
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    Events {
+        Arm = Arm,
+        Fire = Fire,
+        Tick = Tick,
+        Shot = Shot,
+        Reload = Reload,
+    }
+
+    Transitions {
+        Arm [
+            Idle => Armed,
+        ],
+        Fire [
+            Loading => Firing,
+        ],
+        Tick [
+            Armed -> internal,
+        ],
+        Shot [
+            Firing -> internal if has_budget,
+            Firing => Loading,
+        ],
+        Reload [
+            Idle -> internal,
+        ],
+    }
+}
+
+#[test]
+fn internal_transition_runs_only_the_action() {
+    let mut machine = Machine::new();
+
+    assert_eq!(
+        machine.event(Event::Reload(Reload)),
+        Ok(Transition::Internal {
+            state: State::Idle(Idle {}),
+        })
+    );
+    assert_eq!(machine.state(), State::Idle(Idle {}));
+    assert_eq!(machine.context_mut().take(), vec!["on Reload"]);
+}
+
+#[test]
+fn internal_superstate_transition_keeps_the_current_leaf() {
+    let mut machine = Machine::new();
+    machine.event(Event::Arm(Arm)).unwrap();
+    machine.event(Event::Fire(Fire)).unwrap();
+    let _ = machine.context_mut().take();
+
+    assert_eq!(
+        machine.event(Event::Tick(Tick)),
+        Ok(Transition::Internal {
+            state: State::Firing(Firing {}),
+        })
+    );
+    assert_eq!(machine.state(), State::Firing(Firing {}));
+    assert_eq!(machine.context_mut().take(), vec!["on Tick"]);
+}
+
+#[test]
+fn guarded_internal_transition_falls_back_to_external() {
+    let mut machine = Machine::new();
+    machine.event(Event::Arm(Arm)).unwrap();
+    machine.event(Event::Fire(Fire)).unwrap();
+    machine.context_mut().budget = 1;
+    let _ = machine.context_mut().take();
+
+    assert_eq!(
+        machine.event(Event::Shot(Shot)),
+        Ok(Transition::Internal {
+            state: State::Firing(Firing {}),
+        })
+    );
+    assert_eq!(machine.context_mut().take(), vec!["on Shot"]);
+
+    assert_eq!(
+        machine.event(Event::Shot(Shot)),
+        Ok(Transition::Taken {
+            from: State::Firing(Firing {}),
+            to: State::Loading(Loading {}),
+        })
+    );
+    assert_eq!(
+        machine.context_mut().take(),
+        vec!["on Shot", "exit Firing", "entry Loading"]
+    );
+}
+
+#[test]
+fn internal_transitions_are_permitted_events() {
+    let mut machine = Machine::new();
+
+    assert!(machine.can_handle(&Event::Reload(Reload)));
+    assert!(!machine.can_handle(&Event::Tick(Tick)));
+
+    machine.event(Event::Arm(Arm)).unwrap();
+
+    assert!(machine.can_handle(&Event::Tick(Tick)));
+    assert_eq!(
+        machine.permitted_events().collect::<Vec<_>>(),
+        vec![EventId::Fire, EventId::Tick]
+    );
+}
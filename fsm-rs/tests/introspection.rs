@@ -0,0 +1,123 @@
+use fsm_rs::fsm;
+
+#[macro_use]
+mod common;
+
+macro_rules! rounds_event {
+    ($($name:ident),*) => {
+        $(
+            #[derive(Clone, Copy, Debug, PartialEq)]
+            pub struct $name {
+                pub rounds: u8,
+            }
+
+            impl $name {
+                pub fn on<C>(&self, _: &mut C) -> Result<(), &'static str> {
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+state!(Ready, Armed, Single, Burst, Fault);
+rounds_event!(Arm, Fire, Overheat);
+
+#[derive(Default)]
+pub struct Gun {}
+
+impl Gun {
+    fn is_single(&self, fire: &Fire) -> bool {
+        fire.rounds == 1
+    }
+}
+
+fsm! {
+    Context = Gun;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    States {
+        Ready = Ready,
+        Armed = Armed {
+            Single = Single,
+            Burst = Burst,
+        },
+        Fault = Fault,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    Events {
+        Arm = Arm,
+        Fire = Fire,
+        Overheat = Overheat,
+    }
+
+    Transitions {
+        Arm [
+            Ready => Armed,
+        ],
+        Fire [
+            Single => Burst if is_single,
+        ],
+        Overheat [
+            Armed => Fault,
+        ],
+    }
+}
+
+#[test]
+fn test_state_and_event_names() {
+    assert_eq!(
+        State::ALL,
+        &[
+            StateId::Ready,
+            StateId::Single,
+            StateId::Burst,
+            StateId::Fault
+        ]
+    );
+    assert_eq!(State::NAMES, &["Ready", "Single", "Burst", "Fault"]);
+    assert_eq!(Event::NAMES, &["Arm", "Fire", "Overheat"]);
+
+    assert_eq!(State::Burst(Burst {}).name(), "Burst");
+    assert_eq!(Event::Fire(Fire { rounds: 1 }).id(), EventId::Fire);
+    assert_eq!(EventId::Overheat.name(), "Overheat");
+}
+
+#[test]
+fn test_transitions_table() {
+    // superstate transitions are listed for every leaf they apply to
+    assert_eq!(
+        Machine::TRANSITIONS,
+        &[
+            (StateId::Ready, EventId::Arm, &[StateId::Single][..]),
+            (StateId::Single, EventId::Fire, &[StateId::Burst][..]),
+            (StateId::Single, EventId::Overheat, &[StateId::Fault][..]),
+            (StateId::Burst, EventId::Overheat, &[StateId::Fault][..]),
+        ]
+    );
+}
+
+#[test]
+fn test_machine_queries() {
+    let mut machine = Machine::new();
+
+    assert_eq!(
+        machine.permitted_events().collect::<Vec<_>>(),
+        vec![EventId::Arm]
+    );
+    assert!(machine.can_handle(&Event::Arm(Arm { rounds: 0 })));
+    assert!(!machine.can_handle(&Event::Fire(Fire { rounds: 1 })));
+
+    assert!(machine.event(Event::Arm(Arm { rounds: 0 })).is_ok());
+
+    assert_eq!(
+        machine.permitted_events().collect::<Vec<_>>(),
+        vec![EventId::Fire, EventId::Overheat]
+    );
+
+    // guards are evaluated against the event data
+    assert!(machine.can_handle(&Event::Fire(Fire { rounds: 1 })));
+    assert!(!machine.can_handle(&Event::Fire(Fire { rounds: 3 })));
+    assert!(machine.can_handle(&Event::Overheat(Overheat { rounds: 0 })));
+}
@@ -0,0 +1,85 @@
+use fsm_rs::fsm;
+
+#[macro_use]
+mod common;
+
+#[derive(Default)]
+pub struct Trigger {
+    auto: bool,
+}
+
+impl Trigger {
+    fn is_auto(&self, _: &Pull) -> bool {
+        self.auto
+    }
+}
+
+state!(Ready, Armed, Preloading, Single, FullAuto, Overcurrent);
+event!(Half, Pull, Release, Current);
+
+fsm! {
+    Context = Trigger;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    States {
+        Ready = Ready,
+        Armed = Armed {
+            Preloading = Preloading,
+            Single = Single,
+            FullAuto = FullAuto,
+        },
+        Overcurrent = Overcurrent,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    Events {
+        Half = Half,
+        Pull = Pull,
+        Release = Release,
+        Current = Current,
+    }
+
+    Transitions {
+        Half [
+            Ready => Preloading,
+        ],
+        Pull [
+            Preloading => FullAuto if is_auto,
+            Preloading => Single,
+        ],
+        Release [
+            Armed => Ready,
+        ],
+        Current [
+            Armed => Overcurrent,
+            Overcurrent => DeepHistory(Armed),
+        ],
+    }
+
+    // Overcurrent resumes FullAuto, but only after Preloading led to it
+    Invariants {
+        reachable(FullAuto, Overcurrent),
+        forbidden(Ready => FullAuto),
+        through(Ready => FullAuto, Preloading),
+        can_reach(Ready),
+        no_deadlock,
+    }
+}
+
+#[test]
+fn full_auto_is_entered_through_preloading() {
+    let mut machine = Machine::new();
+    machine.context_mut().auto = true;
+
+    assert!(machine.event(Event::Pull(Pull)).is_err());
+    machine.event(Event::Half(Half)).unwrap();
+    machine.event(Event::Pull(Pull)).unwrap();
+    assert_eq!(machine.state(), State::FullAuto(FullAuto {}));
+
+    machine.event(Event::Current(Current)).unwrap();
+    machine.event(Event::Current(Current)).unwrap();
+    assert_eq!(machine.state(), State::FullAuto(FullAuto {}));
+
+    machine.event(Event::Release(Release)).unwrap();
+    assert_eq!(machine.state(), State::Ready(Ready {}));
+}
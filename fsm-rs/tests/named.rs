@@ -0,0 +1,227 @@
+use fsm_rs::fsm;
+
+#[macro_use]
+mod common;
+
+/// What the machines need of the hardware, mocked on the host.
+pub trait Board {
+    fn fire(&mut self);
+}
+
+#[derive(Debug, Default)]
+pub struct MockBoard {
+    shots: u32,
+}
+
+impl Board for MockBoard {
+    fn fire(&mut self) {
+        self.shots += 1;
+    }
+}
+
+#[derive(Default)]
+pub struct FireControl<B: Board> {
+    board: B,
+    millis: u64,
+    hot: bool,
+    queue: TriggerFsmQueue,
+}
+
+impl<B: Board> FireControl<B> {
+    pub fn is_cool(&self, _: &Pull) -> bool {
+        !self.hot
+    }
+}
+
+impl<B: Board> TriggerFsmClock for FireControl<B> {
+    fn now(&self) -> u64 {
+        self.millis
+    }
+}
+
+impl<B: Board> PowerFsmClock for FireControl<B> {
+    fn now(&self) -> u64 {
+        self.millis
+    }
+}
+
+impl<B: Board> TriggerFsmEventQueue for FireControl<B> {
+    fn queue(&mut self) -> &mut TriggerFsmQueue {
+        &mut self.queue
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Firing {}
+
+impl Firing {
+    pub fn entry<B: Board>(
+        &mut self,
+        fire_control: &mut FireControl<B>,
+        _: Option<&TriggerFsmEvent>,
+    ) -> Result<(), &'static str> {
+        fire_control.board.fire();
+        Ok(())
+    }
+
+    pub fn exit<B: Board>(
+        &self,
+        _: &mut FireControl<B>,
+        _: Option<&TriggerFsmEvent>,
+    ) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+state!(Ready, Preloading);
+state!(Healthy, Low, Cool, Hot, Cooling);
+event!(Half, Pull, Release, Drop, Charge, Heat);
+
+fsm! {
+    #[fsm(queue = 4)]
+
+    Name = TriggerFsm<B: Board>;
+
+    Context = FireControl<B>;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    States {
+        Ready = Ready,
+        Preloading = Preloading,
+        Firing = Firing,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    Events {
+        Half = Half,
+        Pull = Pull,
+        Release = Release,
+    }
+
+    Transitions {
+        Half [
+            Ready => Preloading,
+        ],
+        Pull [
+            Preloading => Firing if is_cool,
+        ],
+        Release [
+            Preloading => Ready,
+        ],
+        Firing => Ready after 100ms,
+    }
+
+    Defer {
+        Ready: [Pull],
+    }
+}
+
+fsm! {
+    #[fsm(backend = "table")]
+
+    Name = PowerFsm<B> where B: Board;
+
+    Context = FireControl<B>;
+
+    Unhandled = Ignore;
+
+    Regions {
+        Battery {
+            #[derive(Clone, Copy, Debug, PartialEq)]
+            States {
+                Healthy = Healthy,
+                Low = Low,
+            }
+
+            Transitions {
+                Drop [
+                    Healthy => Low,
+                ],
+                Charge [
+                    Low => Healthy,
+                ],
+            }
+        },
+        Thermal {
+            #[derive(Clone, Copy, Debug, PartialEq)]
+            States {
+                Cool = Cool,
+                Hot = Hot,
+                Cooling = Cooling,
+            }
+
+            Transitions {
+                Heat [
+                    Cool => Hot,
+                ],
+                Charge [
+                    Hot => Cooling,
+                ],
+                Cooling => Cool after 50ms,
+            }
+        },
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    Events {
+        Drop = Drop,
+        Charge = Charge,
+        Heat = Heat,
+    }
+}
+
+#[test]
+fn two_machines_share_a_module() {
+    let mut trigger: TriggerFsm<MockBoard> = TriggerFsm::new();
+    let mut power: PowerFsm<MockBoard> = PowerFsm::new();
+
+    trigger.post(TriggerFsmEvent::Pull(Pull)).unwrap();
+    trigger.post(TriggerFsmEvent::Half(Half)).unwrap();
+    trigger.run().unwrap();
+    assert_eq!(trigger.state(), TriggerFsmState::Firing(Firing {}));
+    assert_eq!(trigger.context().board.shots, 1);
+
+    trigger.context_mut().millis = 100;
+    assert_eq!(
+        trigger.tick(100),
+        Some(Ok(TriggerFsmTransition::Taken {
+            from: TriggerFsmState::Firing(Firing {}),
+            to: TriggerFsmState::Ready(Ready {}),
+        }))
+    );
+
+    assert!(power.event(PowerFsmEvent::Drop(Drop)).is_ok());
+    assert!(power.event(PowerFsmEvent::Heat(Heat)).is_ok());
+    assert!(power.event(PowerFsmEvent::Charge(Charge)).is_ok());
+    assert_eq!(
+        power.state(),
+        PowerFsmState {
+            battery: power_fsm::battery::State::Healthy(Healthy {}),
+            thermal: power_fsm::thermal::State::Cooling(Cooling {}),
+        }
+    );
+    assert_eq!(power.next_deadline(), Some(50));
+}
+
+#[test]
+fn a_generic_machine_runs_on_the_board_it_is_given() {
+    let mut trigger = TriggerFsm::with_context(FireControl {
+        hot: true,
+        ..FireControl::<MockBoard>::default()
+    });
+    trigger.event(TriggerFsmEvent::Half(Half)).unwrap();
+    assert_eq!(
+        trigger.event(TriggerFsmEvent::Pull(Pull)),
+        Err(TriggerFsmTransitionError::NoGuardPassed {
+            state: TriggerFsmState::Preloading(Preloading {}),
+            event: TriggerFsmEvent::Pull(Pull),
+        })
+    );
+    assert_eq!(trigger.context().board.shots, 0);
+
+    assert_eq!(
+        trigger.permitted_events().collect::<Vec<_>>(),
+        vec![TriggerFsmEventId::Pull, TriggerFsmEventId::Release]
+    );
+    assert!(TriggerFsm::<MockBoard>::DOT.starts_with("digraph TriggerFsm {"));
+}
@@ -0,0 +1,175 @@
+use fsm_rs::fsm;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Idle {}
+
+impl Idle {
+    pub fn entry(&mut self, _: &mut Context, _: Option<&Event>) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    pub fn exit(&self, context: &mut Context, _: Option<&Event>) -> Result<(), &'static str> {
+        context.log.push("exit idle");
+        if context.jammed {
+            return Err("jammed");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Running {}
+
+impl Running {
+    pub fn entry(&mut self, context: &mut Context, _: Option<&Event>) -> Result<(), &'static str> {
+        context.log.push("entry running");
+        Ok(())
+    }
+
+    pub fn exit(&self, _: &mut Context, _: Option<&Event>) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Start {}
+
+impl Start {
+    pub fn on(&self, _: &mut Context) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stop {}
+
+impl Stop {
+    pub fn on(&self, _: &mut Context) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct Context {
+    jammed: bool,
+    log: Vec<&'static str>,
+}
+
+fsm! {
+    Context = Context;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    States {
+        Idle = Idle,
+        Running = Running,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    Events {
+        Start = Start,
+        Stop = Stop,
+    }
+
+    Transitions {
+        Start [
+            Idle => Running,
+        ],
+        Stop [
+            Running => Idle,
+        ],
+    }
+}
+
+/// Records every notification, the way firmware would write them to a
+/// trace buffer.
+#[derive(Default)]
+pub struct Recorder(Vec<String>);
+
+impl Observer for Recorder {
+    fn before_transition(&mut self, from: &State, event: &Event, to: StateId) {
+        self.0.push(format!(
+            "before {} {} {}",
+            from.name(),
+            event.name(),
+            to.name()
+        ));
+    }
+
+    fn after_transition(
+        &mut self,
+        from: &State,
+        event: &Event,
+        to: StateId,
+        result: &Result<Transition, TransitionError<&'static str>>,
+    ) {
+        self.0.push(format!(
+            "after {} {} {} {:?}",
+            from.name(),
+            event.name(),
+            to.name(),
+            result.map(|_| ())
+        ));
+    }
+
+    fn rejected(
+        &mut self,
+        state: &State,
+        event: &Event,
+        result: &Result<Transition, TransitionError<&'static str>>,
+    ) {
+        self.0.push(format!(
+            "rejected {} {} {}",
+            state.name(),
+            event.name(),
+            result.is_err()
+        ));
+    }
+}
+
+#[test]
+fn test_observer_is_notified_around_callbacks() {
+    let mut machine = Machine::with_observer(Context::default(), Recorder::default());
+
+    assert!(machine.event(Event::Start(Start {})).is_ok());
+
+    assert_eq!(machine.context().log, vec!["exit idle", "entry running"]);
+    assert_eq!(
+        machine.observer().0,
+        vec![
+            "before Idle Start Running",
+            "after Idle Start Running Ok(())",
+        ]
+    );
+}
+
+#[test]
+fn test_observer_sees_failures_and_rejections() {
+    let mut machine = Machine::with_observer(Context::default(), Recorder::default());
+    machine.context_mut().jammed = true;
+
+    assert_eq!(
+        machine.event(Event::Start(Start {})),
+        Err(TransitionError::Callback("jammed"))
+    );
+    assert_eq!(machine.state(), State::Idle(Idle {}));
+
+    assert!(machine.event(Event::Stop(Stop {})).is_err());
+
+    assert_eq!(
+        machine.observer().0,
+        vec![
+            "before Idle Start Running",
+            "after Idle Start Running Err(Callback(\"jammed\"))",
+            "rejected Idle Stop true",
+        ]
+    );
+}
+
+#[test]
+fn test_machine_without_observer_is_unchanged() {
+    // `NoObserver` takes no space, the machine is just its context and state
+    assert_eq!(
+        core::mem::size_of::<Machine>(),
+        core::mem::size_of::<(Context, State)>()
+    );
+}
@@ -0,0 +1,306 @@
+use std::collections::HashSet;
+
+use fsm_rs::fsm;
+
+#[macro_use]
+mod common;
+
+#[derive(Debug, Default)]
+pub struct Trigger {
+    pulls: u32,
+}
+
+macro_rules! counted_event {
+    ($($name:ident),*) => {
+        $(
+            #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+            pub struct $name;
+
+            impl $name {
+                pub fn on(&self, trigger: &mut Trigger) -> Result<(), &'static str> {
+                    trigger.pulls += 1;
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+state!(Ready, Armed, Preloading, Firing);
+counted_event!(Half, Pull, Release);
+
+mod trigger {
+    use super::*;
+
+    fsm! {
+        /// Fires on a full pull once preloaded.
+        #[derive(Debug)]
+        pub(crate)
+
+        Context = Trigger;
+
+        #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+        #[repr(u8)]
+        pub(crate) States {
+            /// Waiting for a half pull.
+            Ready = Ready,
+            /// The trigger is held.
+            Armed = Armed {
+                Preloading = Preloading,
+                #[allow(dead_code)]
+                Firing = Firing,
+            },
+        }
+
+        #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+        Events {
+            Half = Half,
+            /// Pulled all the way.
+            Pull = Pull,
+            Release = Release,
+        }
+
+        Transitions {
+            Half [
+                Ready => Preloading,
+            ],
+            Pull [
+                Preloading => Firing,
+            ],
+            Release [
+                Armed => Ready,
+            ],
+        }
+    }
+}
+
+#[test]
+fn derives_and_visibility_reach_the_generated_items() {
+    let mut machine = trigger::Machine::new();
+    machine.event(trigger::Event::Half(Half)).unwrap();
+    machine.event(trigger::Event::Pull(Pull)).unwrap();
+
+    let states: HashSet<trigger::State> = vec![
+        trigger::State::Ready(Ready {}),
+        machine.state(),
+        trigger::State::Firing(Firing {}),
+    ]
+    .into_iter()
+    .collect();
+    assert_eq!(states.len(), 2);
+
+    let events: HashSet<trigger::Event> = trigger::Event::ALL
+        .iter()
+        .map(|v| match v {
+            trigger::EventId::Half => trigger::Event::Half(Half),
+            trigger::EventId::Pull => trigger::Event::Pull(Pull),
+            trigger::EventId::Release => trigger::Event::Release(Release),
+        })
+        .collect();
+    assert_eq!(events.len(), 3);
+
+    assert!(format!("{:?}", machine).starts_with("Machine { context: Trigger { pulls: 2 }"));
+}
+
+macro_rules! no_callbacks {
+    ($($name:ident),*) => {
+        $(
+            impl $name {
+                pub fn entry(&mut self, _: &mut Console, _: Option<&Event>) -> Result<(), String> {
+                    Ok(())
+                }
+
+                pub fn exit(&self, _: &mut Console, _: Option<&Event>) -> Result<(), String> {
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! no_action {
+    ($($name:ident),*) => {
+        $(
+            impl $name {
+                pub fn on(&self, _: &mut Console) -> Result<(), String> {
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+/// States and events holding data that can't be `Copy`, with none of the
+/// derives the generated machine used to add, for either backend.
+macro_rules! display {
+    ($($fsm:tt)*) => {
+        use super::*;
+
+        #[derive(Default)]
+        pub struct Console {
+            millis: u64,
+            shown: Vec<String>,
+            queue: Queue,
+        }
+
+        impl Clock for Console {
+            fn now(&self) -> u64 {
+                self.millis
+            }
+        }
+
+        impl EventQueue for Console {
+            fn queue(&mut self) -> &mut Queue {
+                &mut self.queue
+            }
+        }
+
+        #[derive(Clone, Debug, Default, PartialEq)]
+        pub struct Idle {}
+
+        /// The lines printed since the display came on.
+        #[derive(Clone, Debug, Default, PartialEq)]
+        pub struct Display {
+            lines: Vec<String>,
+        }
+
+        #[derive(Clone, Debug, Default, PartialEq)]
+        pub struct Showing {
+            text: String,
+        }
+
+        #[derive(Clone, Debug, Default, PartialEq)]
+        pub struct Scrolling {
+            text: String,
+        }
+
+        no_callbacks!(Idle, Display, Scrolling);
+
+        impl Showing {
+            pub fn entry(&mut self, console: &mut Console, event: Option<&Event>) -> Result<(), String> {
+                if let Some(Event::Print(print)) = event {
+                    self.text = print.0.clone();
+                    console.shown.push(print.0.clone());
+                }
+                Ok(())
+            }
+
+            pub fn exit(&self, _: &mut Console, _: Option<&Event>) -> Result<(), String> {
+                Ok(())
+            }
+        }
+
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct Print(pub String);
+
+        impl Print {
+            pub fn on(&self, _: &mut Console) -> Result<(), String> {
+                if self.0.is_empty() {
+                    Err("nothing to print".to_string())
+                } else {
+                    Ok(())
+                }
+            }
+        }
+
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct Clear;
+
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct Wake;
+
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct Scroll;
+
+        no_action!(Clear, Wake, Scroll);
+
+        fsm! {
+            $($fsm)*
+
+            Context = Console;
+
+            Error = String;
+
+            #[derive(Clone, Debug, PartialEq)]
+            States {
+                Idle = Idle,
+                Display = Display {
+                    Showing = Showing,
+                    Scrolling = Scrolling,
+                },
+            }
+
+            #[derive(Clone, Debug, PartialEq)]
+            Events {
+                Print = Print,
+                Clear = Clear,
+                Wake = Wake,
+                Scroll = Scroll,
+            }
+
+            Transitions {
+                Print [
+                    Idle => Showing,
+                    Display -> internal,
+                ],
+                Scroll [
+                    Showing => Scrolling,
+                ],
+                Clear [
+                    Display => Idle,
+                ],
+                Wake [
+                    Idle => History(Display),
+                ],
+                Scrolling => Showing after 100ms,
+            }
+
+            Defer {
+                Scrolling: [Print],
+            }
+        }
+
+        #[test]
+        fn states_and_events_need_not_be_copy() {
+            let mut machine = Machine::new();
+
+            machine.post(Event::Print(Print("hello".to_string()))).unwrap();
+            machine.post(Event::Scroll(Scroll)).unwrap();
+            machine.run().unwrap();
+            assert_eq!(machine.state(), State::Scrolling(Scrolling::default()));
+
+            // deferred while scrolling, and handled once it is done
+            machine.post(Event::Print(Print("world".to_string()))).unwrap();
+            machine.run().unwrap();
+            machine.context_mut().millis = 100;
+            assert_eq!(
+                machine.tick(100),
+                Some(Ok(Transition::Taken {
+                    from: State::Scrolling(Scrolling::default()),
+                    to: State::Showing(Showing {
+                        text: String::new()
+                    }),
+                }))
+            );
+            machine.run().unwrap();
+
+            assert_eq!(
+                machine.event(Event::Print(Print(String::new()))),
+                Err(TransitionError::Callback("nothing to print".to_string()))
+            );
+
+            machine.event(Event::Clear(Clear)).unwrap();
+            machine.event(Event::Wake(Wake)).unwrap();
+            assert_eq!(machine.state().id(), StateId::Showing);
+            assert_eq!(machine.context().shown, vec!["hello"]);
+        }
+    };
+}
+
+mod display {
+    display!(#[fsm(queue = 2)]);
+}
+
+mod display_table {
+    display!(#[fsm(backend = "table", queue = 2)]);
+}
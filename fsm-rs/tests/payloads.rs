@@ -0,0 +1,177 @@
+use fsm_rs::fsm;
+
+#[derive(Default)]
+pub struct Log {
+    entries: Vec<String>,
+    millis: u64,
+}
+
+impl Log {
+    fn take(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.entries)
+    }
+
+    fn push(&mut self, callback: &str, event: Option<&Event>) {
+        let event = event.map_or("none", |v| v.name());
+        self.entries.push(format!("{} on {}", callback, event));
+    }
+}
+
+impl Clock for Log {
+    fn now(&self) -> u64 {
+        self.millis
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Healthy {}
+
+impl Healthy {
+    pub fn entry(&mut self, log: &mut Log, event: Option<&Event>) -> Result<(), &'static str> {
+        log.push("entry Healthy", event);
+        Ok(())
+    }
+
+    pub fn exit(&self, log: &mut Log, event: Option<&Event>) -> Result<(), &'static str> {
+        log.push("exit Healthy", event);
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Degraded {}
+
+impl Degraded {
+    pub fn entry(&mut self, log: &mut Log, event: Option<&Event>) -> Result<(), &'static str> {
+        log.push("entry Degraded", event);
+        Ok(())
+    }
+
+    pub fn exit(&self, log: &mut Log, event: Option<&Event>) -> Result<(), &'static str> {
+        log.push("exit Degraded", event);
+        Ok(())
+    }
+}
+
+/// Remembers the voltage of the change that entered it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Low {
+    millivolts: u16,
+}
+
+impl Low {
+    pub fn entry(&mut self, log: &mut Log, event: Option<&Event>) -> Result<(), &'static str> {
+        if let Some(Event::VoltageChange(change)) = event {
+            self.millivolts = change.millivolts;
+        }
+        log.push("entry Low", event);
+        Ok(())
+    }
+
+    pub fn exit(&self, log: &mut Log, event: Option<&Event>) -> Result<(), &'static str> {
+        log.push("exit Low", event);
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VoltageChange {
+    millivolts: u16,
+}
+
+impl VoltageChange {
+    pub fn on(&self, _: &mut Log) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Recover;
+
+impl Recover {
+    pub fn on(&self, _: &mut Log) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+fsm! {
+    Context = Log;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    States {
+        Healthy = Healthy,
+        Degraded = Degraded {
+            Low = Low,
+        },
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    Events {
+        VoltageChange = VoltageChange,
+        Recover = Recover,
+    }
+
+    Transitions {
+        VoltageChange [
+            Healthy => Degraded,
+        ],
+        Recover [
+            Degraded => Healthy,
+        ],
+        Degraded => Healthy after 1s,
+    }
+}
+
+#[test]
+fn entry_builds_the_target_state_from_the_event() {
+    let mut machine = Machine::new();
+
+    assert_eq!(
+        machine.event(Event::VoltageChange(VoltageChange { millivolts: 3100 })),
+        Ok(Transition::Taken {
+            from: State::Healthy(Healthy {}),
+            to: State::Low(Low { millivolts: 3100 }),
+        })
+    );
+    assert_eq!(machine.state(), State::Low(Low { millivolts: 3100 }));
+}
+
+#[test]
+fn exit_and_entry_are_handed_the_event() {
+    let mut machine = Machine::new();
+    machine
+        .event(Event::VoltageChange(VoltageChange { millivolts: 3100 }))
+        .unwrap();
+    machine.event(Event::Recover(Recover)).unwrap();
+
+    assert_eq!(
+        machine.context_mut().take(),
+        vec![
+            "exit Healthy on VoltageChange",
+            "entry Degraded on VoltageChange",
+            "entry Low on VoltageChange",
+            "exit Low on Recover",
+            "exit Degraded on Recover",
+            "entry Healthy on Recover",
+        ]
+    );
+}
+
+#[test]
+fn a_delay_hands_no_event() {
+    let mut machine = Machine::new();
+    machine
+        .event(Event::VoltageChange(VoltageChange { millivolts: 3100 }))
+        .unwrap();
+    let _ = machine.context_mut().take();
+
+    assert!(machine.tick(1000).unwrap().is_ok());
+    assert_eq!(
+        machine.context_mut().take(),
+        vec![
+            "exit Low on none",
+            "exit Degraded on none",
+            "entry Healthy on none"
+        ]
+    );
+}
@@ -0,0 +1,318 @@
+use fsm_rs::fsm;
+
+macro_rules! logged_state {
+    ($context:ident, $($name:ident),*) => {
+        $(
+            #[derive(Clone, Copy, Debug, Default, PartialEq)]
+            pub struct $name {}
+
+            impl $name {
+                pub fn entry(&mut self, log: &mut $context, _: Option<&Event>) -> Result<(), &'static str> {
+                    log.entries.push(format!("entry {}", stringify!($name)));
+                    Ok(())
+                }
+
+                pub fn exit(&self, log: &mut $context, _: Option<&Event>) -> Result<(), &'static str> {
+                    log.entries.push(format!("exit {}", stringify!($name)));
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! logged_event {
+    ($context:ident, $($name:ident),*) => {
+        $(
+            #[derive(Clone, Copy, Debug, PartialEq)]
+            pub struct $name;
+
+            impl $name {
+                pub fn on(&self, log: &mut $context) -> Result<(), &'static str> {
+                    log.entries.push(format!("on {}", stringify!($name)));
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+mod trigger {
+    use super::*;
+
+    #[derive(Default)]
+    pub struct Trigger {
+        entries: Vec<String>,
+        queue: Queue,
+    }
+
+    impl EventQueue for Trigger {
+        fn queue(&mut self) -> &mut Queue {
+            &mut self.queue
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct Overcurrent {}
+
+    impl Overcurrent {
+        pub fn entry(
+            &mut self,
+            trigger: &mut Trigger,
+            _: Option<&Event>,
+        ) -> Result<(), &'static str> {
+            trigger.entries.push("entry Overcurrent".to_string());
+            // handled once this transition finished
+            trigger
+                .queue
+                .post(Event::Release(Release))
+                .map_err(|_| "queue full")
+        }
+
+        pub fn exit(&self, trigger: &mut Trigger, _: Option<&Event>) -> Result<(), &'static str> {
+            trigger.entries.push("exit Overcurrent".to_string());
+            Ok(())
+        }
+    }
+
+    logged_state!(Trigger, Ready, Armed, Preloading, Single);
+    logged_event!(Trigger, Half, Pull, Release, Current);
+
+    fsm! {
+        #[fsm(queue = 4)]
+
+        Context = Trigger;
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        States {
+            Ready = Ready,
+            Armed = Armed {
+                Preloading = Preloading,
+                Single = Single,
+            },
+            Overcurrent = Overcurrent,
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        Events {
+            Half = Half,
+            Pull = Pull,
+            Release = Release,
+            Current = Current,
+        }
+
+        Transitions {
+            Half [
+                Ready => Preloading,
+            ],
+            Pull [
+                Preloading => Single,
+            ],
+            Release [
+                Armed => Ready,
+                Overcurrent => Ready,
+            ],
+            Current [
+                Armed => Overcurrent,
+            ],
+        }
+
+        // a pull before the half press is kept for after preloading
+        Defer {
+            Ready: [Pull],
+            Overcurrent: [Half],
+        }
+    }
+
+    fn take(machine: &mut Machine) -> Vec<String> {
+        std::mem::take(&mut machine.context_mut().entries)
+    }
+
+    #[test]
+    fn deferred_events_are_replayed_after_the_next_transition() {
+        let mut machine = Machine::new();
+
+        machine.post(Event::Pull(Pull)).unwrap();
+        machine.post(Event::Half(Half)).unwrap();
+        assert!(Machine::defers(&machine.state(), &Event::Pull(Pull)));
+        machine.run().unwrap();
+
+        assert_eq!(machine.state(), State::Single(Single {}));
+        assert_eq!(
+            take(&mut machine),
+            vec![
+                "on Half",
+                "exit Ready",
+                "entry Armed",
+                "entry Preloading",
+                "on Pull",
+                "exit Preloading",
+                "entry Single",
+            ]
+        );
+        assert!(machine.context_mut().queue.is_empty());
+    }
+
+    #[test]
+    fn callbacks_post_follow_up_events() {
+        let mut machine = Machine::new();
+        machine.event(Event::Half(Half)).unwrap();
+        let _ = take(&mut machine);
+
+        machine.post(Event::Current(Current)).unwrap();
+        machine.post(Event::Half(Half)).unwrap();
+        machine.run().unwrap();
+
+        // the Release posted on entering Overcurrent lands behind Half, which
+        // Overcurrent defers until Release left it
+        assert_eq!(machine.state(), State::Preloading(Preloading {}));
+        assert_eq!(
+            take(&mut machine),
+            vec![
+                "on Current",
+                "exit Preloading",
+                "exit Armed",
+                "entry Overcurrent",
+                "on Release",
+                "exit Overcurrent",
+                "entry Ready",
+                "on Half",
+                "exit Ready",
+                "entry Armed",
+                "entry Preloading",
+            ]
+        );
+    }
+
+    #[test]
+    fn run_stops_at_the_first_error() {
+        let mut machine = Machine::new();
+
+        machine.post(Event::Release(Release)).unwrap();
+        machine.post(Event::Half(Half)).unwrap();
+        machine.post(Event::Pull(Pull)).unwrap();
+        machine.post(Event::Current(Current)).unwrap();
+        assert_eq!(machine.post(Event::Half(Half)), Err(Event::Half(Half)));
+
+        assert_eq!(
+            machine.run(),
+            Err(TransitionError::Unhandled {
+                state: State::Ready(Ready {}),
+                event: Event::Release(Release),
+            })
+        );
+        assert_eq!(
+            machine
+                .context_mut()
+                .queue
+                .iter()
+                .copied()
+                .collect::<Vec<_>>(),
+            vec![
+                Event::Half(Half),
+                Event::Pull(Pull),
+                Event::Current(Current)
+            ]
+        );
+
+        machine.run().unwrap();
+        assert_eq!(machine.state(), State::Ready(Ready {}));
+    }
+}
+
+mod regions {
+    use super::*;
+
+    #[derive(Default)]
+    pub struct Log {
+        entries: Vec<String>,
+        queue: Queue,
+    }
+
+    impl EventQueue for Log {
+        fn queue(&mut self) -> &mut Queue {
+            &mut self.queue
+        }
+    }
+
+    logged_state!(Log, Healthy, Low, Released, Pulled);
+    logged_event!(Log, Drop, Charge, Pull, Release);
+
+    fsm! {
+        #[fsm(queue = 8)]
+
+        Context = Log;
+
+        Unhandled = Ignore;
+
+        Regions {
+            Power {
+                #[derive(Clone, Copy, Debug, PartialEq)]
+                States {
+                    Healthy = Healthy,
+                    Low = Low,
+                }
+
+                Transitions {
+                    Drop [
+                        Healthy => Low,
+                    ],
+                    Charge [
+                        Low => Healthy,
+                    ],
+                }
+            },
+            Trigger {
+                #[derive(Clone, Copy, Debug, PartialEq)]
+                States {
+                    Released = Released,
+                    Pulled = Pulled,
+                }
+
+                Transitions {
+                    Pull [
+                        Released => Pulled,
+                    ],
+                    Release [
+                        Pulled => Released,
+                    ],
+                }
+
+                // no battery changes mid shot
+                Defer {
+                    Pulled: [Drop, Charge],
+                }
+            },
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        Events {
+            Drop = Drop,
+            Charge = Charge,
+            Pull = Pull,
+            Release = Release,
+        }
+    }
+
+    #[test]
+    fn an_event_waits_while_any_region_defers_it() {
+        let mut machine = Machine::new();
+
+        machine.post(Event::Pull(Pull)).unwrap();
+        machine.post(Event::Drop(Drop)).unwrap();
+        machine.run().unwrap();
+
+        assert_eq!(machine.state().power, power::State::Healthy(Healthy {}));
+        assert_eq!(machine.context_mut().queue.len(), 1);
+
+        machine.post(Event::Release(Release)).unwrap();
+        machine.run().unwrap();
+
+        assert_eq!(machine.state().power, power::State::Low(Low {}));
+        assert_eq!(
+            machine.state().trigger,
+            trigger::State::Released(Released {})
+        );
+        assert!(machine.context_mut().queue.is_empty());
+    }
+}
@@ -0,0 +1,190 @@
+use fsm_rs::fsm;
+
+#[macro_use]
+mod common;
+
+#[derive(Default)]
+pub struct Log {
+    entries: Vec<String>,
+    millivolts: u16,
+}
+
+impl Log {
+    fn take(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.entries)
+    }
+
+    fn is_low(&self, _: &VoltageChange) -> bool {
+        self.millivolts < 3300
+    }
+}
+
+macro_rules! logged_state {
+    ($($name:ident),*) => {
+        $(
+            #[derive(Clone, Copy, Debug, Default, PartialEq)]
+            pub struct $name {}
+
+            impl $name {
+                pub fn entry(&mut self, log: &mut Log, _: Option<&Event>) -> Result<(), &'static str> {
+                    log.entries.push(format!("entry {}", stringify!($name)));
+                    Ok(())
+                }
+
+                pub fn exit(&self, log: &mut Log, _: Option<&Event>) -> Result<(), &'static str> {
+                    log.entries.push(format!("exit {}", stringify!($name)));
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+logged_state!(Healthy, Low, Released, Pulled, Safe, Auto, Off);
+event!(VoltageChange, Pull, Release, Select, PowerOff);
+
+fsm! {
+    Context = Log;
+
+    Unhandled = Ignore;
+
+    Regions {
+        Power {
+            #[derive(Clone, Copy, Debug, PartialEq)]
+            States {
+                Healthy = Healthy,
+                Low = Low,
+                Off = Off,
+            }
+
+            Final { Off }
+
+            Transitions {
+                VoltageChange [
+                    Healthy => Low if is_low,
+                    Low => Healthy,
+                ],
+                PowerOff [
+                    [Healthy, Low] => Off,
+                ],
+            }
+        },
+        Trigger {
+            Initial = Released;
+
+            #[derive(Clone, Copy, Debug, PartialEq)]
+            States {
+                Pulled = Pulled,
+                Released = Released,
+            }
+
+            Transitions {
+                Pull [
+                    Released => Pulled,
+                ],
+                Release [
+                    Pulled => Released,
+                ],
+                PowerOff [
+                    Pulled => Released,
+                ],
+            }
+        },
+        Selector {
+            #[derive(Clone, Copy, Debug, PartialEq)]
+            States {
+                Safe = Safe,
+                Auto = Auto,
+            }
+
+            Transitions {
+                Select [
+                    Safe => Auto,
+                    Auto => Safe,
+                ],
+            }
+        },
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    Events {
+        VoltageChange = VoltageChange,
+        Pull = Pull,
+        Release = Release,
+        Select = Select,
+        PowerOff = PowerOff,
+    }
+}
+
+#[test]
+fn every_region_starts_in_its_initial_state() {
+    let machine = Machine::new();
+
+    assert_eq!(
+        machine.state(),
+        State {
+            power: power::State::Healthy(Healthy {}),
+            trigger: trigger::State::Released(Released {}),
+            selector: selector::State::Safe(Safe {}),
+        }
+    );
+    assert_eq!(machine.trigger().state().id(), trigger::StateId::Released);
+}
+
+#[test]
+fn an_event_is_handed_to_every_region() {
+    let mut machine = Machine::new();
+    assert!(machine.event(Event::Pull(Pull)).is_ok());
+    let _ = machine.context_mut().take();
+
+    let outcome = machine.event(Event::PowerOff(PowerOff));
+
+    assert_eq!(
+        outcome.power,
+        Ok(power::Transition::Taken {
+            from: power::State::Healthy(Healthy {}),
+            to: power::State::Off(Off {}),
+        })
+    );
+    assert_eq!(
+        outcome.trigger,
+        Ok(trigger::Transition::Taken {
+            from: trigger::State::Pulled(Pulled {}),
+            to: trigger::State::Released(Released {}),
+        })
+    );
+    assert_eq!(
+        outcome.selector,
+        Ok(selector::Transition::Ignored {
+            state: selector::State::Safe(Safe {}),
+            event: Event::PowerOff(PowerOff),
+        })
+    );
+    assert_eq!(
+        machine.context_mut().take(),
+        vec!["exit Healthy", "entry Off", "exit Pulled", "entry Released"]
+    );
+}
+
+#[test]
+fn regions_share_the_context() {
+    let mut machine = Machine::new();
+    machine.context_mut().millivolts = 5000;
+
+    assert!(!machine.can_handle(&Event::VoltageChange(VoltageChange)));
+
+    machine.context_mut().millivolts = 3000;
+
+    assert!(machine.can_handle(&Event::VoltageChange(VoltageChange)));
+    assert!(machine.event(Event::VoltageChange(VoltageChange)).is_ok());
+    assert_eq!(machine.state().power, power::State::Low(Low {}));
+}
+
+#[test]
+fn the_machine_finishes_when_every_region_does() {
+    let mut machine = Machine::new();
+    assert!(machine.event(Event::PowerOff(PowerOff)).is_ok());
+
+    assert!(machine.power().is_finished());
+    assert!(!machine.is_finished());
+}
@@ -0,0 +1,316 @@
+use fsm_rs::fsm;
+
+#[macro_use]
+mod common;
+
+macro_rules! codec {
+    ($($name:ident),*) => {
+        $(
+            impl Codec for $name {
+                fn encode(&self, _: &mut [u8]) -> Option<usize> {
+                    Some(0)
+                }
+
+                fn decode(_: &[u8]) -> Option<(Self, usize)> {
+                    Some(($name {}, 0))
+                }
+            }
+        )*
+    };
+}
+
+mod trigger {
+    use super::*;
+
+    #[derive(Default)]
+    pub struct Trigger {
+        millis: u64,
+    }
+
+    impl Clock for Trigger {
+        fn now(&self) -> u64 {
+            self.millis
+        }
+    }
+
+    /// Counts the shots of the burst it is in.
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Burst {
+        shots: u16,
+    }
+
+    impl Burst {
+        pub fn entry(&mut self, _: &mut Trigger, _: Option<&Event>) -> Result<(), &'static str> {
+            Ok(())
+        }
+
+        pub fn exit(&self, _: &mut Trigger, _: Option<&Event>) -> Result<(), &'static str> {
+            Ok(())
+        }
+    }
+
+    impl Codec for Burst {
+        fn encode(&self, bytes: &mut [u8]) -> Option<usize> {
+            self.shots.encode(bytes)
+        }
+
+        fn decode(bytes: &[u8]) -> Option<(Self, usize)> {
+            let (shots, len) = u16::decode(bytes)?;
+            Some((Burst { shots }, len))
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct CurrentChange {
+        milliamps: u16,
+    }
+
+    impl CurrentChange {
+        pub fn on(&self, _: &mut Trigger) -> Result<(), &'static str> {
+            Ok(())
+        }
+    }
+
+    impl Codec for CurrentChange {
+        fn encode(&self, bytes: &mut [u8]) -> Option<usize> {
+            self.milliamps.encode(bytes)
+        }
+
+        fn decode(bytes: &[u8]) -> Option<(Self, usize)> {
+            let (milliamps, len) = u16::decode(bytes)?;
+            Some((CurrentChange { milliamps }, len))
+        }
+    }
+
+    state!(Ready, Preloading, FullAuto, Overcurrent);
+    event!(Half, Pull);
+    codec!(Ready, Preloading, FullAuto, Overcurrent, Half, Pull);
+
+    fsm! {
+        #[fsm(snapshot)]
+
+        Context = Trigger;
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        States {
+            Ready = Ready,
+            Burst = Burst {
+                Preloading = Preloading,
+                FullAuto = FullAuto,
+            },
+            Overcurrent = Overcurrent,
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        Events {
+            Half = Half,
+            Pull = Pull,
+            CurrentChange = CurrentChange,
+        }
+
+        Transitions {
+            Half [
+                Ready => Preloading,
+            ],
+            Pull [
+                Preloading => FullAuto,
+            ],
+            CurrentChange [
+                Burst => Overcurrent,
+                Overcurrent => History(Burst),
+            ],
+            Preloading => Ready after 200ms,
+        }
+    }
+
+    #[test]
+    fn a_restored_machine_picks_up_where_the_snapshot_was_taken() {
+        let mut machine = Machine::new();
+        machine.event(Event::Half(Half)).unwrap();
+        machine
+            .event(Event::CurrentChange(CurrentChange { milliamps: 2500 }))
+            .unwrap();
+        machine.context_mut().millis = 50;
+
+        let mut bytes = [0; 32];
+        let len = machine.snapshot().encode(&mut bytes).unwrap();
+        // state, burst, burst history, preloading deadline
+        assert_eq!(len, 1 + 2 + 2 + 1);
+        assert_eq!(Snapshot::decode(&bytes[..len - 1]), None);
+
+        let (snapshot, _) = Snapshot::decode(&bytes).unwrap();
+        assert_eq!(snapshot, machine.snapshot());
+        assert_eq!(snapshot.state(), State::Overcurrent(Overcurrent {}));
+
+        let mut restored = Machine::new();
+        restored.restore(snapshot);
+        assert_eq!(restored.state(), State::Overcurrent(Overcurrent {}));
+
+        restored
+            .event(Event::CurrentChange(CurrentChange { milliamps: 100 }))
+            .unwrap();
+        assert_eq!(restored.state(), State::Preloading(Preloading {}));
+        assert_eq!(restored.next_deadline(), Some(200));
+    }
+
+    #[test]
+    fn running_delays_keep_the_time_they_had_left() {
+        let mut machine = Machine::new();
+        machine.context_mut().millis = 1000;
+        machine.event(Event::Half(Half)).unwrap();
+        machine.context_mut().millis = 1150;
+
+        let snapshot = machine.snapshot();
+
+        let mut restored = Machine::new();
+        restored.context_mut().millis = 10;
+        restored.restore(snapshot);
+        assert_eq!(restored.next_deadline(), Some(60));
+        assert_eq!(restored.tick(59), None);
+        assert!(restored.tick(60).is_some());
+        assert_eq!(restored.state(), State::Ready(Ready {}));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn a_snapshot_round_trips_through_serde() {
+        let mut machine = Machine::new();
+        machine.event(Event::Half(Half)).unwrap();
+        machine
+            .event(Event::CurrentChange(CurrentChange { milliamps: 2500 }))
+            .unwrap();
+
+        let json = serde_json::to_string(&machine.snapshot()).unwrap();
+        let snapshot: Snapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(snapshot, machine.snapshot());
+
+        let mut restored = Machine::new();
+        restored.restore(snapshot);
+        assert_eq!(restored.state(), State::Overcurrent(Overcurrent {}));
+
+        let event = Event::CurrentChange(CurrentChange { milliamps: 100 });
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(serde_json::from_str::<Event>(&json).unwrap(), event);
+    }
+
+    #[test]
+    fn recorded_events_are_replayed() {
+        let events = [
+            Event::Half(Half),
+            Event::CurrentChange(CurrentChange { milliamps: 2500 }),
+            Event::CurrentChange(CurrentChange { milliamps: 100 }),
+            Event::Pull(Pull),
+        ];
+
+        let mut log = [0; 16];
+        let mut len = 0;
+        for event in &events {
+            len += event.encode(&mut log[len..]).unwrap();
+        }
+        assert_eq!(&log[..len], &[0, 2, 0xc4, 0x09, 2, 100, 0, 1]);
+
+        let mut machine = Machine::new();
+        let mut rest = &log[..len];
+        while let Some((event, taken)) = Event::decode(rest) {
+            machine.event(event).unwrap();
+            rest = &rest[taken..];
+        }
+        assert_eq!(machine.state(), State::FullAuto(FullAuto {}));
+
+        assert_eq!(Event::decode(&[3]), None);
+        assert_eq!(Event::Pull(Pull).encode(&mut []), None);
+    }
+}
+
+mod regions {
+    use super::*;
+
+    #[derive(Default)]
+    pub struct Log {
+        millis: u64,
+    }
+
+    impl Clock for Log {
+        fn now(&self) -> u64 {
+            self.millis
+        }
+    }
+
+    state!(Healthy, Low, Released, Pulled, Cooling);
+    event!(Drop, Charge, Pull, Release);
+    codec!(Healthy, Low, Released, Pulled, Cooling, Drop, Charge, Pull, Release);
+
+    fsm! {
+        #[fsm(backend = "table", snapshot)]
+
+        Context = Log;
+
+        Unhandled = Ignore;
+
+        Regions {
+            Power {
+                #[derive(Clone, Copy, Debug, PartialEq)]
+                States {
+                    Healthy = Healthy,
+                    Low = Low,
+                }
+
+                Transitions {
+                    Drop [
+                        Healthy => Low,
+                    ],
+                    Charge [
+                        Low => Healthy,
+                    ],
+                }
+            },
+            Trigger {
+                #[derive(Clone, Copy, Debug, PartialEq)]
+                States {
+                    Released = Released,
+                    Pulled = Pulled,
+                    Cooling = Cooling,
+                }
+
+                Transitions {
+                    Pull [
+                        Released => Pulled,
+                    ],
+                    Release [
+                        Pulled => Cooling,
+                    ],
+                    Cooling => Released after 50ms,
+                }
+            },
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        Events {
+            Drop = Drop,
+            Charge = Charge,
+            Pull = Pull,
+            Release = Release,
+        }
+    }
+
+    #[test]
+    fn every_region_is_restored() {
+        let mut machine = Machine::new();
+        let _ = machine.event(Event::Drop(Drop));
+        let _ = machine.event(Event::Pull(Pull));
+        let _ = machine.event(Event::Release(Release));
+        machine.context_mut().millis = 20;
+
+        let mut bytes = [0; 16];
+        let len = machine.snapshot().encode(&mut bytes).unwrap();
+        let (snapshot, taken) = Snapshot::decode(&bytes).unwrap();
+        assert_eq!(taken, len);
+
+        let mut restored = Machine::new();
+        restored.restore(snapshot);
+        assert_eq!(restored.state(), machine.state());
+        assert_eq!(restored.next_deadline(), Some(30));
+    }
+}
@@ -0,0 +1,492 @@
+//! Differential tests: the same definitions built with the `match` and the
+//! `table` backend must do the same thing for the same events and ticks.
+
+/// What the callbacks, guards and actions of both backends run against.
+#[derive(Default)]
+pub struct Log {
+    entries: Vec<String>,
+    millis: u64,
+    fuel: u8,
+    hot: bool,
+    /// The callback that fails, e.g. `"exit Armed"`.
+    fail: Option<&'static str>,
+    guards: std::cell::Cell<u32>,
+}
+
+impl Log {
+    fn call(
+        &mut self,
+        callback: &'static str,
+        event: Option<&'static str>,
+    ) -> Result<(), &'static str> {
+        self.entries
+            .push(format!("{} on {}", callback, event.unwrap_or("none")));
+        if self.fail == Some(callback) {
+            return Err(callback);
+        }
+        Ok(())
+    }
+
+    fn guard(&self, passes: bool) -> bool {
+        self.guards.set(self.guards.get() + 1);
+        passes
+    }
+
+    // the event types of each backend's machine differ, the guards don't
+
+    pub fn has_fuel<T>(&self, _: &T) -> bool {
+        self.guard(self.fuel > 0)
+    }
+
+    pub fn has_more_fuel<T>(&self, _: &T) -> bool {
+        self.guard(self.fuel > 1)
+    }
+
+    pub fn is_empty<T>(&self, _: &T) -> bool {
+        self.guard(self.fuel == 0)
+    }
+
+    pub fn is_hot<T>(&self, _: &T) -> bool {
+        self.guard(self.hot)
+    }
+}
+
+/// The callbacks `step` makes fail now and then.
+const FAILING: &[&str] = &[
+    "entry Armed",
+    "exit Armed",
+    "entry Firing",
+    "exit Single",
+    "entry Burst",
+    "exit Fault",
+    "entry Idle",
+    "on Fire",
+    "on Recover",
+    "entry Cooling",
+    "exit Hot",
+];
+
+/// xorshift64, so every seed replays the same run on both backends.
+pub struct Random(u64);
+
+impl Random {
+    fn next(&mut self, bound: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % bound
+    }
+}
+
+/// One random step against `log`: an event, a tick, or a change to what
+/// the guards and callbacks will do. Returns the event to send, if any.
+fn step(random: &mut Random, log: &mut Log, events: usize) -> Option<usize> {
+    match random.next(10) {
+        0 => {
+            log.fail = match random.next(3) {
+                0 => Some(FAILING[random.next(FAILING.len() as u64) as usize]),
+                _ => None,
+            };
+            None
+        }
+        1 => {
+            log.fuel = random.next(3) as u8;
+            log.hot = random.next(2) == 0;
+            None
+        }
+        2 | 3 => {
+            log.millis += random.next(400);
+            None
+        }
+        _ => Some(random.next(events as u64) as usize),
+    }
+}
+
+macro_rules! logged_state {
+    ($($name:ident),*) => {
+        $(
+            #[derive(Clone, Copy, Debug, Default, PartialEq)]
+            pub struct $name {}
+
+            impl $name {
+                pub fn entry(&mut self, log: &mut Log, event: Option<&Event>) -> Result<(), &'static str> {
+                    log.call(concat!("entry ", stringify!($name)), event.map(|v| v.name()))
+                }
+
+                pub fn exit(&self, log: &mut Log, event: Option<&Event>) -> Result<(), &'static str> {
+                    log.call(concat!("exit ", stringify!($name)), event.map(|v| v.name()))
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! logged_event {
+    ($($name:ident),*) => {
+        $(
+            #[derive(Clone, Copy, Debug, PartialEq)]
+            pub struct $name;
+
+            impl $name {
+                pub fn on(&self, log: &mut Log) -> Result<(), &'static str> {
+                    log.call(concat!("on ", stringify!($name)), None)
+                }
+            }
+        )*
+    };
+}
+
+/// Records every notification as text, which reads the same for both
+/// backends.
+macro_rules! recorder {
+    ($($timed_out:tt)*) => {
+        #[derive(Default)]
+        pub struct Recorder(Vec<String>);
+
+        impl Observer for Recorder {
+            fn before_transition(&mut self, from: &State, event: &Event, to: StateId) {
+                self.0.push(format!("before {:?} {:?} {:?}", from, event, to));
+            }
+
+            fn after_transition(
+                &mut self,
+                from: &State,
+                event: &Event,
+                to: StateId,
+                result: &Result<Transition, TransitionError<&'static str>>,
+            ) {
+                self.0.push(format!("after {:?} {:?} {:?} {:?}", from, event, to, result));
+            }
+
+            fn rejected(
+                &mut self,
+                state: &State,
+                event: &Event,
+                result: &Result<Transition, TransitionError<&'static str>>,
+            ) {
+                self.0.push(format!("rejected {:?} {:?} {:?}", state, event, result));
+            }
+
+            $($timed_out)*
+        }
+    };
+}
+
+macro_rules! hierarchy {
+    ($($backend:tt)*) => {
+        use super::{step, Log, Random};
+        use fsm_rs::fsm;
+
+        impl Clock for Log {
+            fn now(&self) -> u64 {
+                self.millis
+            }
+        }
+
+        logged_state!(Idle, Armed, Loading, Firing, Single, Burst, Fault);
+
+        /// Never left, so it has no `exit`.
+        #[derive(Clone, Copy, Debug, Default, PartialEq)]
+        pub struct Done {}
+
+        impl Done {
+            pub fn entry(&mut self, log: &mut Log, event: Option<&Event>) -> Result<(), &'static str> {
+                log.call("entry Done", event.map(|v| v.name()))
+            }
+        }
+        logged_event!(Arm, Fire, Switch, Refuel, Overload, Recover, DeepRecover, Reset, Shutdown);
+
+        recorder! {
+            fn timed_out(
+                &mut self,
+                from: &State,
+                to: StateId,
+                result: &Result<Transition, TransitionError<&'static str>>,
+            ) {
+                self.0.push(format!("timed out {:?} {:?} {:?}", from, to, result));
+            }
+        }
+
+        fsm! {
+            $($backend)*
+
+            Context = Log;
+
+            #[derive(Clone, Copy, Debug, PartialEq)]
+            States {
+                Idle = Idle,
+                Armed = Armed {
+                    Loading = Loading,
+                    Firing = Firing {
+                        Single = Single,
+                        Burst = Burst,
+                    },
+                },
+                Fault = Fault,
+                Done = Done,
+            }
+
+            Final { Done }
+
+            #[derive(Clone, Copy, Debug, PartialEq)]
+            Events {
+                Arm = Arm,
+                Fire = Fire,
+                Switch = Switch,
+                Refuel = Refuel,
+                Overload = Overload,
+                Recover = Recover,
+                DeepRecover = DeepRecover,
+                Reset = Reset,
+                Shutdown = Shutdown,
+            }
+
+            Transitions {
+                Arm [
+                    Idle => Armed if has_more_fuel,
+                    Idle => Fault,
+                ],
+                Fire [
+                    Loading => Firing if has_fuel,
+                    Single => Burst if is_hot,
+                    Firing => Loading if has_fuel,
+                ],
+                Switch [
+                    Single => Burst,
+                    Burst => Single,
+                ],
+                Refuel [
+                    Armed -> internal,
+                    Idle -> internal if is_empty,
+                ],
+                Overload [
+                    * - [Done] => Fault,
+                ],
+                Recover [
+                    Fault => History(Armed),
+                ],
+                DeepRecover [
+                    Fault => DeepHistory(Armed),
+                ],
+                Reset [
+                    Armed => Armed,
+                ],
+                Shutdown [
+                    Fault => Done,
+                ],
+                Loading => Firing after 200ms,
+                Armed => Idle after 1s,
+                Fault => Idle after 50ms,
+            }
+        }
+
+        /// Everything observable about `steps` random steps from `seed`.
+        pub fn run(seed: u64, steps: usize) -> Vec<String> {
+            let events = [
+                Event::Arm(Arm),
+                Event::Fire(Fire),
+                Event::Switch(Switch),
+                Event::Refuel(Refuel),
+                Event::Overload(Overload),
+                Event::Recover(Recover),
+                Event::DeepRecover(DeepRecover),
+                Event::Reset(Reset),
+                Event::Shutdown(Shutdown),
+            ];
+            let mut random = Random(seed);
+            let mut machine = Machine::with_observer(Log::default(), Recorder::default());
+            let mut transcript = Vec::new();
+
+            for _ in 0..steps {
+                let result = match step(&mut random, machine.context_mut(), events.len()) {
+                    Some(event) => format!("{:?}", machine.event(events[event])),
+                    None => {
+                        let now = machine.context().millis;
+                        format!("tick {:?}", machine.tick(now))
+                    }
+                };
+                transcript.push(result);
+                transcript.push(format!("{:?} finished {}", machine.state(), machine.is_finished()));
+                transcript.append(&mut machine.context_mut().entries);
+                transcript.append(&mut machine.observer_mut().0);
+                transcript.push(format!("deadline {:?}", machine.next_deadline()));
+                for event in &events {
+                    transcript.push(format!("{:?} {}", event, machine.can_handle(event)));
+                }
+                transcript.push(format!("{:?}", machine.permitted_events().collect::<Vec<_>>()));
+                transcript.push(format!("guards {}", machine.context().guards.get()));
+            }
+
+            transcript
+        }
+    };
+}
+
+macro_rules! regions {
+    ($($backend:tt)*) => {
+        use super::{step, Log, Random};
+        use fsm_rs::fsm;
+
+        impl Clock for Log {
+            fn now(&self) -> u64 {
+                self.millis
+            }
+        }
+
+        logged_state!(Cold, Hot, Cooling, Off, On);
+        logged_event!(Heat, Toggle);
+
+        fsm! {
+            $($backend)*
+
+            Context = Log;
+
+            Unhandled = Ignore;
+
+            Regions {
+                Temperature {
+                    #[derive(Clone, Copy, Debug, PartialEq)]
+                    States {
+                        Cold = Cold,
+                        Hot = Hot {
+                            Cooling = Cooling,
+                        },
+                    }
+
+                    Transitions {
+                        Heat [
+                            Cold => Hot if is_hot,
+                            Cooling -> internal,
+                        ],
+                        Cold => Cold after 300ms,
+                        Hot => Cold after 100ms,
+                    }
+                },
+                Switch {
+                    #[derive(Clone, Copy, Debug, PartialEq)]
+                    States {
+                        Off = Off,
+                        On = On,
+                    }
+
+                    Transitions {
+                        Toggle [
+                            Off => On,
+                            On => Off,
+                        ],
+                    }
+                },
+            }
+
+            #[derive(Clone, Copy, Debug, PartialEq)]
+            Events {
+                Heat = Heat,
+                Toggle = Toggle,
+            }
+        }
+
+        /// Everything observable about `steps` random steps from `seed`.
+        pub fn run(seed: u64, steps: usize) -> Vec<String> {
+            let events = [Event::Heat(Heat), Event::Toggle(Toggle)];
+            let mut random = Random(seed);
+            let mut machine = Machine::new();
+            let mut transcript = Vec::new();
+
+            for _ in 0..steps {
+                let result = match step(&mut random, machine.context_mut(), events.len()) {
+                    Some(event) => format!("{:?}", machine.event(events[event])),
+                    None => {
+                        let now = machine.context().millis;
+                        format!("tick {:?}", machine.tick(now))
+                    }
+                };
+                transcript.push(result);
+                transcript.push(format!("{:?} finished {}", machine.state(), machine.is_finished()));
+                transcript.append(&mut machine.context_mut().entries);
+                transcript.push(format!("deadline {:?}", machine.next_deadline()));
+                for event in &events {
+                    transcript.push(format!("{:?} {}", event, machine.can_handle(event)));
+                }
+                transcript.push(format!("guards {}", machine.context().guards.get()));
+            }
+
+            transcript
+        }
+    };
+}
+
+mod hierarchy_match {
+    hierarchy!();
+}
+
+mod hierarchy_table {
+    hierarchy!(#[fsm(backend = "table")]);
+}
+
+mod regions_match {
+    regions!();
+}
+
+mod regions_table {
+    regions!(#[fsm(backend = "table")]);
+}
+
+#[test]
+fn backends_agree_on_a_hierarchy() {
+    for seed in 1..=200 {
+        assert_eq!(
+            hierarchy_match::run(seed, 200),
+            hierarchy_table::run(seed, 200),
+            "seed {}",
+            seed
+        );
+    }
+}
+
+#[test]
+fn backends_agree_on_regions() {
+    for seed in 1..=200 {
+        assert_eq!(
+            regions_match::run(seed, 200),
+            regions_table::run(seed, 200),
+            "seed {}",
+            seed
+        );
+    }
+}
+
+#[test]
+fn a_tick_starts_the_next_delay_from_its_now() {
+    // the context's clock stays at 0, only `tick` is told the time
+    let mut machine = regions_match::Machine::new();
+    machine.tick(1000);
+    assert_eq!(machine.next_deadline(), Some(1300));
+
+    let mut machine = regions_table::Machine::new();
+    machine.tick(1000);
+    assert_eq!(machine.next_deadline(), Some(1300));
+}
+
+#[test]
+fn runs_cover_the_hierarchy() {
+    let transcript = (1..=200)
+        .flat_map(|v| hierarchy_table::run(v, 200))
+        .collect::<Vec<_>>();
+    let seen = |needle: &str| transcript.iter().any(|v| v.contains(needle));
+
+    assert!(seen("before Fault(Fault) Recover(Recover) Single"));
+    assert!(seen("before Fault(Fault) DeepRecover(DeepRecover) Burst"));
+    assert!(seen("Ok(Internal { state: Idle(Idle) })"));
+    assert!(seen("Err(NoGuardPassed"));
+    assert!(seen("Err(Unhandled"));
+    assert!(seen("Err(Callback(\"exit Armed\"))"));
+    assert!(seen("timed out Loading(Loading) Single"));
+    assert!(seen("finished true"));
+}
+
+#[test]
+fn tables_are_consts() {
+    assert_eq!(hierarchy_table::table::NODES.len(), 8);
+    assert_eq!(hierarchy_table::table::LEAVES, &[0, 2, 4, 5, 6, 7][..],);
+    assert!(regions_table::temperature::table::ROWS.len() == 2);
+}
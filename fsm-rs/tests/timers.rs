@@ -0,0 +1,166 @@
+use fsm_rs::fsm;
+
+#[macro_use]
+mod common;
+
+#[derive(Default)]
+pub struct Log {
+    entries: Vec<String>,
+    millis: u64,
+}
+
+impl Log {
+    fn take(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.entries)
+    }
+}
+
+impl Clock for Log {
+    fn now(&self) -> u64 {
+        self.millis
+    }
+}
+
+macro_rules! logged_state {
+    ($($name:ident),*) => {
+        $(
+            #[derive(Clone, Copy, Debug, Default, PartialEq)]
+            pub struct $name {}
+
+            impl $name {
+                pub fn entry(&mut self, log: &mut Log, _: Option<&Event>) -> Result<(), &'static str> {
+                    log.entries.push(format!("entry {}", stringify!($name)));
+                    Ok(())
+                }
+
+                pub fn exit(&self, log: &mut Log, _: Option<&Event>) -> Result<(), &'static str> {
+                    log.entries.push(format!("exit {}", stringify!($name)));
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+logged_state!(Booting, Ready, Armed, Preloading, Firing);
+event!(Arm, Fire, Disarm);
+
+fsm! {
+    Context = Log;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    States {
+        Booting = Booting,
+        Ready = Ready,
+        Armed = Armed {
+            Preloading = Preloading,
+            Firing = Firing,
+        },
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    Events {
+        Arm = Arm,
+        Fire = Fire,
+        Disarm = Disarm,
+    }
+
+    Transitions {
+        Arm [
+            Ready => Armed,
+        ],
+        Fire [
+            Preloading => Firing,
+        ],
+        Disarm [
+            Armed => Ready,
+        ],
+        Booting => Ready after 50ms,
+        Preloading => Ready after 200ms,
+        Armed => Ready after 1s,
+    }
+}
+
+fn armed_at(millis: u64) -> Machine {
+    let mut machine = Machine::new();
+    machine.context_mut().millis = millis;
+    machine.tick(millis).unwrap().unwrap();
+    machine.event(Event::Arm(Arm)).unwrap();
+    let _ = machine.context_mut().take();
+    machine
+}
+
+#[test]
+fn the_initial_state_starts_its_delay_when_built() {
+    let mut machine = Machine::with_context(Log {
+        millis: 1000,
+        ..Log::default()
+    });
+
+    assert_eq!(machine.next_deadline(), Some(1050));
+    assert_eq!(machine.tick(1049), None);
+    assert_eq!(
+        machine.tick(1050),
+        Some(Ok(Transition::Taken {
+            from: State::Booting(Booting {}),
+            to: State::Ready(Ready {}),
+        }))
+    );
+    assert_eq!(
+        machine.context_mut().take(),
+        vec!["exit Booting", "entry Ready"]
+    );
+    assert_eq!(machine.next_deadline(), None);
+}
+
+#[test]
+fn the_innermost_delay_is_taken_first() {
+    let mut machine = armed_at(100);
+
+    assert_eq!(machine.next_deadline(), Some(300));
+    assert_eq!(machine.tick(299), None);
+    assert_eq!(
+        machine.tick(300),
+        Some(Ok(Transition::Taken {
+            from: State::Preloading(Preloading {}),
+            to: State::Ready(Ready {}),
+        }))
+    );
+    assert_eq!(
+        machine.context_mut().take(),
+        vec!["exit Preloading", "exit Armed", "entry Ready"]
+    );
+}
+
+#[test]
+fn a_superstate_delay_runs_across_its_children() {
+    let mut machine = armed_at(100);
+    machine.context_mut().millis = 150;
+    machine.event(Event::Fire(Fire)).unwrap();
+
+    assert_eq!(machine.next_deadline(), Some(1100));
+    assert_eq!(machine.tick(1099), None);
+    assert_eq!(
+        machine.tick(1100),
+        Some(Ok(Transition::Taken {
+            from: State::Firing(Firing {}),
+            to: State::Ready(Ready {}),
+        }))
+    );
+}
+
+#[test]
+fn leaving_a_state_cancels_its_delay() {
+    let mut machine = armed_at(100);
+    machine.event(Event::Disarm(Disarm)).unwrap();
+
+    assert_eq!(machine.next_deadline(), None);
+    assert_eq!(machine.tick(5000), None);
+    assert_eq!(machine.state(), State::Ready(Ready {}));
+}
+
+#[test]
+fn delays_are_drawn_as_transitions() {
+    assert!(Machine::MERMAID.contains("Preloading --> Ready : after 200ms\n"));
+    assert!(Machine::MERMAID.contains("Armed --> Ready : after 1s\n"));
+}
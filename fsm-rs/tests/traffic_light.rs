@@ -1,5 +1,4 @@
-#[macro_use]
-extern crate machine;
+use fsm_rs::{machine, methods, transitions};
 
 machine!(
     #[derive(Clone, Debug, PartialEq)]
@@ -0,0 +1,164 @@
+use fsm_rs::fsm;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Idle {}
+
+impl Idle {
+    pub fn entry<C, E>(&mut self, _: &mut C, _: Option<&E>) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    pub fn exit<C, E>(&self, _: &mut C, _: Option<&E>) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Running {}
+
+impl Running {
+    pub fn entry<C, E>(&mut self, _: &mut C, _: Option<&E>) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    pub fn exit<C, E>(&self, _: &mut C, _: Option<&E>) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Start {}
+
+impl Start {
+    pub fn on<C>(&self, _: &mut C) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stop {}
+
+impl Stop {
+    pub fn on<C>(&self, _: &mut C) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Reset {}
+
+impl Reset {
+    pub fn on<C>(&self, _: &mut C) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct Context {}
+
+mod strict {
+    use super::*;
+
+    fsm! {
+        Context = Context;
+
+        Allow = [unused_events];
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        States {
+            Idle = Idle,
+            Running = Running,
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        Events {
+            Start = Start,
+            Stop = Stop,
+            Reset = Reset,
+        }
+
+        Transitions {
+            Start [
+                Idle => Running,
+            ],
+            Stop [
+                Running => Idle,
+            ],
+        }
+    }
+
+    #[test]
+    fn unhandled_event_is_an_error() {
+        let mut machine = Machine::new();
+
+        assert_eq!(
+            machine.event(Event::Stop(Stop {})),
+            Err(TransitionError::Unhandled {
+                state: State::Idle(Idle {}),
+                event: Event::Stop(Stop {}),
+            })
+        );
+        assert_eq!(
+            machine.event(Event::Reset(Reset {})),
+            Err(TransitionError::Unhandled {
+                state: State::Idle(Idle {}),
+                event: Event::Reset(Reset {}),
+            })
+        );
+        assert_eq!(machine.state(), State::Idle(Idle {}));
+    }
+}
+
+mod lenient {
+    use super::*;
+
+    fsm! {
+        Context = Context;
+
+        Unhandled = Ignore;
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        States {
+            Idle = Idle,
+            Running = Running,
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        Events {
+            Start = Start,
+            Stop = Stop,
+        }
+
+        Transitions {
+            Start [
+                Idle => Running,
+            ],
+            Stop [
+                Running => Idle,
+            ],
+        }
+    }
+
+    #[test]
+    fn unhandled_event_is_ignored() {
+        let mut machine = Machine::new();
+
+        assert_eq!(
+            machine.event(Event::Stop(Stop {})),
+            Ok(Transition::Ignored {
+                state: State::Idle(Idle {}),
+                event: Event::Stop(Stop {}),
+            })
+        );
+        assert_eq!(machine.state(), State::Idle(Idle {}));
+
+        assert!(machine.event(Event::Start(Start {})).is_ok());
+        assert_eq!(
+            machine.event(Event::Start(Start {})),
+            Ok(Transition::Ignored {
+                state: State::Running(Running {}),
+                event: Event::Start(Start {}),
+            })
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use fsm_rs::fsm;
+
+#[macro_use]
+mod common;
+
+state!(Idle, Busy, Loading, Running, Fault);
+event!(Load, Run, Stop, Fail, Reset);
+
+#[derive(Default)]
+pub struct Context {}
+
+fsm! {
+    Context = Context;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    States {
+        Idle = Idle,
+        Busy = Busy {
+            Loading = Loading,
+            Running = Running,
+        },
+        Fault = Fault,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    Events {
+        Load = Load,
+        Run = Run,
+        Stop = Stop,
+        Fail = Fail,
+        Reset = Reset,
+    }
+
+    Transitions {
+        Load [
+            Idle => Loading,
+        ],
+        Run [
+            Loading => Running,
+        ],
+        Stop [
+            [Loading, Running] => Idle,
+        ],
+        Fail [
+            * => Fault,
+        ],
+        Reset [
+            * - [Idle, Busy] => Idle,
+        ],
+    }
+}
+
+#[test]
+fn test_wildcard_covers_every_state() {
+    for setup in [
+        &[][..],
+        &[Event::Load(Load)],
+        &[Event::Load(Load), Event::Run(Run)],
+    ] {
+        let mut machine = Machine::new();
+        for event in setup {
+            assert!(machine.event(*event).is_ok());
+        }
+
+        assert!(machine.event(Event::Fail(Fail)).is_ok());
+        assert_eq!(machine.state(), State::Fault(Fault {}));
+
+        // the wildcard includes the target itself
+        assert_eq!(
+            machine.event(Event::Fail(Fail)),
+            Ok(Transition::Taken {
+                from: State::Fault(Fault {}),
+                to: State::Fault(Fault {}),
+            })
+        );
+    }
+}
+
+#[test]
+fn test_multi_source_and_excluded_states() {
+    let mut machine = Machine::new();
+
+    assert!(machine.event(Event::Load(Load)).is_ok());
+    assert!(machine.event(Event::Stop(Stop)).is_ok());
+    assert_eq!(machine.state(), State::Idle(Idle {}));
+
+    assert!(machine.event(Event::Load(Load)).is_ok());
+    assert!(machine.event(Event::Run(Run)).is_ok());
+    assert!(machine.event(Event::Stop(Stop)).is_ok());
+    assert_eq!(machine.state(), State::Idle(Idle {}));
+
+    // excluding a superstate excludes all of its children
+    assert!(machine.event(Event::Load(Load)).is_ok());
+    assert_eq!(
+        machine.event(Event::Reset(Reset)),
+        Err(TransitionError::Unhandled {
+            state: State::Loading(Loading {}),
+            event: Event::Reset(Reset),
+        })
+    );
+
+    assert!(machine.event(Event::Fail(Fail)).is_ok());
+    assert!(machine.event(Event::Reset(Reset)).is_ok());
+    assert_eq!(machine.state(), State::Idle(Idle {}));
+}
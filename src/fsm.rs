@@ -1,9 +1,22 @@
 use fsm_rs::fsm;
 
-use crate::peripherals;
-use crate::peripherals::Shared;
-use core::cell::RefCell;
-use cortex_m::interrupt::Mutex;
+/// The lowest supply voltage the power-on self-test passes with.
+pub const POST_MIN_MILLIVOLTS: u16 = 2400;
+
+/// The battery voltage under which firing is locked out.
+pub const BATTERY_LOW_MILLIVOLTS: u16 = 3000;
+
+/// The most current the motor may draw before it is stopped.
+pub const CURRENT_LIMIT_MILLIAMPS: u16 = 1500;
+
+/// What the fire control drives on the board. The firmware implements it
+/// on its shared peripherals.
+pub trait Board {
+    fn set_motor(&self, on: bool);
+
+    /// The current the motor draws, read through the ADC.
+    fn motor_milliamps(&self) -> u16;
+}
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum TriggerMode {
@@ -12,147 +25,256 @@ pub enum TriggerMode {
     AUTO,
 }
 
+impl Default for TriggerMode {
+    fn default() -> Self {
+        TriggerMode::SAFE
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FireControlError {
+    NoBoard,
+    BatteryVoltageLow { millivolts: u16 },
+    Overcurrent { milliamps: u16 },
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 pub struct PowerON {}
 
 impl PowerON {
-    pub fn entry(&self) -> Result<(), &'static str> {
-        unreachable!()
+    pub fn entry(
+        &mut self,
+        _: &mut FireControl,
+        _: Option<&Event>,
+    ) -> Result<(), FireControlError> {
+        Ok(())
     }
 
-    pub fn exit(&self) -> Result<State, &'static str> {
-        Ok(State::Ready(Ready {}))
+    pub fn exit(&self, _: &mut FireControl, _: Option<&Event>) -> Result<(), FireControlError> {
+        Ok(())
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 pub struct POSTError {}
 
 impl POSTError {
-    pub fn entry(&self) -> Result<(), &'static str> {
+    pub fn entry(
+        &mut self,
+        _: &mut FireControl,
+        _: Option<&Event>,
+    ) -> Result<(), FireControlError> {
         Ok(())
     }
 
-    pub fn exit(&self) -> Result<State, &'static str> {
-        unreachable!()
+    pub fn exit(&self, _: &mut FireControl, _: Option<&Event>) -> Result<(), FireControlError> {
+        Ok(())
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 pub struct Ready {}
 
 impl Ready {
-    pub fn entry(&self) -> Result<(), &'static str> {
+    pub fn entry(
+        &mut self,
+        _: &mut FireControl,
+        _: Option<&Event>,
+    ) -> Result<(), FireControlError> {
         Ok(())
     }
 
-    pub fn exit(&self) -> Result<State, &'static str> {
-        unreachable!()
+    pub fn exit(&self, _: &mut FireControl, _: Option<&Event>) -> Result<(), FireControlError> {
+        Ok(())
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub struct BatteryVoltageLow {}
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BatteryVoltageLow {
+    millivolts: u16,
+}
 
 impl BatteryVoltageLow {
-    pub fn entry(&self) -> Result<(), &'static str> {
+    pub fn entry(
+        &mut self,
+        _: &mut FireControl,
+        event: Option<&Event>,
+    ) -> Result<(), FireControlError> {
+        if let Some(Event::BatteryVoltageChange(change)) = event {
+            self.millivolts = change.millivolts;
+        }
         Ok(())
     }
 
-    pub fn exit(&self) -> Result<State, &'static str> {
-        unreachable!()
+    pub fn exit(&self, _: &mut FireControl, _: Option<&Event>) -> Result<(), FireControlError> {
+        Ok(())
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub struct Overcurrent {}
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Overcurrent {
+    milliamps: u16,
+}
 
 impl Overcurrent {
-    pub fn entry(&self) -> Result<(), &'static str> {
+    pub fn entry(
+        &mut self,
+        _: &mut FireControl,
+        event: Option<&Event>,
+    ) -> Result<(), FireControlError> {
+        if let Some(Event::SystemCurrentChange(change)) = event {
+            self.milliamps = change.milliamps;
+        }
         Ok(())
     }
 
-    pub fn exit(&self) -> Result<State, &'static str> {
-        unreachable!()
+    pub fn exit(&self, _: &mut FireControl, _: Option<&Event>) -> Result<(), FireControlError> {
+        Ok(())
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Armed {}
+
+impl Armed {
+    pub fn entry(
+        &mut self,
+        _: &mut FireControl,
+        _: Option<&Event>,
+    ) -> Result<(), FireControlError> {
+        Ok(())
+    }
+
+    pub fn exit(&self, _: &mut FireControl, _: Option<&Event>) -> Result<(), FireControlError> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 pub struct Preloading {}
 
 impl Preloading {
-    pub fn entry(&self) -> Result<(), &'static str> {
-        Ok(())
+    pub fn entry(
+        &mut self,
+        context: &mut FireControl,
+        _: Option<&Event>,
+    ) -> Result<(), FireControlError> {
+        // the motor spins up while the trigger is half pulled
+        context.set_motor(true)
     }
 
-    pub fn exit(&self) -> Result<State, &'static str> {
-        unreachable!()
+    pub fn exit(
+        &self,
+        context: &mut FireControl,
+        _: Option<&Event>,
+    ) -> Result<(), FireControlError> {
+        context.set_motor(false)
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 pub struct Safe {}
 
 impl Safe {
-    pub fn entry(&self) -> Result<(), &'static str> {
+    pub fn entry(
+        &mut self,
+        _: &mut FireControl,
+        _: Option<&Event>,
+    ) -> Result<(), FireControlError> {
         Ok(())
     }
 
-    pub fn exit(&self) -> Result<State, &'static str> {
-        unreachable!()
+    pub fn exit(&self, _: &mut FireControl, _: Option<&Event>) -> Result<(), FireControlError> {
+        Ok(())
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 pub struct HalfAutoNFire {}
 
 impl HalfAutoNFire {
-    pub fn entry(&self) -> Result<(), &'static str> {
-        Ok(())
+    pub fn entry(
+        &mut self,
+        context: &mut FireControl,
+        _: Option<&Event>,
+    ) -> Result<(), FireControlError> {
+        context.set_motor(true)
     }
 
-    pub fn exit(&self) -> Result<State, &'static str> {
-        unreachable!()
+    pub fn exit(
+        &self,
+        context: &mut FireControl,
+        _: Option<&Event>,
+    ) -> Result<(), FireControlError> {
+        context.set_motor(false)
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 pub struct FullAutoFire {}
 
 impl FullAutoFire {
-    pub fn entry(&self) -> Result<(), &'static str> {
-        Ok(())
+    pub fn entry(
+        &mut self,
+        context: &mut FireControl,
+        _: Option<&Event>,
+    ) -> Result<(), FireControlError> {
+        context.set_motor(true)
     }
 
-    pub fn exit(&self) -> Result<State, &'static str> {
-        unreachable!()
+    pub fn exit(
+        &self,
+        context: &mut FireControl,
+        _: Option<&Event>,
+    ) -> Result<(), FireControlError> {
+        context.set_motor(false)
     }
 }
 
 // event
+/// The power-on self-test, with the supply voltage it measured.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub struct Post {}
+pub struct Post {
+    pub millivolts: u16,
+}
 
 impl Post {
-    pub fn on(&self) -> Result<(), &'static str> {
+    pub fn on(&self, _: &mut FireControl) -> Result<(), FireControlError> {
         Ok(())
     }
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub struct BatteryVoltageChange {}
+pub struct BatteryVoltageChange {
+    pub millivolts: u16,
+}
 
 impl BatteryVoltageChange {
-    pub fn on(&self) -> Result<(), &'static str> {
+    pub fn on(&self, _: &mut FireControl) -> Result<(), FireControlError> {
         Ok(())
     }
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub struct SystemCurrentChange {}
+pub struct SystemCurrentChange {
+    pub milliamps: u16,
+}
 
 impl SystemCurrentChange {
-    pub fn on(&self) -> Result<(), &'static str> {
+    pub fn on(&self, _: &mut FireControl) -> Result<(), FireControlError> {
+        Ok(())
+    }
+}
+
+/// The fire selector was moved to `mode`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TriggerModeChange {
+    pub mode: TriggerMode,
+}
+
+impl TriggerModeChange {
+    pub fn on(&self, context: &mut FireControl) -> Result<(), FireControlError> {
+        context.trigger_mode = self.mode;
         Ok(())
     }
 }
@@ -161,7 +283,7 @@ impl SystemCurrentChange {
 pub struct PullHalfTrigger {}
 
 impl PullHalfTrigger {
-    pub fn on(&self) -> Result<(), &'static str> {
+    pub fn on(&self, _: &mut FireControl) -> Result<(), FireControlError> {
         Ok(())
     }
 }
@@ -170,7 +292,7 @@ impl PullHalfTrigger {
 pub struct PullFullTrigger {}
 
 impl PullFullTrigger {
-    pub fn on(&self) -> Result<(), &'static str> {
+    pub fn on(&self, _: &mut FireControl) -> Result<(), FireControlError> {
         Ok(())
     }
 }
@@ -179,41 +301,139 @@ impl PullFullTrigger {
 pub struct ReleaseTrigger {}
 
 impl ReleaseTrigger {
-    pub fn on(&self) -> Result<(), &'static str> {
+    pub fn on(&self, _: &mut FireControl) -> Result<(), FireControlError> {
         Ok(())
     }
 }
 
 #[derive(Default)]
 pub struct FireControl {
-    shard: Option<Mutex<RefCell<Shared>>>,
+    /// The board the callbacks drive, `None` until the firmware hands it
+    /// over.
+    board: Option<&'static dyn Board>,
+    trigger_mode: TriggerMode,
+    /// Whether the last current reading queued was over the limit.
+    current_over_limit: bool,
+    /// Milliseconds since boot, kept up to date from SysTick.
+    uptime_millis: u64,
+    /// Events waiting for `Machine::run`, including those posted by the
+    /// callbacks.
+    queue: Queue,
 }
 
-impl Default for State {
-    fn default() -> Self {
-        State::PowerON(PowerON {})
+impl Clock for FireControl {
+    fn now(&self) -> u64 {
+        self.uptime_millis
+    }
+}
+
+impl EventQueue for FireControl {
+    fn queue(&mut self) -> &mut Queue {
+        &mut self.queue
+    }
+}
+
+impl FireControl {
+    pub fn new(board: &'static dyn Board) -> Self {
+        FireControl {
+            board: Some(board),
+            ..Default::default()
+        }
+    }
+
+    fn board(&self) -> Result<&'static dyn Board, FireControlError> {
+        self.board.ok_or(FireControlError::NoBoard)
+    }
+
+    fn set_motor(&self, on: bool) -> Result<(), FireControlError> {
+        self.board()?.set_motor(on);
+        Ok(())
+    }
+
+    /// Reads the motor current, and queues it for the next `Machine::run`
+    /// when it has crossed CURRENT_LIMIT_MILLIAMPS since the last reading
+    /// queued. The transitions decide what a crossing means.
+    pub fn sample_current(&mut self) -> Result<(), FireControlError> {
+        let milliamps = self.board()?.motor_milliamps();
+        let over = milliamps > CURRENT_LIMIT_MILLIAMPS;
+        if over != self.current_over_limit {
+            let change = SystemCurrentChange { milliamps };
+            if self.queue.post(Event::SystemCurrentChange(change)).is_ok() {
+                self.current_over_limit = over;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_uptime_millis(&mut self, millis: u64) {
+        self.uptime_millis = millis;
+    }
+
+    fn post_passed(&self, post: &Post) -> bool {
+        post.millivolts >= POST_MIN_MILLIVOLTS
+    }
+
+    fn voltage_low(&self, change: &BatteryVoltageChange) -> bool {
+        change.millivolts < BATTERY_LOW_MILLIVOLTS
+    }
+
+    fn over_current_limit(&self, change: &SystemCurrentChange) -> bool {
+        change.milliamps > CURRENT_LIMIT_MILLIAMPS
+    }
+
+    fn under_current_limit(&self, change: &SystemCurrentChange) -> bool {
+        change.milliamps <= CURRENT_LIMIT_MILLIAMPS
+    }
+
+    fn trigger_mode_is_safe(&self, _: &PullFullTrigger) -> bool {
+        self.trigger_mode == TriggerMode::SAFE
+    }
+
+    fn trigger_mode_is_semi(&self, _: &PullFullTrigger) -> bool {
+        self.trigger_mode == TriggerMode::SEMI
+    }
+
+    fn trigger_mode_is_auto(&self, _: &PullFullTrigger) -> bool {
+        self.trigger_mode == TriggerMode::AUTO
     }
 }
 
 fsm! {
+    // the match backend's code grows as states × events, too much for
+    // the 16K of flash
+    #[fsm(backend = "table", queue = 8)]
+
     Context = FireControl;
 
+    Error = FireControlError;
+
+    Unhandled = Ignore;
+
+    Initial = PowerON;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
     States {
         PowerON = PowerON,
         POSTError = POSTError,
         Ready = Ready,
         BatteryVoltageLow = BatteryVoltageLow,
         Overcurrent = Overcurrent,
-        Preloading = Preloading,
-        Safe = Safe,
-        HalfAutoNFire = HalfAutoNFire,
-        FullAutoFire = FullAutoFire,
+        Armed = Armed {
+            Preloading = Preloading,
+            Safe = Safe,
+            HalfAutoNFire = HalfAutoNFire,
+            FullAutoFire = FullAutoFire,
+        },
     }
 
+    Final { POSTError }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
     Events {
         POST = Post,
         BatteryVoltageChange = BatteryVoltageChange,
         SystemCurrentChange = SystemCurrentChange,
+        TriggerModeChange = TriggerModeChange,
         PullHalfTrigger = PullHalfTrigger,
         PullFullTrigger = PullFullTrigger,
         ReleaseTrigger = ReleaseTrigger,
@@ -221,33 +441,43 @@ fsm! {
 
     Transitions {
         POST [
-            PowerON => Ready,
+            PowerON => Ready if post_passed,
             PowerON => POSTError,
         ],
         BatteryVoltageChange [
-            Ready => BatteryVoltageLow,
-            Safe => BatteryVoltageLow,
-            Preloading => BatteryVoltageLow,
-            HalfAutoNFire => BatteryVoltageLow,
-            FullAutoFire => BatteryVoltageLow,
+            [Ready, Armed] => BatteryVoltageLow if voltage_low,
         ],
         SystemCurrentChange [
-            Preloading => Overcurrent,
-            HalfAutoNFire => Overcurrent,
-            FullAutoFire => Overcurrent,
+            // Safe has the motor off, a current change there is no fault
+            [Preloading, HalfAutoNFire, FullAutoFire] => Overcurrent if over_current_limit,
+            // only resume firing once the fault has cleared
+            Overcurrent => History(Armed) if under_current_limit,
+        ],
+        // the new mode is only looked at on the next full pull
+        TriggerModeChange [
+            * -> internal,
         ],
         PullHalfTrigger [
             Ready => Preloading,
         ],
         PullFullTrigger [
-            Preloading => Safe,
-            Preloading => HalfAutoNFire,
-            Preloading => FullAutoFire,
+            Preloading => Safe if trigger_mode_is_safe,
+            Preloading => HalfAutoNFire if trigger_mode_is_semi,
+            Preloading => FullAutoFire if trigger_mode_is_auto,
+            FullAutoFire -> internal,
         ],
         ReleaseTrigger [
-            Safe => Ready,
-            HalfAutoNFire => Ready,
-            FullAutoFire => Ready,
+            [Safe, HalfAutoNFire, FullAutoFire] => Ready,
         ],
+        Preloading => Ready after 200ms,
+    }
+
+    // checked when the firmware is built; a low battery latches until the
+    // next power cycle
+    Invariants {
+        reachable(Ready),
+        forbidden(Ready => FullAutoFire),
+        through(Ready => FullAutoFire, Preloading),
+        can_reach(Ready, POSTError, BatteryVoltageLow),
     }
 }
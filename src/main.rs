@@ -85,6 +85,9 @@ impl __Deref for QUEUE {
     }
 }
 
+/// Milliseconds since boot, counted by SysTick.
+static UPTIME_MILLIS: Mutex<Cell<u64>> = Mutex::new(Cell::new(0));
+
 static BOARD: BOARD = BOARD {
     __private_field: (),
 };
@@ -114,17 +117,73 @@ impl __Deref for BOARD {
     }
 }
 
+impl fsm::Board for BOARD {
+    fn set_motor(&self, on: bool) {
+        cortex_m::interrupt::free(|cs| {
+            let mut board = self.borrow(cs).borrow_mut();
+            if on {
+                board.motor.set_high().ok();
+            } else {
+                board.motor.set_low().ok();
+            }
+        })
+    }
+
+    fn motor_milliamps(&self) -> u16 {
+        cortex_m::interrupt::free(|cs| self.borrow(cs).borrow_mut().motor_milliamps())
+    }
+}
+
 #[entry]
 fn main() -> ! {
-    let mut system_fsm = fsm::Machine::new();
-    if system_fsm.event(fsm::Event::POST(fsm::Post {})).is_ok() {
+    // the power-on self-test: the MCU has to run off a supply it is
+    // specified for
+    let millivolts = cortex_m::interrupt::free(|cs| {
+        let mut board = BOARD.borrow(cs).borrow_mut();
+        stm32f0xx_hal::adc::VRef::read_vdda(&mut board.adc)
+    });
+
+    let mut system_fsm = fsm::Machine::with_context(fsm::FireControl::new(&BOARD));
+    let _ = system_fsm.event(fsm::Event::POST(fsm::Post { millivolts }));
+    if let fsm::State::Ready(_) = system_fsm.state() {
+        // the selector position the machine was last told about
+        let mut trigger_mode = fsm::TriggerMode::default();
         loop {
-            // Enter critical section
+            // Enter critical section. The board is only borrowed around
+            // reading it, the machine's callbacks borrow it themselves.
             cortex_m::interrupt::free(|cs| {
-                let mut board = BOARD.borrow(cs).borrow_mut();
                 let queue = QUEUE.borrow(cs).borrow();
 
+                // fire the timed transitions that are due
+                let now = UPTIME_MILLIS.borrow(cs).get();
+                system_fsm.context_mut().set_uptime_millis(now);
+                let _ = system_fsm.tick(now);
+
+                // the motor current goes to the machine's own queue
+                system_fsm.context_mut().sample_current().ok();
+
+                // the fire selector is polled, a new position goes through
+                // the queue like any other event
+                let mode = {
+                    let board = BOARD.borrow(cs).borrow();
+                    if board.selector_auto.is_high().unwrap_or(false) {
+                        fsm::TriggerMode::AUTO
+                    } else if board.selector_semi.is_high().unwrap_or(false) {
+                        fsm::TriggerMode::SEMI
+                    } else {
+                        fsm::TriggerMode::SAFE
+                    }
+                };
+                if mode != trigger_mode {
+                    let event = fsm::Event::TriggerModeChange(fsm::TriggerModeChange { mode });
+                    if queue.push(event, cs).is_ok() {
+                        trigger_mode = mode;
+                    }
+                }
+
                 if let Some(event) = queue.pop(cs) {
+                    let mut board = BOARD.borrow(cs).borrow_mut();
+
                     // Read temperature data from internal sensor using ADC
                     let t = stm32f0xx_hal::adc::VTemp::read(&mut board.adc, None);
                     println!("Temperature {}.{}C\r", t / 100, t % 100).ok();
@@ -133,9 +192,17 @@ fn main() -> ! {
                     let t = stm32f0xx_hal::adc::VRef::read_vdda(&mut board.adc);
                     println!("Vdda {}mV\r", t).ok();
                     board.led.toggle().ok();
+
+                    drop(board);
+                    system_fsm.post(event).ok();
                 } else {
                     asm::wfi();
                 }
+
+                // run to completion, along with whatever the callbacks and
+                // the current sampling posted; a reading no guard acts on
+                // fails only its own event
+                while system_fsm.run().is_err() {}
             });
         }
     } else {
@@ -144,14 +211,23 @@ fn main() -> ! {
     }
 }
 
+#[exception]
+fn SysTick() {
+    cortex_m::interrupt::free(|cs| {
+        let uptime = UPTIME_MILLIS.borrow(cs);
+        uptime.set(uptime.get() + 1);
+    })
+}
+
 #[interrupt]
 fn EXTI4_15() {
     cortex_m::interrupt::free(|cs| {
         let mut board = BOARD.borrow(cs).borrow_mut();
         let queue = QUEUE.borrow(cs).borrow();
 
+        let millivolts = stm32f0xx_hal::adc::VRef::read_vdda(&mut board.adc);
         queue.push(
-            fsm::Event::BatteryVoltageChange(fsm::BatteryVoltageChange {}),
+            fsm::Event::BatteryVoltageChange(fsm::BatteryVoltageChange { millivolts }),
             cs,
         );
 
@@ -1,15 +1,31 @@
 use core::cell::RefCell;
 use cortex_m::{interrupt::Mutex, peripheral::syst::SystClkSource::Core};
-use stm32f0xx_hal::delay::Delay;
 use stm32f0xx_hal::{prelude::*, stm32, stm32::{Interrupt, Peripherals, EXTI},};
 
+/// The motor's current sense resistor.
+const CURRENT_SENSE_MILLIOHMS: u32 = 100;
+
 pub struct Shared {
     pub adc: stm32f0xx_hal::adc::Adc,
     pub led:
         stm32f0xx_hal::gpio::gpiob::PB1<stm32f0xx_hal::gpio::Output<stm32f0xx_hal::gpio::PushPull>>,
     pub tx: stm32f0xx_hal::serial::Tx<stm32::USART1>,
-    pub delay: Delay,
     pub exti: EXTI,
+    pub selector_semi:
+        stm32f0xx_hal::gpio::gpioa::PA0<stm32f0xx_hal::gpio::Input<stm32f0xx_hal::gpio::PullDown>>,
+    pub selector_auto:
+        stm32f0xx_hal::gpio::gpioa::PA1<stm32f0xx_hal::gpio::Input<stm32f0xx_hal::gpio::PullDown>>,
+    pub motor:
+        stm32f0xx_hal::gpio::gpioa::PA6<stm32f0xx_hal::gpio::Output<stm32f0xx_hal::gpio::PushPull>>,
+    pub current_sense: stm32f0xx_hal::gpio::gpioa::PA4<stm32f0xx_hal::gpio::Analog>,
+}
+
+impl Shared {
+    /// The motor current, from the voltage over its sense resistor.
+    pub fn motor_milliamps(&mut self) -> u16 {
+        let millivolts = u32::from(self.adc.read_abs_mv(&mut self.current_sense));
+        (millivolts * 1000 / CURRENT_SENSE_MILLIOHMS) as u16
+    }
 }
 
 pub fn init_peripherals() -> Result<Shared, &'static str> {
@@ -46,8 +62,23 @@ pub fn init_peripherals() -> Result<Shared, &'static str> {
                 stm32f0xx_hal::serial::Serial::usart1(p.USART1, (tx, rx), 115_200.bps(), &mut rcc)
                     .split();
 
-            // Get delay provider
-            let mut delay = Delay::new(syst, &rcc);
+            // SysTick every millisecond, for the state machine's timers
+            syst.set_clock_source(Core);
+            syst.set_reload(rcc.clocks.hclk().0 / 1_000 - 1);
+            syst.clear_current();
+            syst.enable_counter();
+            syst.enable_interrupt();
+
+            // The fire selector: PA0 high for SEMI, PA1 high for AUTO,
+            // neither for SAFE
+            let selector_semi = gpioa.pa0.into_pull_down_input(cs);
+            let selector_auto = gpioa.pa1.into_pull_down_input(cs);
+
+            // The motor driver on PA6, off until a firing state starts it,
+            // and its current sense on PA4
+            let mut motor = gpioa.pa6.into_push_pull_output(cs);
+            motor.set_low().ok();
+            let current_sense = gpioa.pa4.into_analog(cs);
 
             // Configure PB8 as input (button)
             let _ = gpiob.pb8.into_pull_down_input(cs);
@@ -72,8 +103,11 @@ pub fn init_peripherals() -> Result<Shared, &'static str> {
                 adc,
                 led,
                 tx,
-                delay,
                 exti,
+                selector_semi,
+                selector_auto,
+                motor,
+                current_sense,
             })
         })
     } else {